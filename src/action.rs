@@ -0,0 +1,385 @@
+use crate::config::CommandConfig;
+use crate::keymap;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+
+/// The screens, and the modes of the preview, that have their own keys
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Context {
+    Main,
+    Preview,
+    Table,    // a CSV/TSV file or the rows of a database table in the preview
+    Database, // the tables of a SQLite database in the preview
+    Tree,     // the tree of a JSON, TOML or YAML file in the preview
+    Diff,
+    Edit,
+}
+
+/// Something a screen can do, bound to keys in the keymap and run by custom commands of the configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    Close,
+    Help,
+    Palette,
+    NewFile,
+    Delete,
+    Copy,
+    Paste,
+    Rename,
+    Search,
+    ToggleHidden,
+    TogglePreviewPane,
+    ToggleMark,
+    Compress,
+    Extract,
+    Diff,
+    Edit,
+    ExternalEditor,
+    Pager,
+    OpenWith,
+    Shell,
+    CommandPrompt,
+    CommandOutput,
+    Refresh,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    Left,
+    Right,
+    LineStart,
+    LineEnd,
+    Open,
+    Back,
+    ToggleLineNumbers,
+    ToggleWrap,
+    GoToLine,
+    NextMatch,
+    PreviousMatch,
+    ToggleFollow,
+    PickEncoding,
+    ToggleMarkdown,
+    ToggleTree,
+    ToggleSummary,
+    ToggleNode,
+    Expand,
+    Collapse,
+    NextHunk,
+    PreviousHunk,
+    ToggleLayout,
+    Save,
+    Undo,
+    FindNext,
+}
+
+impl Action {
+    pub const ALL: [Action; 55] = [
+        Action::Quit,
+        Action::Close,
+        Action::Help,
+        Action::Palette,
+        Action::NewFile,
+        Action::Delete,
+        Action::Copy,
+        Action::Paste,
+        Action::Rename,
+        Action::Search,
+        Action::ToggleHidden,
+        Action::TogglePreviewPane,
+        Action::ToggleMark,
+        Action::Compress,
+        Action::Extract,
+        Action::Diff,
+        Action::Edit,
+        Action::ExternalEditor,
+        Action::Pager,
+        Action::OpenWith,
+        Action::Shell,
+        Action::CommandPrompt,
+        Action::CommandOutput,
+        Action::Refresh,
+        Action::Up,
+        Action::Down,
+        Action::PageUp,
+        Action::PageDown,
+        Action::First,
+        Action::Last,
+        Action::Left,
+        Action::Right,
+        Action::LineStart,
+        Action::LineEnd,
+        Action::Open,
+        Action::Back,
+        Action::ToggleLineNumbers,
+        Action::ToggleWrap,
+        Action::GoToLine,
+        Action::NextMatch,
+        Action::PreviousMatch,
+        Action::ToggleFollow,
+        Action::PickEncoding,
+        Action::ToggleMarkdown,
+        Action::ToggleTree,
+        Action::ToggleSummary,
+        Action::ToggleNode,
+        Action::Expand,
+        Action::Collapse,
+        Action::NextHunk,
+        Action::PreviousHunk,
+        Action::ToggleLayout,
+        Action::Save,
+        Action::Undo,
+        Action::FindNext,
+    ];
+
+    /// The name of the action in the configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Close => "close",
+            Action::Help => "help",
+            Action::Palette => "palette",
+            Action::NewFile => "new",
+            Action::Delete => "delete",
+            Action::Copy => "copy",
+            Action::Paste => "paste",
+            Action::Rename => "rename",
+            Action::Search => "search",
+            Action::ToggleHidden => "toggle_hidden",
+            Action::TogglePreviewPane => "toggle_preview_pane",
+            Action::ToggleMark => "toggle_mark",
+            Action::Compress => "compress",
+            Action::Extract => "extract",
+            Action::Diff => "diff",
+            Action::Edit => "edit",
+            Action::ExternalEditor => "external_editor",
+            Action::Pager => "pager",
+            Action::OpenWith => "open_with",
+            Action::Shell => "shell",
+            Action::CommandPrompt => "command_prompt",
+            Action::CommandOutput => "command_output",
+            Action::Refresh => "refresh",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::LineStart => "line_start",
+            Action::LineEnd => "line_end",
+            Action::Open => "open",
+            Action::Back => "back",
+            Action::ToggleLineNumbers => "toggle_line_numbers",
+            Action::ToggleWrap => "toggle_wrap",
+            Action::GoToLine => "go_to_line",
+            Action::NextMatch => "next_match",
+            Action::PreviousMatch => "previous_match",
+            Action::ToggleFollow => "toggle_follow",
+            Action::PickEncoding => "pick_encoding",
+            Action::ToggleMarkdown => "toggle_markdown",
+            Action::ToggleTree => "toggle_tree",
+            Action::ToggleSummary => "toggle_summary",
+            Action::ToggleNode => "toggle_node",
+            Action::Expand => "expand",
+            Action::Collapse => "collapse",
+            Action::NextHunk => "next_hunk",
+            Action::PreviousHunk => "previous_hunk",
+            Action::ToggleLayout => "toggle_layout",
+            Action::Save => "save",
+            Action::Undo => "undo",
+            Action::FindNext => "find_next",
+        }
+    }
+
+    /// What the action does, shown in the command palette
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit stfm",
+            Action::Close => "Go back",
+            Action::Help => "Show the help",
+            Action::Palette => "Open the command palette",
+            Action::NewFile => "Create a file or a directory",
+            Action::Delete => "Delete the selected entry",
+            Action::Copy => "Copy the selected file",
+            Action::Paste => "Paste the copied file here",
+            Action::Rename => "Rename the selected entry",
+            Action::Search => "Search",
+            Action::ToggleHidden => "Show or hide hidden files",
+            Action::TogglePreviewPane => "Show or hide the preview pane",
+            Action::ToggleMark => "Mark or unmark the selected entry",
+            Action::Compress => "Compress the marked entries",
+            Action::Extract => "Extract the selected archive",
+            Action::Diff => "Compare the marked files",
+            Action::Edit => "Edit the selected file",
+            Action::ExternalEditor => "Open the selected file in $EDITOR",
+            Action::Pager => "Open the selected file in $PAGER",
+            Action::OpenWith => "Open the selected file with an opener rule",
+            Action::Shell => "Start $SHELL here",
+            Action::CommandPrompt => "Run a shell command",
+            Action::CommandOutput => "Show the output of the last shell command",
+            Action::Refresh => "List the directory again",
+            Action::Up => "Move up",
+            Action::Down => "Move down",
+            Action::PageUp => "Move up a page",
+            Action::PageDown => "Move down a page",
+            Action::First => "Go to the top",
+            Action::Last => "Go to the bottom",
+            Action::Left => "Move left",
+            Action::Right => "Move right",
+            Action::LineStart => "Go to the start of the line",
+            Action::LineEnd => "Go to the end of the line",
+            Action::Open => "Open the selected entry",
+            Action::Back => "Go to the parent directory",
+            Action::ToggleLineNumbers => "Show or hide line numbers",
+            Action::ToggleWrap => "Turn soft wrap on or off",
+            Action::GoToLine => "Go to a line",
+            Action::NextMatch => "Jump to the next match",
+            Action::PreviousMatch => "Jump to the previous match",
+            Action::ToggleFollow => "Follow the file as it grows",
+            Action::PickEncoding => "Pick the encoding of the file",
+            Action::ToggleMarkdown => "Switch between rendered Markdown and the raw file",
+            Action::ToggleTree => "Switch between the tree and the text",
+            Action::ToggleSummary => "Show or hide the column summary",
+            Action::ToggleNode => "Expand or collapse the selected node",
+            Action::Expand => "Expand the selected node",
+            Action::Collapse => "Collapse the selected node",
+            Action::NextHunk => "Jump to the next hunk",
+            Action::PreviousHunk => "Jump to the previous hunk",
+            Action::ToggleLayout => "Switch between side by side and unified",
+            Action::Save => "Save the file",
+            Action::Undo => "Undo the last change",
+            Action::FindNext => "Jump to the next match of the search",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|action| action.name() == name)
+    }
+}
+
+/// A key with its modifiers, written like "F", "ctrl-f", "alt-x" or "f5" in the configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn parse(text: &str) -> Result<Key, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        loop {
+            let lower = rest.to_lowercase();
+            if lower.starts_with("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+            } else if lower.starts_with("alt-") {
+                modifiers |= KeyModifiers::ALT;
+            } else {
+                break;
+            }
+            rest = &rest[rest.find('-').map_or(0, |index| index + 1)..];
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            // The terminal sends Ctrl with a lowercase letter
+            (Some(c), None) if modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char(c.to_ascii_lowercase()),
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "space" => KeyCode::Char(' '),
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                function => match function.strip_prefix('f').and_then(|number| number.parse::<u8>().ok()) {
+                    Some(number) if (1..=12).contains(&number) => KeyCode::F(number),
+                    _ => return Err(format!("unknown key \"{}\"", text)),
+                },
+            },
+        };
+        Ok(Key { code, modifiers })
+    }
+
+    /// If `event` is this key. Uppercase letters come with Shift, which is ignored for characters
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        let modifiers = match event.code {
+            KeyCode::Char(_) => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+        event.code == self.code && modifiers == self.modifiers
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::CONTROL) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(number) => write!(f, "F{}", number),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// A step of a custom command
+#[derive(Debug, Clone)]
+pub enum Step {
+    Action(Action),
+    Shell(String), // run like a command of the '!' prompt
+}
+
+/// A named command of the configuration made of built-in actions and shell commands, run one after the other
+#[derive(Debug)]
+pub struct CustomCommand {
+    pub name: String,
+    pub key: Option<Key>,
+    pub steps: Vec<Step>,
+}
+
+impl CustomCommand {
+    pub fn new(config: &CommandConfig) -> Result<CustomCommand, String> {
+        let key = match &config.key {
+            Some(key) => Some(Key::parse(key).map_err(|e| format!("command \"{}\": {}", config.name, e))?),
+            None => None,
+        };
+        if config.run.is_empty() {
+            return Err(format!("command \"{}\" runs nothing", config.name));
+        }
+        let mut steps = Vec::new();
+        for step in &config.run {
+            match step.strip_prefix('!') {
+                Some(line) => steps.push(Step::Shell(line.to_string())),
+                None => match Action::from_name(step.trim()) {
+                    // Steps run on the main screen, so only its actions can be used
+                    Some(action) if keymap::bindings(Context::Main).iter().any(|(bound, _)| *bound == action) => {
+                        steps.push(Step::Action(action))
+                    }
+                    Some(_) => {
+                        return Err(format!(
+                            "command \"{}\": \"{}\" is not an action of the main screen",
+                            config.name, step
+                        ))
+                    }
+                    None => return Err(format!("command \"{}\": unknown action \"{}\"", config.name, step)),
+                },
+            }
+        }
+        Ok(CustomCommand {
+            name: config.name.clone(),
+            key,
+            steps,
+        })
+    }
+}
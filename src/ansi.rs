@@ -0,0 +1,128 @@
+use crate::preview::display_line;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+/// Turn text with ANSI escape sequences into styled lines.
+/// Only SGR sequences (colors and text attributes) are applied, every other escape sequence is dropped
+pub fn parse(text: &str) -> Text<'static> {
+    let mut lines = Vec::new();
+    let mut style = Style::default();
+    for line in text.lines() {
+        let mut spans = Vec::new();
+        let mut current = String::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    match chars.next() {
+                        // Control sequence: parameters and intermediate bytes up to a final byte in @..~
+                        Some('[') => {
+                            let mut parameters = String::new();
+                            let mut last = None;
+                            for c in chars.by_ref() {
+                                if ('@'..='~').contains(&c) {
+                                    last = Some(c);
+                                    break;
+                                }
+                                parameters.push(c);
+                            }
+                            if last == Some('m') {
+                                if !current.is_empty() {
+                                    spans.push(Span::styled(std::mem::take(&mut current), style));
+                                }
+                                style = apply_sgr(style, &parameters);
+                            }
+                        }
+                        // Operating system command, like a window title, ends with BEL or ESC \
+                        Some(']') => {
+                            while let Some(c) = chars.next() {
+                                if c == '\x07' {
+                                    break;
+                                }
+                                if c == '\x1b' && chars.peek() == Some(&'\\') {
+                                    chars.next();
+                                    break;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                '\r' => {}
+                '\t' => current.push_str(&display_line("\t")),
+                c if c.is_control() => {}
+                c => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            spans.push(Span::styled(current, style));
+        }
+        lines.push(Line::from(spans));
+    }
+    Text::from(lines)
+}
+
+/// Apply the parameters of a "select graphic rendition" sequence to `style`
+fn apply_sgr(style: Style, parameters: &str) -> Style {
+    let codes: Vec<u16> = match parameters.is_empty() {
+        true => vec![0],
+        false => parameters.split([';', ':']).map(|code| code.parse().unwrap_or(0)).collect(),
+    };
+    let mut style = style;
+    let mut index = 0;
+    while index < codes.len() {
+        let code = codes[index];
+        match code {
+            0 => style = Style::default(),
+            1 => style = style.add_modifier(Modifier::BOLD),
+            2 => style = style.add_modifier(Modifier::DIM),
+            3 => style = style.add_modifier(Modifier::ITALIC),
+            4 => style = style.add_modifier(Modifier::UNDERLINED),
+            5 | 6 => style = style.add_modifier(Modifier::SLOW_BLINK),
+            7 => style = style.add_modifier(Modifier::REVERSED),
+            8 => style = style.add_modifier(Modifier::HIDDEN),
+            9 => style = style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style = style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style = style.remove_modifier(Modifier::ITALIC),
+            24 => style = style.remove_modifier(Modifier::UNDERLINED),
+            25 => style = style.remove_modifier(Modifier::SLOW_BLINK),
+            27 => style = style.remove_modifier(Modifier::REVERSED),
+            28 => style = style.remove_modifier(Modifier::HIDDEN),
+            29 => style = style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style = style.fg(Color::Indexed((code - 30) as u8)),
+            39 => style = style.fg(Color::Reset),
+            40..=47 => style = style.bg(Color::Indexed((code - 40) as u8)),
+            49 => style = style.bg(Color::Reset),
+            90..=97 => style = style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style = style.bg(Color::Indexed((code - 100 + 8) as u8)),
+            // Extended colors: 5;n picks from the 256 color palette, 2;r;g;b is a true color
+            38 | 48 => {
+                let color = match codes.get(index + 1) {
+                    Some(5) => {
+                        let color = codes.get(index + 2).map(|n| Color::Indexed(*n as u8));
+                        index += 2;
+                        color
+                    }
+                    Some(2) => {
+                        let color = match (codes.get(index + 2), codes.get(index + 3), codes.get(index + 4)) {
+                            (Some(r), Some(g), Some(b)) => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+                            _ => None,
+                        };
+                        index += 4;
+                        color
+                    }
+                    _ => None,
+                };
+                if let Some(color) = color {
+                    style = match code {
+                        38 => style.fg(color),
+                        _ => style.bg(color),
+                    };
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    style
+}
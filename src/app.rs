@@ -1,10 +1,40 @@
 #[allow(dead_code)]
+use crate::action::{Action, Context, CustomCommand, Step};
+use crate::archive::{
+    create_archive, extract_archive, extract_member, read_member, ArchiveBrowser, ArchiveFormat, ArchiveIndex,
+    ArchiveMember, CREATE_FORMATS, MEMBER_READ_LIMIT,
+};
+use crate::binary::{self, BinaryFormat};
+use crate::config::Config;
+use crate::diff::DiffView;
+use crate::editor::Editor;
+use crate::directory::DirectorySummary;
+use crate::encoding::*;
 use crate::files::*;
+use crate::jobs::Job;
+use crate::markdown::{self, MARKDOWN_EXTENSIONS};
+use crate::media::{self, MediaKind};
+use crate::opener::{OpenMode, Opener};
+use crate::palette::{Palette, PaletteTarget};
+use crate::pdf::{self, PDF_PAGE_LIMIT};
+use crate::preview::*;
+use crate::previewer::Previewer;
+use crate::shell::ShellCommand;
+use crate::sqlite::{self, SqliteBrowser};
+use crate::table::TableView;
+use crate::tree::{ParseError, TreeFormat, TreeView};
+use encoding_rs::Encoding;
 use ::std::path::PathBuf;
+use ratatui::style::{Color, Style};
+use ratatui::text::Text;
 use ratatui::widgets::{ListState, ScrollbarState};
 
+use std::collections::VecDeque;
 use std::env::current_dir;
+use std::process::Command;
 use std::sync::{mpsc,Mutex,Arc};
+use std::sync::mpsc::TryRecvError;
+use std::time::Instant;
 /*FIXME: performance issue here when reading large files,try to load chunks of the file instead
 extern crate rdump;
 use rdump::dump;
@@ -24,7 +54,27 @@ pub enum CurrentScreen {
     Help,
     ConfirmCopyingPopUp,
     CopyingProgressBar,
+    GoToLine,
+    PreviewSearch,
+    EncodingPicker,
+    Compress,
+    ExtractTarget,
+    Diff,
+    Edit,
+    EditSearch,
+    ConfirmEditExit,
+    OpenWith,
+    CommandPrompt,
+    CommandOutput,
+    Palette,
 }
+/// A program given the terminal by the main loop until it exits
+#[derive(Debug)]
+pub struct ForegroundCommand {
+    pub command: Command,
+    pub check_status: bool, // if a failure is shown, a shell exits with the status of its last command
+}
+
 #[derive(Debug)]
 pub struct App {
     /*MAIN*/
@@ -34,6 +84,7 @@ pub struct App {
     pub index_selected: Option<usize>, // the index of the file the user has selected
     pub selected_file: Option<StfmFile>, // the current file the user is selected
     pub list_state: ListState,         // the state of the list widget
+    pub marked: Vec<String>,           // the full paths of the entries marked with Space
 
     /*PREVIEW */
     pub preview_string: String, // the string to be displayed in the preview block
@@ -41,6 +92,80 @@ pub struct App {
     pub vertical_scroll: usize, // the vertical scroll position of the preview block
     pub h_preview_scroll_state: ScrollbarState, // the state of the vertical scrollbar at preview
     pub horizontal_scroll: usize, // the vertical scroll position of the preview block
+    pub preview_line_starts: Vec<usize>, // the byte offset where each line of the preview starts
+    pub preview_max_width: usize, // the width of the longest line of the preview
+    pub preview_height: usize, // the number of visible lines in the preview block, updated on every draw
+    pub preview_area_width: usize, // the number of visible columns in the preview block, updated on every draw
+    pub show_line_numbers: bool, // if the line number gutter should be shown
+    pub soft_wrap: bool, // if long lines should be wrapped instead of scrolled horizontally
+    pub goto_line_input: String, // the line number typed in the go to line prompt
+    pub preview_search_input: String, // the text typed in the preview search prompt
+    pub preview_search_regex: bool, // if the preview search input is a regular expression
+    pub preview_search_ignore_case: bool, // if the preview search ignores case
+    pub preview_search_error: Option<String>, // the error of the last preview search, like an invalid regex
+    pub preview_matches: Vec<PreviewMatch>, // the matches of the last preview search
+    pub preview_match_index: Option<usize>, // the index of the current match in preview_matches
+    pub preview_path: Option<PathBuf>, // the path of the file shown in the preview
+    pub follow: Option<Follow>, // the follow mode state, when the preview is pinned to the end of a growing file
+    pub preview_text: Option<Text<'static>>, // a rendered view of the file, like formatted Markdown, shown instead of preview_string
+    pub preview_show_raw: bool, // if preview_string is shown even when the file has a rendered view
+    pub preview_tree: Option<TreeView>, // the parsed tree of a JSON, TOML or YAML file
+    pub preview_tree_error: Option<ParseError>, // the error found while parsing a structured file, shown above the text
+    pub show_tree: bool, // if the tree is shown instead of the text of a structured file
+    pub preview_table: Option<TableView>, // the rows of a CSV or TSV file or a database table, shown instead of the text
+    pub preview_sqlite: Option<SqliteBrowser>, // the tables and views of a SQLite database, shown instead of the text
+    pub preview_label: Option<String>, // what the preview shows when it is not the text of the file, like "zip archive"
+    pub preview_loader: Option<mpsc::Receiver<Result<PreviewContent, String>>>, // the content being loaded in the background
+    pub preview_size: u64, // the number of bytes of the file decoded into the preview
+    pub preview_encoding: Option<&'static Encoding>, // the encoding used to decode the preview
+    pub preview_encoding_override: Option<&'static Encoding>, // the encoding picked by the user instead of the detected one
+    pub preview_line_ending: LineEnding, // the line ending style of the preview
+    pub encoding_list_state: ListState, // the state of the list in the encoding picker
+
+    /*PREVIEW PANE */
+    pub show_preview_pane: bool, // if the preview pane is shown next to the file list
+    pub pane_content: Option<(String, Text<'static>)>, // the path and the content shown in the preview pane
+    pub pane_target: Option<(String, Instant)>, // the path the preview pane should show and when it was selected
+    pub pane_receiver: Option<(String, mpsc::Receiver<Text<'static>>)>, // the path being loaded in the background and the channel to receive it
+    pub pane_directory: Option<DirectorySummary>, // the summary shown above the entries of a directory in the preview pane
+
+    /*ARCHIVE */
+    pub archive: Option<ArchiveBrowser>, // the archive browsed as if it was a directory
+    pub archive_loader: Option<(PathBuf, mpsc::Receiver<Result<ArchiveIndex, String>>)>, // the archive whose index is being read and the channel to receive it
+    pub compress_sources: Vec<PathBuf>, // the entries put in the archive being named in the compress prompt
+    pub compress_format: usize, // the index in CREATE_FORMATS of the format picked in the compress prompt
+    pub extract_list_state: ListState, // the state of the list picking where an archive is extracted
+
+    /*DIFF */
+    pub diff: Option<DiffView>, // the two files compared in the diff screen
+
+    /*EDITOR */
+    pub editor: Option<Editor>, // the file opened in the editor screen
+    pub foreground_command: Option<ForegroundCommand>, // like $EDITOR, $PAGER or $SHELL
+
+    /*OPENERS */
+    pub openers: Vec<Opener>, // the opener rules of the configuration
+    pub open_with: Vec<Option<usize>>, // the entries of the "Open with" menu: matching rules, None for the preview
+    pub open_with_state: ListState,
+
+    /*COMMAND */
+    pub command_input: String, // the command typed in the '!' prompt
+    pub shell_command: Option<ShellCommand>, // the last command run from the prompt, with its output
+
+    /*CUSTOM COMMANDS */
+    pub custom_commands: Vec<CustomCommand>, // the custom commands of the configuration
+    pending_steps: VecDeque<Step>, // the steps of a custom command waiting for its shell command to exit
+    pub palette: Option<Palette>, // the command palette, with the screen it was opened from
+    pub help_scroll: u16, // the first line of the help shown
+    pub quit: bool,
+
+    /*JOBS */
+    pub jobs: Vec<Job>, // the work running in the background, like creating or extracting an archive
+    pub background_errors: Vec<String>, // errors of background work, shown once the listing is back on screen
+
+    /*PREVIEWER */
+    pub previewer: Option<Previewer>, // the external command building the preview of some files, from the configuration
+    pub previewer_size: (u16, u16), // the columns and lines of the preview given to the previewer, updated on every draw
 
     /*CreateNewFile */
     pub new_file: String,      // the name of the new file to be created
@@ -50,6 +175,8 @@ pub struct App {
     pub search_input: String,
     
     pub file_to_copy: Option<StfmFile>, // the file to be copied
+    pub copy_from_archive: Option<ArchiveMember>, // the archive member behind file_to_copy, when it is copied out of an archive
+    pub copy_error: Arc<Mutex<Option<String>>>, // the error that stopped copying out of an archive, set by the copying thread
     pub readed_bytes: u64, // the number of bytes readed in the copy process
 
     pub progress_sender: Option<mpsc::Sender<u64>>,
@@ -68,17 +195,76 @@ impl App {
             selected_file: None,
             index_selected: None,
             list_state: ListState::default(),
+            marked: Vec::new(),
             preview_string: String::new(),
             v_preview_scroll_state: ScrollbarState::default(),
             h_preview_scroll_state: ScrollbarState::default(),
             vertical_scroll: 0,
             horizontal_scroll: 0,
+            preview_line_starts: Vec::new(),
+            preview_max_width: 0,
+            preview_height: 0,
+            preview_area_width: 0,
+            show_line_numbers: true,
+            soft_wrap: false,
+            goto_line_input: String::new(),
+            preview_search_input: String::new(),
+            preview_search_regex: false,
+            preview_search_ignore_case: false,
+            preview_search_error: None,
+            preview_matches: Vec::new(),
+            preview_match_index: None,
+            preview_path: None,
+            follow: None,
+            preview_text: None,
+            preview_show_raw: false,
+            preview_tree: None,
+            preview_tree_error: None,
+            show_tree: false,
+            preview_table: None,
+            preview_sqlite: None,
+            preview_label: None,
+            preview_loader: None,
+            preview_size: 0,
+            preview_encoding: None,
+            preview_encoding_override: None,
+            preview_line_ending: LineEnding::None,
+            encoding_list_state: ListState::default(),
+            show_preview_pane: false,
+            pane_content: None,
+            pane_target: None,
+            pane_receiver: None,
+            pane_directory: None,
+            archive: None,
+            archive_loader: None,
+            compress_sources: Vec::new(),
+            compress_format: 0,
+            extract_list_state: ListState::default(),
+            diff: None,
+            editor: None,
+            foreground_command: None,
+            openers: Vec::new(),
+            open_with: Vec::new(),
+            open_with_state: ListState::default(),
+            command_input: String::new(),
+            shell_command: None,
+            custom_commands: Vec::new(),
+            pending_steps: VecDeque::new(),
+            palette: None,
+            help_scroll: 0,
+            quit: false,
+            jobs: Vec::new(),
+            background_errors: Vec::new(),
+            previewer: None,
+            previewer_size: (0, 0),
             new_file: String::new(),
             new_file_is_dir: false,
             show_hidden: true,
             error_message: None,
             search_input: String::new(),
             file_to_copy: None,
+            copy_from_archive: None,
+            copy_error: Arc::new(Mutex::new(None)),
             readed_bytes:0,
             progress_sender: None,
             progress_receiver: None,
@@ -86,15 +272,45 @@ impl App {
         a.list_state.select_first();
         a.index_selected = a.list_state.selected();
         a.selected_file = a.files.get(a.index_selected.unwrap_or(0)).cloned();
+        let loaded = Config::load().and_then(|config| {
+            let openers = config.openers.iter().map(Opener::new).collect::<Result<Vec<Opener>, String>>()?;
+            let commands = config
+                .commands
+                .iter()
+                .map(CustomCommand::new)
+                .collect::<Result<Vec<CustomCommand>, String>>()?;
+            Ok((config.previewer.map(Previewer::new), openers, commands))
+        });
+        match loaded {
+            Ok((previewer, openers, commands)) => {
+                a.previewer = previewer;
+                a.openers = openers;
+                a.custom_commands = commands;
+            }
+            Err(e) => {
+                a.error_message = Some(format!("Could not read the configuration: {}", e));
+                a.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
         a
     }
 
     pub fn toggle_hidden(&mut self) {
         self.show_hidden = !self.show_hidden;
+        self.pane_content = None;
+        self.pane_directory = None;
         self.reset();
     }
 
     pub fn cd(&mut self, dir_name: String) {
+        if let Some(archive) = self.archive.as_mut() {
+            archive.dir = match archive.dir.is_empty() {
+                true => dir_name,
+                false => format!("{}/{}", archive.dir, dir_name),
+            };
+            self.reset();
+            return;
+        }
         let mut new_path = self.current_dir.clone();
         new_path.push(dir_name);
         match change_dir(&new_path) {
@@ -169,53 +385,689 @@ impl App {
             Some(file) => {
                 if file.is_dir {
                     self.cd(file.name.clone());
+                } else if let Some(&index) = self.matching_openers(&file).first() {
+                    self.run_opener(index);
                 } else {
-                    self.current_screen = CurrentScreen::Preview;
-                    self.preview_string = match read_file(&file.full_path) {
-                        Ok(content) => content,
-                        Err(e) => {
-                            /*
-                                FIXME: performance issue here when reading large files,try to load chunks of the file instead
-                                match dump(PathBuf::from(&file.full_path),true){
-                                    Ok(content)=>content,
-                                    Err(e)=>{
-                                        self.error_message = Some(e.to_string());
-                                        self.current_screen = CurrentScreen::ErrorPopUp;
-                                        return;
-                                    }
-                                }
-                             */
+                    self.preview_file(&file);
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Show a file in the preview with the first built-in preview, or the previewer, that handles it
+    fn preview_file(&mut self, file: &StfmFile) {
+        if self.archive.is_some() {
+            self.preview_encoding_override = None;
+            self.open_preview(&file.full_path);
+        } else if let Some(previewer) = self.previewer.clone().filter(|previewer| {
+            previewer.handles(&PathBuf::from(&file.full_path))
+        }) {
+            let path = PathBuf::from(&file.full_path);
+            let (width, height) = self.previewer_size;
+            self.open_background_preview(&file.full_path, Some("previewer".to_string()), move || {
+                previewer.run(&path, width, height).map(PreviewContent::Rendered)
+            });
+        } else if TableView::handles(&PathBuf::from(&file.full_path)) {
+            self.open_table_preview(&file.full_path);
+        } else if sqlite::handles(&PathBuf::from(&file.full_path)) {
+            self.open_sqlite_preview(&file.full_path);
+        } else if let Some(format) = ArchiveFormat::from_path(&PathBuf::from(&file.full_path)) {
+            self.open_archive(&file.full_path, format);
+        } else if let Some(format) = BinaryFormat::from_path(&PathBuf::from(&file.full_path)) {
+            let path = PathBuf::from(&file.full_path);
+            // A file goblin cannot parse is shown like any other file
+            self.open_background_preview(&file.full_path, Some(format!("{} binary", format)), move || {
+                match binary::summary(&path) {
+                    Ok(text) => Ok(PreviewContent::Rendered(text)),
+                    Err(_) => read_file(&path.to_string_lossy()).map(PreviewContent::File).map_err(|e| e.to_string()),
+                }
+            });
+        } else if pdf::handles(&PathBuf::from(&file.full_path)) {
+            let path = PathBuf::from(&file.full_path);
+            self.open_background_preview(&file.full_path, Some("PDF".to_string()), move || {
+                let (label, text) = pdf::extract(&path, PDF_PAGE_LIMIT)?;
+                Ok(PreviewContent::Labelled(label, text))
+            });
+        } else if let Some(kind) = MediaKind::from_path(&PathBuf::from(&file.full_path)) {
+            let path = PathBuf::from(&file.full_path);
+            self.open_background_preview(&file.full_path, Some(format!("{} metadata", kind)), move || {
+                media::summary(&path).map(PreviewContent::Rendered)
+            });
+        } else {
+            self.preview_encoding_override = None;
+            self.open_preview(&file.full_path);
+        }
+    }
+
+    /// Read a file into the preview, decoding it with the encoding picked by the user or the detected one
+    pub fn open_preview(&mut self, path: &str) {
+        // Members of an archive are decompressed in the background, it can take a while to get to them
+        if let Some(member) = self.archive_member(path) {
+            self.open_background_preview(path, None, move || match read_member(&member, MEMBER_READ_LIMIT) {
+                Ok(bytes) => Ok(PreviewContent::File(bytes)),
+                Err(e) => Err(e.to_string()),
+            });
+            return;
+        }
+        self.current_screen = CurrentScreen::Preview;
+        let bytes = match read_file(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                /*
+                    FIXME: performance issue here when reading large files,try to load chunks of the file instead
+                    match dump(PathBuf::from(path),true){
+                        Ok(content)=>content,
+                        Err(e)=>{
                             self.error_message = Some(e.to_string());
                             self.current_screen = CurrentScreen::ErrorPopUp;
                             return;
                         }
-                    };
-                    self.v_preview_scroll_state = self
-                        .v_preview_scroll_state
-                        .content_length(self.preview_string.len());
-                    self.vertical_scroll = 0;
-                    self.h_preview_scroll_state = self
-                        .h_preview_scroll_state
-                        .content_length(self.preview_string.len());
-                    self.horizontal_scroll = 0;
+                    }
+                 */
+                self.error_message = Some(e.to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        self.show_file_preview(path, bytes);
+    }
+
+    /// Show the bytes of the file at `path` in the preview, with the rendered view or tree its extension has
+    fn show_file_preview(&mut self, path: &str, bytes: Vec<u8>) {
+        let decoded = match decode(&bytes, self.preview_encoding_override) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        self.load_preview(decoded.text);
+        self.preview_size = bytes.len() as u64;
+        self.preview_encoding = Some(decoded.encoding);
+        self.preview_line_ending = decoded.line_ending;
+        self.preview_path = Some(PathBuf::from(path));
+        let extension = match PathBuf::from(path).extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => String::new(),
+        };
+        if MARKDOWN_EXTENSIONS.contains(&extension.as_str()) {
+            let text = markdown::render(&self.preview_string);
+            self.set_rendered_preview(text);
+        }
+        if let Some(format) = TreeFormat::from_extension(&extension) {
+            match TreeView::parse(&self.preview_string, format) {
+                Ok(tree) => {
+                    self.preview_tree = Some(tree);
+                    self.show_tree = true;
+                }
+                Err(e) => {
+                    self.go_to_line(e.line);
+                    self.preview_tree_error = Some(e);
                 }
             }
-            None => {}
         }
     }
 
+    /// If the preview shows the tree of a structured file
+    pub fn tree_active(&self) -> bool {
+        self.show_tree && self.preview_tree.is_some()
+    }
+
+    /// Switch between the tree and the text of a structured file
+    pub fn toggle_tree(&mut self) {
+        if self.preview_tree.is_some() {
+            self.show_tree = !self.show_tree;
+        }
+    }
+
+    /// Show a delimited file as a table, only reading the rows that are displayed
+    pub fn open_table_preview(&mut self, path: &str) {
+        match TableView::open(&PathBuf::from(path)) {
+            Ok(table) => {
+                self.load_preview(String::new());
+                self.preview_table = Some(table);
+                self.preview_path = Some(PathBuf::from(path));
+                self.current_screen = CurrentScreen::Preview;
+            }
+            Err(e) => {
+                self.error_message = Some(e.to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
+    }
+
+    /// Show the tables and views of a SQLite database, opened read-only
+    pub fn open_sqlite_preview(&mut self, path: &str) {
+        match SqliteBrowser::open(&PathBuf::from(path)) {
+            Ok(browser) => {
+                self.load_preview(String::new());
+                self.preview_sqlite = Some(browser);
+                self.preview_path = Some(PathBuf::from(path));
+                self.current_screen = CurrentScreen::Preview;
+            }
+            Err(e) => {
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
+    }
+
+    /// Page through the rows of the table or view selected in the database, going back to the list closes them
+    pub fn open_sqlite_rows(&mut self) {
+        let browser = match &self.preview_sqlite {
+            Some(browser) => browser,
+            None => return,
+        };
+        let object = match browser.selected() {
+            Some(object) => object,
+            None => return,
+        };
+        match TableView::open_sqlite(&browser.path, &object.name, &object.kind) {
+            Ok(table) => self.preview_table = Some(table),
+            Err(e) => {
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
+    }
+
+    /// Show content loaded by `load` in a background thread, a loading message is shown until it is ready.
+    /// `label` tells what the preview shows when it is not the text of the file
+    pub fn open_background_preview<F>(&mut self, path: &str, label: Option<String>, load: F)
+    where
+        F: FnOnce() -> Result<PreviewContent, String> + Send + 'static,
+    {
+        self.load_preview(String::new());
+        self.preview_path = Some(PathBuf::from(path));
+        self.preview_encoding = None;
+        self.preview_size = 0;
+        self.set_rendered_preview(Text::styled("Loading...", Style::default().fg(Color::DarkGray)));
+        self.preview_label = label;
+        let (sender, receiver) = mpsc::channel();
+        self.preview_loader = Some(receiver);
+        std::thread::spawn(move || {
+            // The preview may have been closed and dropped the receiver, the result is not needed then
+            let _ = sender.send(load());
+        });
+        self.current_screen = CurrentScreen::Preview;
+    }
+
+    fn poll_preview_loader(&mut self) {
+        let receiver = match &self.preview_loader {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        match receiver.try_recv() {
+            Ok(Ok(PreviewContent::File(bytes))) => {
+                self.preview_loader = None;
+                self.preview_label = None;
+                let path = match self.preview_path.clone() {
+                    Some(path) => path.to_string_lossy().to_string(),
+                    None => return,
+                };
+                self.show_file_preview(&path, bytes);
+            }
+            Ok(Ok(PreviewContent::Rendered(text))) => {
+                self.preview_loader = None;
+                self.set_rendered_preview(text);
+            }
+            Ok(Ok(PreviewContent::Labelled(label, text))) => {
+                self.preview_loader = None;
+                self.preview_label = Some(label);
+                self.set_rendered_preview(text);
+            }
+            Ok(Err(e)) => {
+                self.preview_loader = None;
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.preview_loader = None;
+            }
+        }
+    }
+
+    /// Start browsing an archive as a directory once its index is read in the background
+    pub fn open_archive(&mut self, path: &str, format: ArchiveFormat) {
+        let archive_path = PathBuf::from(path);
+        let (sender, receiver) = mpsc::channel();
+        self.archive_loader = Some((archive_path.clone(), receiver));
+        std::thread::spawn(move || {
+            let index = ArchiveIndex::read(&archive_path, format).map_err(|e| e.to_string());
+            let _ = sender.send(index);
+        });
+    }
+
+    fn poll_archive_loader(&mut self) {
+        let (path, receiver) = match &self.archive_loader {
+            Some(loader) => loader,
+            None => return,
+        };
+        match receiver.try_recv() {
+            Ok(Ok(index)) => {
+                self.archive = Some(ArchiveBrowser::new(path.clone(), index));
+                self.archive_loader = None;
+                self.reset();
+            }
+            Ok(Err(e)) => {
+                self.archive_loader = None;
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.archive_loader = None;
+            }
+        }
+    }
+
+    /// The archive member shown in the file list as `path`, when an archive is being browsed
+    pub fn archive_member(&self, path: &str) -> Option<ArchiveMember> {
+        match &self.archive {
+            Some(archive) => archive.member(path),
+            None => None,
+        }
+    }
+
+    /// The directory being listed, which is inside the archive when one is browsed
+    pub fn location(&self) -> PathBuf {
+        match &self.archive {
+            Some(archive) => archive.location(),
+            None => self.current_dir.clone(),
+        }
+    }
+
+    /// Files of the directory being listed
+    fn list_current_dir(&self) -> Vec<StfmFile> {
+        match &self.archive {
+            Some(archive) => archive.list_files(&archive.dir, self.show_hidden),
+            None => list_files(&self.current_dir, self.show_hidden),
+        }
+    }
+
+    /// Open the encoding picker with the current encoding of the preview selected
+    pub fn open_encoding_picker(&mut self) {
+        // Only the text of a file has an encoding, not a view like an archive listing
+        if self.preview_encoding.is_none() {
+            return;
+        }
+        let index = PICKER_ENCODINGS
+            .iter()
+            .position(|encoding| Some(*encoding) == self.preview_encoding)
+            .unwrap_or(0);
+        self.encoding_list_state.select(Some(index));
+        self.current_screen = CurrentScreen::EncodingPicker;
+    }
+
+    /// Reload the preview with the encoding selected in the picker
+    pub fn pick_encoding(&mut self) {
+        let encoding = match self.encoding_list_state.selected() {
+            Some(index) => PICKER_ENCODINGS[index.min(PICKER_ENCODINGS.len() - 1)],
+            None => return,
+        };
+        let path = match self.preview_path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        self.preview_encoding_override = Some(encoding);
+        self.open_preview(&path.to_string_lossy());
+    }
+
     pub fn previus_dir(&mut self) {
+        if let Some(archive) = self.archive.as_mut() {
+            // Going up from the root of the archive goes back to the directory that has it
+            match archive.dir.rsplit_once('/') {
+                Some((parent, _)) => archive.dir = parent.to_string(),
+                None if !archive.dir.is_empty() => archive.dir.clear(),
+                None => self.archive = None,
+            }
+            self.reset();
+            return;
+        }
         self.current_dir.pop();
         self.reset();
     }
 
+    /// Replace the preview content and move the scroll back to the top
+    pub fn load_preview(&mut self, content: String) {
+        self.preview_string = content;
+        self.preview_line_starts = line_starts(&self.preview_string);
+        self.preview_text = None;
+        self.preview_show_raw = false;
+        self.preview_tree = None;
+        self.preview_tree_error = None;
+        self.show_tree = false;
+        self.preview_table = None;
+        self.preview_sqlite = None;
+        self.preview_label = None;
+        self.preview_loader = None;
+        self.update_preview_size();
+        self.vertical_scroll = 0;
+        self.horizontal_scroll = 0;
+        self.v_preview_scroll_state = self.v_preview_scroll_state.position(0);
+        self.h_preview_scroll_state = self.h_preview_scroll_state.position(0);
+        self.preview_matches.clear();
+        self.preview_match_index = None;
+        self.preview_search_error = None;
+        self.follow = None;
+    }
+
+    /// Add text to the end of the preview, only scanning the lines that changed
+    pub fn append_preview(&mut self, text: &str) {
+        let first_changed_line = self.preview_line_count().saturating_sub(1);
+        let from = self.preview_line_starts.pop().unwrap_or_default();
+        self.preview_string.push_str(text);
+        let new_starts = line_starts(&self.preview_string[from..]);
+        self.preview_line_starts.extend(new_starts.iter().map(|start| start + from));
+        for index in first_changed_line..self.preview_line_count() {
+            let width = line_width(&self.preview_plain_line(index));
+            self.preview_max_width = self.preview_max_width.max(width);
+        }
+        self.v_preview_scroll_state = self
+            .v_preview_scroll_state
+            .content_length(self.preview_line_count());
+        self.h_preview_scroll_state = self
+            .h_preview_scroll_state
+            .content_length(self.preview_max_width);
+        // Only the new lines are searched, and the last line as it may have grown
+        if !self.preview_search_input.is_empty() && self.preview_search_error.is_none() {
+            if let Ok(matches) = self.find_preview_matches(first_changed_line) {
+                let kept = self.preview_matches.partition_point(|found| found.line < first_changed_line);
+                self.preview_matches.truncate(kept);
+                self.preview_matches.extend(matches);
+            }
+        }
+    }
+
+    /// Start or stop keeping the preview pinned to the end of the file as it grows
+    pub fn toggle_follow(&mut self) {
+        if self.follow.is_some() {
+            self.follow = None;
+            return;
+        }
+        // Only files on disk grow, not the members of an archive or views like an archive listing
+        if self.preview_encoding.is_none() || self.archive.is_some() {
+            return;
+        }
+        let path = match self.preview_path.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        // New content is appended to the raw file, a rendered view would not show it
+        if self.preview_text.is_some() && !self.preview_show_raw {
+            self.toggle_raw_preview();
+        }
+        self.show_tree = false;
+        let encoding = self.preview_encoding.unwrap_or(encoding_rs::UTF_8);
+        match Follow::new(&path, self.preview_size, encoding) {
+            Ok(follow) => {
+                self.follow = Some(follow);
+                self.scroll_bottom();
+            }
+            Err(e) => {
+                self.error_message = Some(e.to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
+    }
+
+    /// Called on every iteration of the event loop to update state that changes without user input
+    pub fn tick(&mut self) {
+        if let CurrentScreen::Preview = self.current_screen {
+            self.poll_follow();
+            self.poll_preview_loader();
+            if let Some(table) = self.preview_table.as_mut() {
+                table.poll_count();
+            }
+            if let Some(browser) = self.preview_sqlite.as_mut() {
+                browser.poll_counts();
+            }
+        }
+        if let Some(diff) = self.diff.as_mut() {
+            diff.poll();
+        }
+        self.poll_archive_loader();
+        self.poll_jobs();
+        // Errors of background work wait for the listing instead of taking over the screen showing
+        if matches!(self.current_screen, CurrentScreen::Main) && !self.background_errors.is_empty() {
+            self.error_message = Some(self.background_errors.drain(..).collect::<Vec<String>>().join("\n"));
+            self.current_screen = CurrentScreen::ErrorPopUp;
+        }
+        // The command may have changed the files
+        if self.shell_command.as_mut().is_some_and(|command| command.poll()) {
+            self.refresh();
+            let shown = matches!(self.current_screen, CurrentScreen::CommandOutput);
+            // The next steps of a custom command only run when its shell command succeeded
+            match self.shell_command.as_ref().is_some_and(|command| command.succeeded()) {
+                true => self.run_pending_steps(shown),
+                false => {
+                    self.pending_steps.clear();
+                    // A command running hidden tells it failed like a job does
+                    if let (false, Some(command)) = (shown, self.shell_command.as_ref()) {
+                        self.background_errors
+                            .push(format!("!{} failed, press 'O' to see its output", command.line));
+                    }
+                }
+            }
+        }
+        if self.show_preview_pane {
+            self.update_preview_pane();
+        }
+    }
+
+    pub fn toggle_preview_pane(&mut self) {
+        self.show_preview_pane = !self.show_preview_pane;
+        self.pane_content = None;
+        self.pane_target = None;
+        self.pane_receiver = None;
+        self.pane_directory = None;
+    }
+
+    /// Load the selected entry in the preview pane once the selection stopped moving for `PANE_DEBOUNCE`
+    fn update_preview_pane(&mut self) {
+        if let Some((path, receiver)) = &self.pane_receiver {
+            match receiver.try_recv() {
+                Ok(content) => {
+                    self.pane_content = Some((path.clone(), content));
+                    self.pane_receiver = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    self.pane_receiver = None;
+                }
+            }
+        }
+        if let Some(summary) = self.pane_directory.as_mut() {
+            summary.poll();
+        }
+        let file = match self.selected_file.clone() {
+            Some(file) => file,
+            None => {
+                self.pane_content = None;
+                self.pane_target = None;
+                self.pane_directory = None;
+                return;
+            }
+        };
+        // Dropping the summary of another entry stops the walk of its subdirectories
+        if matches!(&self.pane_directory, Some(summary) if summary.path != file.full_path) {
+            self.pane_directory = None;
+        }
+        match &self.pane_target {
+            Some((path, selected_at)) if *path == file.full_path => {
+                if selected_at.elapsed() < PANE_DEBOUNCE {
+                    return;
+                }
+            }
+            _ => {
+                self.pane_target = Some((file.full_path.clone(), Instant::now()));
+                return;
+            }
+        }
+        if file.is_dir && self.archive.is_none() && self.pane_directory.is_none() {
+            self.pane_directory = Some(DirectorySummary::open(&file.full_path, self.show_hidden));
+        }
+        let loaded = matches!(&self.pane_content, Some((path, _)) if *path == file.full_path);
+        let loading = matches!(&self.pane_receiver, Some((path, _)) if *path == file.full_path);
+        if loaded || loading {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.pane_receiver = Some((file.full_path.clone(), receiver));
+        let show_hidden = self.show_hidden;
+        let archive = self.archive.clone();
+        let previewer = self.previewer.clone().map(|previewer| (previewer, self.previewer_size));
+        std::thread::spawn(move || {
+            let path = PathBuf::from(&file.full_path);
+            let content = pane_content(&path, file.is_dir, show_hidden, PANE_MAX_LINES, archive, previewer);
+            // The selection may have moved on and dropped the receiver, the result is not needed then
+            let _ = sender.send(content);
+        });
+    }
+
+    fn poll_follow(&mut self) {
+        let follow = match self.follow.as_mut() {
+            Some(follow) => follow,
+            None => return,
+        };
+        if follow.last_check.elapsed() < FOLLOW_INTERVAL {
+            return;
+        }
+        let update = follow.poll();
+        self.preview_size = follow.offset;
+        match update {
+            Ok(FollowUpdate::Unchanged) => {}
+            Ok(FollowUpdate::Appended(text)) => {
+                self.append_preview(&text);
+                self.scroll_bottom();
+            }
+            Ok(FollowUpdate::Reopened(content)) => {
+                let follow = self.follow.take();
+                self.load_preview(content);
+                self.follow = follow;
+                self.preview_line_ending = detect_line_ending(&self.preview_string);
+                self.scroll_bottom();
+            }
+            // The file can be missing for a moment while it is rotated, try again on the next poll
+            Err(_) => {}
+        }
+    }
+
+    /// The rendered view of the preview, unless the user asked for the raw file
+    pub fn rendered_preview(&self) -> Option<&Text<'static>> {
+        match self.preview_show_raw {
+            true => None,
+            false => self.preview_text.as_ref(),
+        }
+    }
+
+    /// Show a rendered view of the file, like formatted Markdown, instead of its raw content
+    pub fn set_rendered_preview(&mut self, text: Text<'static>) {
+        self.preview_text = Some(text);
+        self.preview_show_raw = false;
+        self.update_preview_size();
+    }
+
+    /// Switch between the rendered view and the raw content of the file
+    pub fn toggle_raw_preview(&mut self) {
+        if self.preview_text.is_none() || self.preview_label.is_some() {
+            return;
+        }
+        self.preview_show_raw = !self.preview_show_raw;
+        self.update_preview_size();
+        self.scroll_top();
+        self.horizontal_scroll = 0;
+        self.h_preview_scroll_state = self.h_preview_scroll_state.position(0);
+        if !self.preview_search_input.is_empty() {
+            self.preview_search();
+        }
+    }
+
+    /// Update the width and scrollbars after the shown content of the preview changed
+    fn update_preview_size(&mut self) {
+        self.preview_max_width = 0;
+        for index in 0..self.preview_line_count() {
+            self.preview_max_width = self.preview_max_width.max(line_width(&self.preview_plain_line(index)));
+        }
+        let last_line = self.preview_line_count().saturating_sub(1);
+        self.vertical_scroll = self.vertical_scroll.min(last_line);
+        self.v_preview_scroll_state = self
+            .v_preview_scroll_state
+            .content_length(self.preview_line_count());
+        self.h_preview_scroll_state = self
+            .h_preview_scroll_state
+            .content_length(self.preview_max_width);
+    }
+
+    /// Number of lines in the preview
+    pub fn preview_line_count(&self) -> usize {
+        match self.rendered_preview() {
+            Some(text) => text.lines.len(),
+            None => self.preview_line_starts.len(),
+        }
+    }
+
+    /// Line `index` of the preview as plain text, the way it is displayed
+    pub fn preview_plain_line(&self, index: usize) -> String {
+        match self.rendered_preview() {
+            Some(text) => match text.lines.get(index) {
+                Some(line) => display_line(&line.spans.iter().map(|span| span.content.as_ref()).collect::<String>()),
+                None => String::new(),
+            },
+            None => display_line(line_at(&self.preview_string, &self.preview_line_starts, index)),
+        }
+    }
+
+    /// Line `index` of the preview as displayed characters with their style
+    pub fn preview_styled_line(&self, index: usize) -> Vec<(char, Style)> {
+        match self.rendered_preview() {
+            Some(text) => {
+                let mut chars = Vec::new();
+                if let Some(line) = text.lines.get(index) {
+                    for span in line.spans.iter() {
+                        let style = line.style.patch(span.style);
+                        chars.extend(display_line(&span.content).chars().map(|c| (c, style)));
+                    }
+                }
+                chars
+            }
+            None => self
+                .preview_plain_line(index)
+                .chars()
+                .map(|c| (c, Style::default()))
+                .collect(),
+        }
+    }
+
+    /// The matches of the search in the lines of the preview from `first_line`
+    fn find_preview_matches(&self, first_line: usize) -> Result<Vec<PreviewMatch>, String> {
+        let matches = find_matches(
+            (first_line..self.preview_line_count()).map(|index| self.preview_plain_line(index)),
+            &self.preview_search_input,
+            self.preview_search_regex,
+            self.preview_search_ignore_case,
+        )?;
+        Ok(matches
+            .into_iter()
+            .map(|found| PreviewMatch {
+                line: found.line + first_line,
+                ..found
+            })
+            .collect())
+    }
+
     pub fn scroll_up(&mut self,position: usize) {
+        self.follow = None;
         self.vertical_scroll = self.vertical_scroll.saturating_sub(position);
         self.v_preview_scroll_state = self.v_preview_scroll_state.position(self.vertical_scroll);
     }
 
     pub fn scroll_down(&mut self,position: usize) {
-        self.vertical_scroll = self.vertical_scroll.saturating_add(position);
+        let last_line = self.preview_line_count().saturating_sub(1);
+        self.vertical_scroll = self.vertical_scroll.saturating_add(position).min(last_line);
         self.v_preview_scroll_state = self.v_preview_scroll_state.position(self.vertical_scroll);
     }
 
@@ -225,10 +1077,124 @@ impl App {
     }
 
     pub fn scroll_right(&mut self) {
-        self.horizontal_scroll = self.horizontal_scroll.saturating_add(10);
+        if self.soft_wrap {
+            return;
+        }
+        let max_scroll = self.preview_max_width.saturating_sub(10);
+        self.horizontal_scroll = self.horizontal_scroll.saturating_add(10).min(max_scroll);
         self.h_preview_scroll_state = self.h_preview_scroll_state.position(self.horizontal_scroll);
     }
 
+    /// Jump to the first line of the preview
+    pub fn scroll_top(&mut self) {
+        self.follow = None;
+        self.vertical_scroll = 0;
+        self.v_preview_scroll_state = self.v_preview_scroll_state.position(self.vertical_scroll);
+    }
+
+    /// Jump so the last line of the preview is at the bottom of the block
+    pub fn scroll_bottom(&mut self) {
+        self.vertical_scroll = self
+            .preview_line_count()
+            .saturating_sub(self.preview_height.max(1));
+        self.v_preview_scroll_state = self.v_preview_scroll_state.position(self.vertical_scroll);
+    }
+
+    /// Move the preview so `line` (starting at 1) is the first visible line
+    pub fn go_to_line(&mut self, line: usize) {
+        let last_line = self.preview_line_count().saturating_sub(1);
+        self.vertical_scroll = line.saturating_sub(1).min(last_line);
+        self.v_preview_scroll_state = self.v_preview_scroll_state.position(self.vertical_scroll);
+    }
+
+    /// Search the preview for `preview_search_input` and jump to the first match after the top line
+    pub fn preview_search(&mut self) {
+        self.preview_matches.clear();
+        self.preview_match_index = None;
+        self.preview_search_error = None;
+        if self.preview_search_input.is_empty() {
+            return;
+        }
+        match self.find_preview_matches(0) {
+            Ok(matches) => self.preview_matches = matches,
+            Err(e) => {
+                self.preview_search_error = Some(e);
+                return;
+            }
+        }
+        if self.preview_matches.is_empty() {
+            return;
+        }
+        let index = self
+            .preview_matches
+            .iter()
+            .position(|found| found.line >= self.vertical_scroll)
+            .unwrap_or(0);
+        self.select_match(index);
+    }
+
+    /// Jump to the next match of the preview search, wrapping at the end
+    pub fn next_match(&mut self) {
+        if self.preview_matches.is_empty() {
+            return;
+        }
+        let index = match self.preview_match_index {
+            Some(index) => (index + 1) % self.preview_matches.len(),
+            None => 0,
+        };
+        self.select_match(index);
+    }
+
+    /// Jump to the previous match of the preview search, wrapping at the start
+    pub fn previous_match(&mut self) {
+        if self.preview_matches.is_empty() {
+            return;
+        }
+        let index = match self.preview_match_index {
+            Some(0) | None => self.preview_matches.len() - 1,
+            Some(index) => index - 1,
+        };
+        self.select_match(index);
+    }
+
+    /// Make match `index` the current one and scroll it into view
+    fn select_match(&mut self, index: usize) {
+        let found = self.preview_matches[index];
+        self.preview_match_index = Some(index);
+        let visible = self.vertical_scroll..self.vertical_scroll + self.preview_height.max(1);
+        if !visible.contains(&found.line) {
+            self.vertical_scroll = found.line.saturating_sub(self.preview_height / 3);
+            self.v_preview_scroll_state = self.v_preview_scroll_state.position(self.vertical_scroll);
+        }
+        // The match is in characters and the scroll in columns
+        let widths: Vec<usize> = self.preview_plain_line(found.line).chars().map(char_width).collect();
+        let start = widths.iter().take(found.start).sum::<usize>();
+        let end = widths.iter().take(found.end).sum::<usize>();
+        if !self.soft_wrap && (start < self.horizontal_scroll || end > self.horizontal_scroll + self.preview_width()) {
+            self.horizontal_scroll = start.saturating_sub(10) / 10 * 10;
+            self.h_preview_scroll_state = self.h_preview_scroll_state.position(self.horizontal_scroll);
+        }
+    }
+
+    /// Number of columns available for text in the preview block, without the line number gutter
+    pub fn preview_width(&self) -> usize {
+        let gutter_width = match self.show_line_numbers {
+            true => self.preview_line_count().max(1).to_string().len() + 1,
+            false => 0,
+        };
+        self.preview_area_width.saturating_sub(gutter_width).max(1)
+    }
+
+    pub fn toggle_line_numbers(&mut self) {
+        self.show_line_numbers = !self.show_line_numbers;
+    }
+
+    pub fn toggle_soft_wrap(&mut self) {
+        self.soft_wrap = !self.soft_wrap;
+        self.horizontal_scroll = 0;
+        self.h_preview_scroll_state = self.h_preview_scroll_state.position(0);
+    }
+
     pub fn new_file(&mut self, file_name: &str) {
         if !self.new_file_is_dir {
             let full_new_path = PathBuf::from(&self.current_dir).join(file_name);
@@ -311,7 +1277,7 @@ impl App {
     pub fn search(&mut self) {
         let query = self.search_input.clone();
         let mut new_files = Vec::new();
-        let files= list_files(&self.current_dir, self.show_hidden);
+        let files= self.list_current_dir();
         for file in files.iter() {
             if file.name.contains(&query) {
                 new_files.push(file.clone());
@@ -325,7 +1291,8 @@ impl App {
 
     pub fn reset(&mut self) {
         self.search_input.clear();
-        self.files = list_files(&self.current_dir, self.show_hidden);
+        self.marked.clear();
+        self.files = self.list_current_dir();
         self.list_state.select_first();
         self.index_selected = Some(0);
         self.selected_file = self.files.get(self.index_selected.unwrap_or(0)).cloned();
@@ -335,11 +1302,841 @@ impl App {
         self.vertical_scroll = 0;
         self.horizontal_scroll = 0;
         self.preview_string.clear();
+        self.preview_line_starts.clear();
+        self.preview_max_width = 0;
+        self.goto_line_input.clear();
+        self.preview_search_input.clear();
+        self.preview_search_error = None;
+        self.preview_matches.clear();
+        self.preview_match_index = None;
+        self.preview_path = None;
+        self.follow = None;
+        self.preview_text = None;
+        self.preview_show_raw = false;
+        self.preview_tree = None;
+        self.preview_tree_error = None;
+        self.show_tree = false;
+        self.preview_table = None;
+        self.preview_sqlite = None;
+        self.preview_label = None;
+        self.preview_loader = None;
+        self.preview_size = 0;
+        self.preview_encoding = None;
+        self.preview_encoding_override = None;
+        self.preview_line_ending = LineEnding::None;
         self.progress_sender= None;
         self.progress_receiver= None;
         self.current_screen = CurrentScreen::Main;
     }
 
+    /// List the current directory again, keeping the selection where it was
+    pub fn refresh(&mut self) {
+        self.files = self.list_current_dir();
+        let index = self.index_selected.unwrap_or(0).min(self.files.len().saturating_sub(1));
+        self.list_state.select(Some(index));
+        self.index_selected = Some(index);
+        self.selected_file = self.files.get(index).cloned();
+        self.pane_content = None;
+        self.pane_directory = None;
+    }
+
+    /// Mark or unmark the selected entry and move to the next one
+    pub fn toggle_mark(&mut self) {
+        let file = match self.selected_file.clone() {
+            Some(file) => file,
+            None => return,
+        };
+        match self.marked.iter().position(|path| *path == file.full_path) {
+            Some(index) => {
+                self.marked.remove(index);
+            }
+            None => self.marked.push(file.full_path),
+        }
+        self.down();
+    }
+
+    /// Open the compress prompt for the marked entries, or the selected one when nothing is marked
+    pub fn open_compress(&mut self) {
+        self.compress_sources = match self.marked.is_empty() {
+            true => match &self.selected_file {
+                Some(file) => vec![PathBuf::from(&file.full_path)],
+                None => Vec::new(),
+            },
+            false => self.marked.iter().map(PathBuf::from).collect(),
+        };
+        self.new_file = match self.compress_sources.as_slice() {
+            [] => {
+                self.error_message = Some("No file selected".to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+            [source] => match source.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => String::new(),
+            },
+            _ => match self.current_dir.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => "archive".to_string(),
+            },
+        };
+        self.current_screen = CurrentScreen::Compress;
+    }
+
+    pub fn next_compress_format(&mut self) {
+        self.compress_format = (self.compress_format + 1) % CREATE_FORMATS.len();
+    }
+
+    pub fn previous_compress_format(&mut self) {
+        self.compress_format = (self.compress_format + CREATE_FORMATS.len() - 1) % CREATE_FORMATS.len();
+    }
+
+    /// Name of the archive the compress prompt creates, with the extension of the picked format
+    pub fn compress_name(&self) -> String {
+        let format = CREATE_FORMATS[self.compress_format];
+        let name = self.new_file.trim();
+        match name.to_lowercase().ends_with(&format!(".{}", format)) {
+            true => name.to_string(),
+            false => format!("{}.{}", name, format),
+        }
+    }
+
+    /// Create the archive named in the compress prompt in a background job
+    pub fn compress(&mut self) {
+        if self.new_file.trim().is_empty() {
+            return;
+        }
+        let format = CREATE_FORMATS[self.compress_format];
+        let name = self.compress_name();
+        let destination = self.current_dir.join(&name);
+        let sources = std::mem::take(&mut self.compress_sources);
+        self.jobs.push(Job::spawn(format!("Compressing {}", name), move |progress| {
+            let mut report = |done, total| progress.report(done, total);
+            create_archive(&sources, &destination, format, &mut report).map_err(|e| e.to_string())
+        }));
+        self.new_file.clear();
+        self.marked.clear();
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Compare the two marked files, or the marked file with the selected one
+    pub fn open_diff(&mut self) {
+        let mut paths = self.marked.clone();
+        if paths.len() == 1 {
+            if let Some(file) = &self.selected_file {
+                if file.full_path != paths[0] {
+                    paths.push(file.full_path.clone());
+                }
+            }
+        }
+        let (old, new) = match paths.as_slice() {
+            [old, new] if self.archive.is_none() => (PathBuf::from(old), PathBuf::from(new)),
+            _ => {
+                self.error_message = Some("Mark two files with 'Space', or one and select the other, to compare them".to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        if old.is_dir() || new.is_dir() {
+            self.error_message = Some("Only files can be compared".to_string());
+            self.current_screen = CurrentScreen::ErrorPopUp;
+            return;
+        }
+        self.diff = Some(DiffView::open(&old, &new));
+        self.current_screen = CurrentScreen::Diff;
+    }
+
+    /// Open the selected file in the editor, or go back to the editor left with unsaved changes
+    pub fn open_editor(&mut self) {
+        if self.editor.as_ref().is_some_and(|editor| editor.is_dirty()) {
+            self.current_screen = CurrentScreen::Edit;
+            return;
+        }
+        let file = match self.selected_file.clone() {
+            Some(file) if !file.is_dir && self.archive.is_none() => file,
+            _ => {
+                self.error_message = Some("Select a file to edit it, members of archives cannot be edited".to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        match Editor::open(&PathBuf::from(&file.full_path)) {
+            Ok(editor) => {
+                self.editor = Some(editor);
+                self.current_screen = CurrentScreen::Edit;
+            }
+            Err(e) => {
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
+    }
+
+    /// Leave the editor, asking first when it has unsaved changes unless `discard` is set
+    pub fn close_editor(&mut self, discard: bool) {
+        if !discard && self.editor.as_ref().is_some_and(|editor| editor.is_dirty()) {
+            self.current_screen = CurrentScreen::ConfirmEditExit;
+            return;
+        }
+        self.editor = None;
+        // The size and modification time of the file changed when it was saved
+        self.refresh();
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Open the selected file in the program named by the first set environment variable of `variables`,
+    /// or in `fallback`. The program can have quoted arguments, like "code --wait" or "less -R '+G'"
+    pub fn open_external(&mut self, variables: &[&str], fallback: &str) {
+        let file = match self.selected_file.clone() {
+            Some(file) if !file.is_dir && self.archive.is_none() => file,
+            _ => {
+                self.error_message = Some("Select a file to open it, members of archives cannot be opened".to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        let program = variables
+            .iter()
+            .filter_map(|variable| std::env::var(variable).ok())
+            .find(|value| !value.trim().is_empty())
+            .unwrap_or_else(|| fallback.to_string());
+        // Split like a shell would, so quoted paths and arguments keep their spaces
+        let words = match shell_words::split(&program) {
+            Ok(words) => words,
+            Err(e) => {
+                self.error_message = Some(format!("Could not run \"{}\": {}", program, e));
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        let (name, args) = match words.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut command = Command::new(name);
+        command.args(args).arg(&file.full_path).current_dir(&self.current_dir);
+        self.foreground_command = Some(ForegroundCommand {
+            command,
+            check_status: true,
+        });
+    }
+
+    /// Start $SHELL in the current directory. STFM_LEVEL tells it is a subshell of stfm, and like in lf
+    /// $f is the selected file, $fs the marked entries and $fx the marked entries or the selected file
+    pub fn open_shell(&mut self) {
+        let shell = match std::env::var("SHELL") {
+            Ok(shell) if !shell.trim().is_empty() => shell,
+            _ => "sh".to_string(),
+        };
+        let level = std::env::var("STFM_LEVEL")
+            .ok()
+            .and_then(|level| level.parse::<u32>().ok())
+            .unwrap_or(0);
+        // Members of an archive have no path the shell could use
+        let (selected, marked) = match self.archive {
+            Some(_) => (String::new(), Vec::new()),
+            None => (
+                self.selected_file.as_ref().map(|file| file.full_path.clone()).unwrap_or_default(),
+                self.marked.clone(),
+            ),
+        };
+        let selection = match marked.is_empty() {
+            true => selected.clone(),
+            false => marked.join("\n"),
+        };
+        let mut command = Command::new(shell);
+        command
+            .current_dir(&self.current_dir)
+            .env("STFM_LEVEL", (level + 1).to_string())
+            .env("f", selected)
+            .env("fs", marked.join("\n"))
+            .env("fx", selection);
+        self.foreground_command = Some(ForegroundCommand {
+            command,
+            check_status: false,
+        });
+    }
+
+    /// Run the command of the '!' prompt in the background and show its output
+    pub fn run_command(&mut self) {
+        if self.command_input.trim().is_empty() {
+            self.current_screen = CurrentScreen::Main;
+            return;
+        }
+        self.pending_steps.clear();
+        self.start_shell_command(&self.command_input.clone(), true);
+    }
+
+    /// Cancel the running shell command, and the steps of the custom command waiting for it
+    pub fn cancel_command(&mut self) {
+        if let Some(command) = self.shell_command.as_mut() {
+            command.cancel();
+        }
+        self.pending_steps.clear();
+    }
+
+    /// Run `line` in the background with the selection, its output is shown when `show` is set.
+    /// Only one command runs at a time, it has to exit or be cancelled before the next one
+    fn start_shell_command(&mut self, line: &str, show: bool) {
+        if let Some(command) = self.shell_command.as_ref().filter(|command| command.is_running()) {
+            self.pending_steps.clear();
+            self.error_message = Some(format!("!{} is still running, press 'O' to see it", command.line));
+            self.current_screen = CurrentScreen::ErrorPopUp;
+            return;
+        }
+        // Members of an archive have no path the command could use
+        let (file, marked) = match self.archive {
+            Some(_) => (String::new(), Vec::new()),
+            None => (
+                self.selected_file.as_ref().map(|file| file.full_path.clone()).unwrap_or_default(),
+                self.marked.clone(),
+            ),
+        };
+        let dir = self.current_dir.to_string_lossy().to_string();
+        match ShellCommand::run(line, &dir, &file, &marked) {
+            Ok(command) => {
+                self.shell_command = Some(command);
+                if show {
+                    self.current_screen = CurrentScreen::CommandOutput;
+                }
+            }
+            Err(e) => {
+                self.pending_steps.clear();
+                self.error_message = Some(e);
+                self.current_screen = CurrentScreen::ErrorPopUp;
+            }
+        }
+    }
+
+    /// Run the steps of a custom command
+    pub fn run_custom_command(&mut self, index: usize) {
+        self.current_screen = CurrentScreen::Main;
+        self.pending_steps = self.custom_commands[index].steps.iter().cloned().collect();
+        self.run_pending_steps(true);
+    }
+
+    /// Run the waiting steps of a custom command up to its next shell command, the rest runs once it exits.
+    /// The output of the shell command is shown when `show` is set. Actions are those of the main screen
+    /// whatever screen is open by then
+    fn run_pending_steps(&mut self, show: bool) {
+        while let Some(step) = self.pending_steps.pop_front() {
+            match step {
+                Step::Action(action) => self.perform_main(action),
+                Step::Shell(line) => {
+                    self.start_shell_command(&line, show);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The keys of the current screen, None for the prompts and popups
+    pub fn context(&self) -> Option<Context> {
+        match self.current_screen {
+            CurrentScreen::Main => Some(Context::Main),
+            CurrentScreen::Preview if self.preview_table.is_some() => Some(Context::Table),
+            CurrentScreen::Preview if self.preview_sqlite.is_some() => Some(Context::Database),
+            CurrentScreen::Preview if self.tree_active() => Some(Context::Tree),
+            CurrentScreen::Preview => Some(Context::Preview),
+            CurrentScreen::Diff => Some(Context::Diff),
+            CurrentScreen::Edit => Some(Context::Edit),
+            _ => None,
+        }
+    }
+
+    /// Open the command palette over the actions of the current screen
+    pub fn open_palette(&mut self) {
+        if let Some(context) = self.context() {
+            self.palette = Some(Palette::new(self.current_screen.clone(), context, &self.custom_commands));
+            self.current_screen = CurrentScreen::Palette;
+        }
+    }
+
+    /// Close the palette, going back to the screen it was opened from
+    pub fn close_palette(&mut self) {
+        if let Some(palette) = self.palette.take() {
+            self.current_screen = palette.screen;
+        }
+    }
+
+    /// Run the entry selected in the palette from the screen it was opened from
+    pub fn run_palette(&mut self) {
+        let target = self.palette.as_ref().and_then(|palette| palette.selected());
+        self.close_palette();
+        match target {
+            Some(PaletteTarget::Action(action)) => self.perform(action),
+            Some(PaletteTarget::Custom(index)) => self.run_custom_command(index),
+            None => {}
+        }
+    }
+
+    /// Do an action in the current screen, actions the screen does not have do nothing.
+    /// Outside of the screens with keys, like after a shell command of a custom command, it is done in the main screen
+    pub fn perform(&mut self, action: Action) {
+        if action == Action::Palette {
+            self.open_palette();
+            return;
+        }
+        match self.context() {
+            Some(Context::Main) | None => self.perform_main(action),
+            Some(Context::Preview) => self.perform_preview(action),
+            Some(Context::Table) => self.perform_table(action),
+            Some(Context::Database) => self.perform_database(action),
+            Some(Context::Tree) => self.perform_tree(action),
+            Some(Context::Diff) => self.perform_diff(action),
+            Some(Context::Edit) => self.perform_edit(action),
+        }
+    }
+
+    fn perform_preview(&mut self, action: Action) {
+        match action {
+            Action::Close => self.current_screen = CurrentScreen::Main,
+            Action::Down => self.scroll_down(10),
+            Action::PageDown => self.scroll_down(50),
+            Action::Up => self.scroll_up(10),
+            Action::PageUp => self.scroll_up(50),
+            Action::Left => self.scroll_left(),
+            Action::Right => self.scroll_right(),
+            Action::First => self.scroll_top(),
+            Action::Last => self.scroll_bottom(),
+            Action::ToggleLineNumbers => self.toggle_line_numbers(),
+            Action::ToggleWrap => self.toggle_soft_wrap(),
+            Action::GoToLine => {
+                self.goto_line_input.clear();
+                self.current_screen = CurrentScreen::GoToLine;
+            }
+            Action::Search => {
+                self.preview_search_error = None;
+                self.current_screen = CurrentScreen::PreviewSearch;
+            }
+            Action::NextMatch => self.next_match(),
+            Action::PreviousMatch => self.previous_match(),
+            Action::ToggleFollow => self.toggle_follow(),
+            Action::PickEncoding => self.open_encoding_picker(),
+            Action::ToggleMarkdown => self.toggle_raw_preview(),
+            Action::ToggleTree => self.toggle_tree(),
+            _ => {}
+        }
+    }
+
+    fn perform_table(&mut self, action: Action) {
+        // The rows of a database table go back to the list of tables
+        if action == Action::Close {
+            match self.preview_sqlite.is_some() {
+                true => self.preview_table = None,
+                false => self.current_screen = CurrentScreen::Main,
+            }
+            return;
+        }
+        let table = match self.preview_table.as_mut() {
+            Some(table) => table,
+            None => return,
+        };
+        match action {
+            Action::Down => table.down(1),
+            Action::Up => table.up(1),
+            Action::PageDown => table.down(50),
+            Action::PageUp => table.up(50),
+            Action::First => table.first(),
+            Action::Last => table.last(),
+            Action::Right => table.scroll_right(),
+            Action::Left => table.scroll_left(),
+            Action::ToggleSummary => table.toggle_summary(),
+            _ => {}
+        }
+    }
+
+    fn perform_database(&mut self, action: Action) {
+        match action {
+            Action::Close => self.current_screen = CurrentScreen::Main,
+            Action::Open => self.open_sqlite_rows(),
+            _ => {
+                let browser = match self.preview_sqlite.as_mut() {
+                    Some(browser) => browser,
+                    None => return,
+                };
+                match action {
+                    Action::Down => browser.down(1),
+                    Action::Up => browser.up(1),
+                    Action::PageDown => browser.down(20),
+                    Action::PageUp => browser.up(20),
+                    Action::First => browser.first(),
+                    Action::Last => browser.last(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn perform_tree(&mut self, action: Action) {
+        match action {
+            Action::Close => self.current_screen = CurrentScreen::Main,
+            Action::ToggleTree => self.toggle_tree(),
+            _ => {
+                let tree = match self.preview_tree.as_mut() {
+                    Some(tree) => tree,
+                    None => return,
+                };
+                match action {
+                    Action::Down => tree.down(1),
+                    Action::Up => tree.up(1),
+                    Action::PageDown => tree.down(20),
+                    Action::PageUp => tree.up(20),
+                    Action::First => tree.first(),
+                    Action::Last => tree.last(),
+                    Action::ToggleNode => tree.toggle(),
+                    Action::Expand => tree.expand(),
+                    Action::Collapse => tree.collapse(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn perform_diff(&mut self, action: Action) {
+        if action == Action::Close {
+            self.diff = None;
+            self.current_screen = CurrentScreen::Main;
+            return;
+        }
+        let diff = match self.diff.as_mut() {
+            Some(diff) => diff,
+            None => return,
+        };
+        match action {
+            Action::Down => diff.down(1),
+            Action::Up => diff.up(1),
+            Action::PageDown => diff.down(diff.height.max(1)),
+            Action::PageUp => diff.up(diff.height.max(1)),
+            Action::First => diff.first(),
+            Action::Last => diff.last(),
+            Action::Right => diff.scroll_right(),
+            Action::Left => diff.scroll_left(),
+            Action::NextHunk => diff.next_hunk(),
+            Action::PreviousHunk => diff.previous_hunk(),
+            Action::ToggleLayout => diff.toggle_layout(),
+            _ => {}
+        }
+    }
+
+    fn perform_edit(&mut self, action: Action) {
+        match action {
+            Action::Close => self.close_editor(false),
+            Action::Save => {
+                self.save_editor();
+            }
+            Action::Search => self.current_screen = CurrentScreen::EditSearch,
+            _ => {
+                let editor = match self.editor.as_mut() {
+                    Some(editor) => editor,
+                    None => return,
+                };
+                match action {
+                    Action::Undo => editor.undo(),
+                    Action::FindNext => editor.find_next(),
+                    Action::First => editor.first(),
+                    Action::Last => editor.last(),
+                    Action::Left => editor.left(),
+                    Action::Right => editor.right(),
+                    Action::Up => editor.up(1),
+                    Action::Down => editor.down(1),
+                    Action::PageUp => editor.up(editor.height.max(1)),
+                    Action::PageDown => editor.down(editor.height.max(1)),
+                    Action::LineStart => editor.line_start(),
+                    Action::LineEnd => editor.line_end(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn perform_main(&mut self, action: Action) {
+        let read_only = matches!(
+            action,
+            Action::NewFile | Action::Delete | Action::Rename | Action::Paste | Action::Compress | Action::Extract
+        );
+        if read_only && self.archive.is_some() {
+            self.error_message =
+                Some("Archives are read-only, copy members out with 'c' and paste them in a directory".to_string());
+            self.current_screen = CurrentScreen::ErrorPopUp;
+            return;
+        }
+        match action {
+            // Unsaved changes of an editor left by an error are saved or discarded first
+            Action::Quit if self.editor.as_ref().is_some_and(|editor| editor.is_dirty()) => {
+                self.current_screen = CurrentScreen::ConfirmEditExit;
+            }
+            Action::Quit => self.quit = true,
+            Action::Help => {
+                self.help_scroll = 0;
+                self.current_screen = CurrentScreen::Help;
+            }
+            Action::NewFile => self.current_screen = CurrentScreen::IsNewFileADir,
+            Action::Delete | Action::Rename => match self.selected_file {
+                Some(_) => {
+                    self.current_screen = match action {
+                        Action::Delete => CurrentScreen::ConfirmDelete,
+                        _ => CurrentScreen::Rename,
+                    }
+                }
+                None => {
+                    self.error_message = Some("No file selected".to_string());
+                    self.current_screen = CurrentScreen::ErrorPopUp;
+                }
+            },
+            Action::Copy => match self.selected_file.clone() {
+                Some(file) => {
+                    // Directories can only be copied out of an archive
+                    self.copy_from_archive = self.archive_member(&file.full_path);
+                    if file.type_name == "File" || self.copy_from_archive.is_some() {
+                        self.file_to_copy = Some(file);
+                    }
+                }
+                None => {
+                    self.error_message = Some("No file selected".to_string());
+                    self.current_screen = CurrentScreen::ErrorPopUp;
+                }
+            },
+            Action::Paste => match self.file_to_copy {
+                Some(_) => self.current_screen = CurrentScreen::ConfirmCopyingPopUp,
+                None => {
+                    self.error_message = Some("No file selected".to_string());
+                    self.current_screen = CurrentScreen::ErrorPopUp;
+                }
+            },
+            Action::Search => {
+                self.search_input.clear();
+                self.current_screen = CurrentScreen::Search;
+            }
+            Action::ToggleHidden => self.toggle_hidden(),
+            Action::TogglePreviewPane => self.toggle_preview_pane(),
+            Action::ToggleMark => self.toggle_mark(),
+            Action::Compress => self.open_compress(),
+            Action::Extract => self.open_extract(),
+            Action::Diff => self.open_diff(),
+            Action::Edit => self.open_editor(),
+            Action::ExternalEditor => self.open_external(&["VISUAL", "EDITOR"], "vi"),
+            Action::Pager => self.open_external(&["PAGER"], "less"),
+            Action::OpenWith => self.open_with_menu(),
+            Action::Shell => self.open_shell(),
+            Action::CommandPrompt => {
+                self.command_input.clear();
+                self.current_screen = CurrentScreen::CommandPrompt;
+            }
+            Action::CommandOutput => match self.shell_command {
+                Some(_) => self.current_screen = CurrentScreen::CommandOutput,
+                None => {
+                    self.error_message = Some("No shell command was run yet".to_string());
+                    self.current_screen = CurrentScreen::ErrorPopUp;
+                }
+            },
+            Action::Refresh => self.refresh(),
+            Action::Up => self.up(),
+            Action::Down => self.down(),
+            Action::PageUp => self.page_up(),
+            Action::PageDown => self.page_down(),
+            Action::Open => self.handle_selected_file(),
+            Action::Back => self.previus_dir(),
+            // The palette lists the actions of the screen it opens over, a custom command only opens it from the listing
+            Action::Palette if matches!(self.current_screen, CurrentScreen::Main) => self.open_palette(),
+            _ => {}
+        }
+    }
+
+    /// Indices of the opener rules opening `file`, in the order of the configuration.
+    /// Directories and members of archives are not opened with rules
+    fn matching_openers(&self, file: &StfmFile) -> Vec<usize> {
+        if file.is_dir || self.archive.is_some() {
+            return Vec::new();
+        }
+        let path = PathBuf::from(&file.full_path);
+        let mut mime = None;
+        (0..self.openers.len())
+            .filter(|&index| self.openers[index].matches(&path, &mut mime))
+            .collect()
+    }
+
+    /// Open the selected file with an opener rule
+    pub fn run_opener(&mut self, index: usize) {
+        let file = match self.selected_file.clone() {
+            Some(file) => file,
+            None => return,
+        };
+        let selection = match self.marked.is_empty() {
+            true => vec![file.full_path.clone()],
+            false => self.marked.clone(),
+        };
+        let opener = &self.openers[index];
+        let name = opener.name.clone();
+        let mut command = opener.command(&file.full_path, &self.current_dir.to_string_lossy(), &selection);
+        self.current_screen = CurrentScreen::Main;
+        match opener.mode {
+            OpenMode::Foreground => {
+                self.foreground_command = Some(ForegroundCommand {
+                    command,
+                    check_status: true,
+                })
+            }
+            OpenMode::Background => {
+                self.jobs.push(Job::spawn(format!("Running {}", name), move |_| match command.status() {
+                    Ok(status) if status.success() => Ok(()),
+                    Ok(status) => Err(format!("{} exited with {}", name, status)),
+                    Err(e) => Err(format!("Could not run {}: {}", name, e)),
+                }));
+            }
+            OpenMode::Detach => match command.spawn() {
+                // Waited for so that it does not stay a zombie once it exits
+                Ok(mut child) => {
+                    std::thread::spawn(move || child.wait());
+                }
+                Err(e) => {
+                    self.error_message = Some(format!("Could not run {}: {}", name, e));
+                    self.current_screen = CurrentScreen::ErrorPopUp;
+                }
+            },
+        }
+    }
+
+    /// Open the "Open with" menu listing the opener rules of the selected file, and the preview
+    pub fn open_with_menu(&mut self) {
+        let file = match self.selected_file.clone() {
+            Some(file) if !file.is_dir && self.archive.is_none() => file,
+            _ => {
+                self.error_message = Some("Select a file to open it, members of archives cannot be opened".to_string());
+                self.current_screen = CurrentScreen::ErrorPopUp;
+                return;
+            }
+        };
+        let matching = self.matching_openers(&file);
+        if matching.is_empty() {
+            self.error_message = Some(format!("No opener rule of the configuration matches {}", file.name));
+            self.current_screen = CurrentScreen::ErrorPopUp;
+            return;
+        }
+        self.open_with = matching.into_iter().map(Some).collect();
+        self.open_with.push(None);
+        self.open_with_state.select(Some(0));
+        self.current_screen = CurrentScreen::OpenWith;
+    }
+
+    /// Run the entry picked in the "Open with" menu
+    pub fn choose_open_with(&mut self) {
+        let entry = self.open_with_state.selected().and_then(|index| self.open_with.get(index)).copied();
+        match entry {
+            Some(Some(index)) => self.run_opener(index),
+            Some(None) => {
+                self.current_screen = CurrentScreen::Main;
+                if let Some(file) = self.selected_file.clone() {
+                    self.preview_file(&file);
+                }
+            }
+            None => self.current_screen = CurrentScreen::Main,
+        }
+    }
+
+    /// Save the file of the editor, errors are shown in the editor
+    pub fn save_editor(&mut self) -> bool {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return false,
+        };
+        match editor.save() {
+            Ok(()) => true,
+            Err(e) => {
+                editor.message = Some(e);
+                false
+            }
+        }
+    }
+
+    /// Ask where the selected archive is extracted
+    pub fn open_extract(&mut self) {
+        let is_archive = match &self.selected_file {
+            Some(file) => !file.is_dir && ArchiveFormat::from_path(&PathBuf::from(&file.full_path)).is_some(),
+            None => false,
+        };
+        if !is_archive {
+            self.error_message = Some("Select a zip or tar archive to extract".to_string());
+            self.current_screen = CurrentScreen::ErrorPopUp;
+            return;
+        }
+        self.extract_list_state.select(Some(0));
+        self.current_screen = CurrentScreen::ExtractTarget;
+    }
+
+    /// Folder the selected archive is extracted to when it gets a folder of its own
+    pub fn extract_folder(&self) -> Option<PathBuf> {
+        self.selected_file
+            .as_ref()
+            .map(|file| self.current_dir.join(ArchiveFormat::stem(&file.name)))
+    }
+
+    /// Extract the selected archive in a background job, into the current directory or a new folder next to it
+    pub fn extract(&mut self) {
+        let file = match self.selected_file.clone() {
+            Some(file) => file,
+            None => return,
+        };
+        let path = PathBuf::from(&file.full_path);
+        let format = match ArchiveFormat::from_path(&path) {
+            Some(format) => format,
+            None => return,
+        };
+        let new_folder = self.extract_list_state.selected() == Some(1);
+        let target = match (new_folder, self.extract_folder()) {
+            (true, Some(folder)) => folder,
+            _ => self.current_dir.clone(),
+        };
+        if new_folder && target.exists() {
+            self.error_message = Some(format!("{} already exists", target.to_string_lossy()));
+            self.current_screen = CurrentScreen::ErrorPopUp;
+            return;
+        }
+        self.jobs.push(Job::spawn(format!("Extracting {}", file.name), move |progress| {
+            if new_folder {
+                std::fs::create_dir(&target).map_err(|e| e.to_string())?;
+            }
+            let mut report = |done, total| progress.report(done, total);
+            extract_archive(&path, format, &target, &mut report).map_err(|e| e.to_string())
+        }));
+        self.current_screen = CurrentScreen::Main;
+    }
+
+    /// Collect the results of the finished jobs, showing the listing again with what they changed
+    fn poll_jobs(&mut self) {
+        let mut finished = false;
+        let mut index = 0;
+        while index < self.jobs.len() {
+            match self.jobs[index].poll() {
+                Some(result) => {
+                    self.jobs.remove(index);
+                    finished = true;
+                    if let Err(e) = result {
+                        self.background_errors.push(e);
+                    }
+                }
+                None => index += 1,
+            }
+        }
+        if finished {
+            self.refresh();
+        }
+    }
+
+    /// Extract a member of an archive, and everything in it, into the current directory
+    fn copy_out_of_archive(&mut self, member: ArchiveMember) {
+        let progress_sender = match self.progress_sender.clone() {
+            Some(sender) => sender,
+            None => return,
+        };
+        let copy_error = Arc::clone(&self.copy_error);
+        let target = self.current_dir.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = extract_member(&member, &target, progress_sender) {
+                *copy_error.lock().unwrap() = Some(e.to_string());
+            }
+        });
+    }
+
     pub fn copy(&mut self){
         match self.file_to_copy.clone() {
             Some(file) => {
@@ -347,6 +2144,10 @@ impl App {
                 let (progress_sender, progress_receiver) = mpsc::channel();
                 self.progress_sender = Some(progress_sender);
                 self.progress_receiver = Some(progress_receiver);
+                if let Some(member) = self.copy_from_archive.clone() {
+                    self.copy_out_of_archive(member);
+                    return;
+                }
                 if file.is_dir {
                     self.error_message = Some("Cannot copy directories".to_string());
                     self.current_screen = CurrentScreen::ErrorPopUp;
@@ -384,3 +2185,22 @@ impl App {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_steps_in_the_main_screen() {
+        let mut app = App::new();
+        app.current_screen = CurrentScreen::Preview;
+        app.list_state.select(Some(0));
+        app.index_selected = Some(0);
+        // The steps after a shell command run when it exits, whatever screen is open by then
+        app.pending_steps = VecDeque::from([Step::Action(Action::Down)]);
+        app.run_pending_steps(false);
+        assert_eq!(app.index_selected, Some(1));
+        assert_eq!(app.vertical_scroll, 0);
+        assert!(matches!(app.current_screen, CurrentScreen::Preview));
+    }
+}
@@ -0,0 +1,1061 @@
+use crate::files::{format_size, StfmFile};
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike, Utc};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use tar::EntryType;
+use zip::ZipArchive;
+
+/// Maximum number of bytes of an archive member read into the preview
+pub const MEMBER_READ_LIMIT: u64 = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Format of the archive at `path` from the end of its name
+    pub fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_lowercase(),
+            None => return None,
+        };
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(ArchiveFormat::TarXz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else {
+            None
+        }
+    }
+}
+
+impl ArchiveFormat {
+    /// Name of an archive without the extension of its format, the name of the folder it is extracted to
+    pub fn stem(name: &str) -> String {
+        for suffix in [".tar.gz", ".tar.xz", ".tar.zst", ".tgz", ".txz", ".tzst", ".zip", ".tar"] {
+            let split = name.len().saturating_sub(suffix.len());
+            if let (Some(stem), Some(extension)) = (name.get(..split), name.get(split..)) {
+                if !stem.is_empty() && extension.eq_ignore_ascii_case(suffix) {
+                    return stem.to_string();
+                }
+            }
+        }
+        name.to_string()
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveFormat::Zip => write!(f, "zip"),
+            ArchiveFormat::Tar => write!(f, "tar"),
+            ArchiveFormat::TarGz => write!(f, "tar.gz"),
+            ArchiveFormat::TarXz => write!(f, "tar.xz"),
+            ArchiveFormat::TarZst => write!(f, "tar.zst"),
+        }
+    }
+}
+
+/// A file, directory or link stored in an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: String, // the path inside the archive, directories without the trailing slash
+    pub is_dir: bool,
+    pub link: Option<String>, // the target of a symbolic link
+    pub size: u64,
+    pub compressed_size: Option<u64>, // only zip archives compress every member on its own
+    pub mode: Option<u32>,
+    pub modified: Option<String>,
+}
+
+/// The members of an archive, read from the tar headers or the zip central directory
+#[derive(Debug, Clone)]
+pub struct ArchiveIndex {
+    pub format: ArchiveFormat,
+    pub entries: Vec<ArchiveEntry>,
+    pub archive_size: u64, // the size of the archive file
+}
+
+impl ArchiveIndex {
+    pub fn read(path: &Path, format: ArchiveFormat) -> io::Result<ArchiveIndex> {
+        let archive_size = std::fs::metadata(path)?.len();
+        let entries = match format {
+            ArchiveFormat::Zip => read_zip_entries(path)?,
+            _ => read_tar_entries(path, format)?,
+        };
+        Ok(ArchiveIndex {
+            format,
+            entries,
+            archive_size,
+        })
+    }
+
+    /// Size of all members once extracted
+    pub fn total_size(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size).sum()
+    }
+
+    /// Size of all members as stored, a tar is compressed as a whole so its file size is used
+    pub fn compressed_size(&self) -> u64 {
+        match self.format {
+            ArchiveFormat::Zip => self.entries.iter().filter_map(|entry| entry.compressed_size).sum(),
+            _ => self.archive_size,
+        }
+    }
+}
+
+/// The tar stream of an archive, decompressed while it is read
+pub fn tar_stream(path: &Path, format: ArchiveFormat) -> io::Result<Box<dyn Read + Send>> {
+    decompress(BufReader::new(File::open(path)?), format)
+}
+
+fn decompress<R: BufRead + Send + 'static>(file: R, format: ArchiveFormat) -> io::Result<Box<dyn Read + Send>> {
+    match format {
+        ArchiveFormat::Tar => Ok(Box::new(file)),
+        ArchiveFormat::TarGz => Ok(Box::new(flate2::read::GzDecoder::new(file))),
+        ArchiveFormat::TarXz => Ok(Box::new(xz2::read::XzDecoder::new(file))),
+        ArchiveFormat::TarZst => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(file)?)),
+        ArchiveFormat::Zip => Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a tar archive")),
+    }
+}
+
+/// Read the members of a tar archive, the data of each member is skipped without being stored
+fn read_tar_entries(path: &Path, format: ArchiveFormat) -> io::Result<Vec<ArchiveEntry>> {
+    let mut archive = tar::Archive::new(tar_stream(path, format)?);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let link = match entry_type {
+            EntryType::Symlink | EntryType::Link => match entry.link_name() {
+                Ok(Some(target)) => Some(target.to_string_lossy().to_string()),
+                _ => None,
+            },
+            _ => None,
+        };
+        entries.push(ArchiveEntry {
+            path: entry.path()?.to_string_lossy().trim_end_matches('/').to_string(),
+            is_dir: entry_type.is_dir(),
+            link,
+            size: entry.size(),
+            compressed_size: None,
+            mode: header.mode().ok(),
+            modified: match header.mtime() {
+                Ok(mtime) => DateTime::<Utc>::from_timestamp(mtime as i64, 0)
+                    .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string()),
+                Err(_) => None,
+            },
+        });
+    }
+    Ok(entries)
+}
+
+fn open_zip(path: &Path) -> io::Result<ZipArchive<BufReader<File>>> {
+    match ZipArchive::new(BufReader::new(File::open(path)?)) {
+        Ok(archive) => Ok(archive),
+        Err(e) => Err(zip_error(e)),
+    }
+}
+
+fn zip_error(error: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Read the members of a zip archive from its central directory, nothing is decompressed
+fn read_zip_entries(path: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let mut archive = open_zip(path)?;
+    let mut entries = Vec::new();
+    for index in 0..archive.len() {
+        let file = match archive.by_index_raw(index) {
+            Ok(file) => file,
+            Err(e) => return Err(zip_error(e)),
+        };
+        let modified = file.last_modified().and_then(|time| {
+            NaiveDate::from_ymd_opt(time.year() as i32, time.month() as u32, time.day() as u32)
+                .and_then(|date| date.and_hms_opt(time.hour() as u32, time.minute() as u32, time.second() as u32))
+                .map(|datetime| datetime.format("%Y-%m-%d %H:%M:%S").to_string())
+        });
+        entries.push(ArchiveEntry {
+            path: file.name().trim_end_matches('/').to_string(),
+            is_dir: file.is_dir(),
+            // The target of a zip symlink is its content, which would have to be decompressed
+            link: match file.is_symlink() {
+                true => Some(String::new()),
+                false => None,
+            },
+            size: file.size(),
+            compressed_size: Some(file.compressed_size()),
+            mode: file.unix_mode(),
+            modified,
+        });
+    }
+    Ok(entries)
+}
+
+/// Permissions in the `drwxr-xr-x` form of `ls -l`
+pub fn mode_string(entry: &ArchiveEntry) -> String {
+    let kind = if entry.is_dir {
+        'd'
+    } else if entry.link.is_some() {
+        'l'
+    } else {
+        '-'
+    };
+    let mode = match entry.mode {
+        Some(mode) => mode,
+        None => return format!("{}?????????", kind),
+    };
+    let mut text = String::from(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        text.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    text
+}
+
+/// Listing of the members of an archive with a line of totals at the end
+pub fn listing(index: &ArchiveIndex) -> Text<'static> {
+    let mut lines = Vec::new();
+    let dim = Style::default().fg(Color::DarkGray);
+    for entry in index.entries.iter() {
+        let mut spans = vec![
+            Span::styled(format!("{} ", mode_string(entry)), dim),
+            Span::raw(format!("{:>9} ", format_size(entry.size))),
+        ];
+        if index.format == ArchiveFormat::Zip {
+            let compressed = format_size(entry.compressed_size.unwrap_or_default());
+            spans.push(Span::styled(format!("{:>9} ", compressed), dim));
+        }
+        spans.push(Span::styled(
+            format!("{:<19} ", entry.modified.clone().unwrap_or_default()),
+            dim,
+        ));
+        let name_style = if entry.is_dir {
+            Style::default().fg(Color::Cyan)
+        } else if entry.link.is_some() {
+            Style::default().fg(Color::Magenta)
+        } else {
+            Style::default().fg(Color::Green)
+        };
+        match &entry.link {
+            Some(target) if !target.is_empty() => {
+                spans.push(Span::styled(entry.path.clone(), name_style));
+                spans.push(Span::styled(format!(" -> {}", target), dim));
+            }
+            _ => spans.push(Span::styled(entry.path.clone(), name_style)),
+        }
+        lines.push(Line::from(spans));
+    }
+    if index.entries.is_empty() {
+        lines.push(Line::styled("Empty archive", dim));
+    }
+
+    let files = index.entries.iter().filter(|entry| !entry.is_dir).count();
+    let total = index.total_size();
+    let compressed = index.compressed_size();
+    let ratio = match total {
+        0 => 0.0,
+        _ => compressed as f64 * 100.0 / total as f64,
+    };
+    lines.push(Line::default());
+    lines.push(Line::styled(
+        format!(
+            "{} entries, {} files | {} uncompressed | {} compressed ({:.1}%)",
+            index.entries.len(),
+            files,
+            format_size(total),
+            format_size(compressed),
+            ratio
+        ),
+        Style::default().add_modifier(Modifier::BOLD),
+    ));
+    Text::from(lines)
+}
+
+/// An archive being browsed as if it was a directory
+#[derive(Debug, Clone)]
+pub struct ArchiveBrowser {
+    pub path: PathBuf, // the archive file
+    pub index: Arc<ArchiveIndex>,
+    pub dir: String, // the directory inside the archive that is listed, empty at its root
+}
+
+/// A file or directory inside an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveMember {
+    pub archive: PathBuf,
+    pub format: ArchiveFormat,
+    pub path: String,
+}
+
+impl ArchiveBrowser {
+    pub fn new(path: PathBuf, index: ArchiveIndex) -> ArchiveBrowser {
+        ArchiveBrowser {
+            path,
+            index: Arc::new(index),
+            dir: String::new(),
+        }
+    }
+
+    /// Where the listed directory is, shown as if the archive was a directory
+    pub fn location(&self) -> PathBuf {
+        self.path.join(&self.dir)
+    }
+
+    /// The member shown in the file list as `full_path`
+    pub fn member(&self, full_path: &str) -> Option<ArchiveMember> {
+        match Path::new(full_path).strip_prefix(&self.path) {
+            Ok(path) if !path.as_os_str().is_empty() => Some(ArchiveMember {
+                archive: self.path.clone(),
+                format: self.index.format,
+                path: path.to_string_lossy().to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Entries of the directory `dir` of the archive, directories without an entry of their own
+    /// are made up from the paths of their members
+    pub fn list_files(&self, dir: &str, show_hidden: bool) -> Vec<StfmFile> {
+        let prefix = match dir.is_empty() {
+            true => String::new(),
+            false => format!("{}/", dir),
+        };
+        let mut files: Vec<StfmFile> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for entry in self.index.entries.iter() {
+            let rest = match entry.path.strip_prefix(&prefix) {
+                Some(rest) if !rest.is_empty() => rest,
+                _ => continue,
+            };
+            let (name, nested) = match rest.split_once('/') {
+                Some((name, _)) => (name, true),
+                None => (rest, false),
+            };
+            if !show_hidden && name.starts_with('.') {
+                continue;
+            }
+            let position = match positions.get(name) {
+                Some(position) => *position,
+                None => {
+                    let is_dir = nested || entry.is_dir;
+                    let extension = if is_dir {
+                        "DIR".to_string()
+                    } else if entry.link.is_some() {
+                        "LINK".to_string()
+                    } else {
+                        match Path::new(name).extension() {
+                            Some(extension) => extension.to_string_lossy().to_string(),
+                            None => "FILE".to_string(),
+                        }
+                    };
+                    files.push(StfmFile {
+                        full_path: self.path.join(format!("{}{}", prefix, name)).to_string_lossy().to_string(),
+                        name: name.to_string(),
+                        extension,
+                        is_dir,
+                        type_name: match (is_dir, entry.link.is_some()) {
+                            (true, _) => "Directory".to_string(),
+                            (false, true) => "Link".to_string(),
+                            (false, false) => "File".to_string(),
+                        },
+                        size: 0,
+                        modified: "Unknown".to_string(),
+                    });
+                    positions.insert(name.to_string(), files.len() - 1);
+                    files.len() - 1
+                }
+            };
+            // The size of a directory is the size of everything in it, the bytes a copy of it writes
+            let file = &mut files[position];
+            file.size += entry.size;
+            if let (false, Some(modified)) = (nested, &entry.modified) {
+                file.modified = modified.clone();
+            }
+        }
+        files
+    }
+}
+
+/// Read up to `limit` bytes of a member, decompressing the archive up to it
+pub fn read_member(member: &ArchiveMember, limit: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match member.format {
+        ArchiveFormat::Zip => {
+            let mut archive = open_zip(&member.archive)?;
+            let file = match archive.by_name(&member.path) {
+                Ok(file) => file,
+                Err(e) => return Err(zip_error(e)),
+            };
+            file.take(limit).read_to_end(&mut bytes)?;
+        }
+        format => {
+            let mut archive = tar::Archive::new(tar_stream(&member.archive, format)?);
+            for entry in archive.entries()? {
+                let entry = entry?;
+                if entry.path()?.to_string_lossy().trim_end_matches('/') == member.path {
+                    entry.take(limit).read_to_end(&mut bytes)?;
+                    return Ok(bytes);
+                }
+            }
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in the archive", member.path)));
+        }
+    }
+    Ok(bytes)
+}
+
+/// Where an entry of the archive at `entry_path` goes when `member` is copied, None if it is not part of it
+fn extracted_path(member: &ArchiveMember, entry_path: &str) -> Option<String> {
+    let name = match member.path.rsplit_once('/') {
+        Some((_, name)) => name,
+        None => member.path.as_str(),
+    };
+    if entry_path == member.path {
+        return Some(name.to_string());
+    }
+    entry_path
+        .strip_prefix(&format!("{}/", member.path))
+        .map(|rest| format!("{}/{}", name, rest))
+}
+
+/// Join a path read from an archive to `target`, refusing paths that would end up outside of it
+pub fn safe_join(target: &Path, path: &str) -> io::Result<PathBuf> {
+    let mut joined = target.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => joined.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Refusing to extract {}, it points outside of the target directory", path),
+                ))
+            }
+        }
+    }
+    Ok(joined)
+}
+
+/// If a symbolic link at `path` pointing to `link` could resolve to something outside of the extracted files.
+/// A `..` after a name is refused: the name can be a link extracted before or after this one and its `..`
+/// then goes up from wherever that link points, only `..` at the start of the link are followed
+fn link_escapes(path: &str, link: &str) -> bool {
+    if Path::new(link).is_absolute() {
+        return true;
+    }
+    let mut depth = Path::new(path)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count()
+        .saturating_sub(1);
+    let mut named = false;
+    for component in Path::new(link).components() {
+        match component {
+            Component::Normal(_) => named = true,
+            Component::CurDir => {}
+            Component::ParentDir => match (named, depth) {
+                (true, _) | (false, 0) => return true,
+                _ => depth -= 1,
+            },
+            _ => return true,
+        }
+    }
+    false
+}
+
+/// Copies entries out of an archive, reporting the number of bytes written so far
+struct Extractor<'a> {
+    target: &'a Path,
+    written: u64,
+    progress: &'a mut dyn FnMut(u64) -> io::Result<()>,
+}
+
+impl Extractor<'_> {
+    /// Where an entry is written, refusing to write through a symbolic link that is already there
+    /// as it could point anywhere
+    fn destination(&self, path: &str) -> io::Result<PathBuf> {
+        let destination = safe_join(self.target, path)?;
+        let mut parent = self.target.to_path_buf();
+        let components: Vec<Component> = Path::new(path).components().collect();
+        for component in components.iter().take(components.len().saturating_sub(1)) {
+            parent.push(component);
+            if let Ok(metadata) = std::fs::symlink_metadata(&parent) {
+                if metadata.file_type().is_symlink() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Refusing to extract {}, {} is a symbolic link", path, parent.to_string_lossy()),
+                    ));
+                }
+            }
+        }
+        Ok(destination)
+    }
+
+    fn directory(&mut self, path: &str) -> io::Result<()> {
+        std::fs::create_dir_all(self.destination(path)?)
+    }
+
+    /// Make room for an entry written at `destination`: a file or a link already there is removed rather than
+    /// written through, a link could point anywhere
+    fn replace(destination: &Path) -> io::Result<()> {
+        match std::fs::symlink_metadata(destination) {
+            Ok(metadata) if !metadata.is_dir() => std::fs::remove_file(destination),
+            _ => Ok(()),
+        }
+    }
+
+    fn file(&mut self, path: &str, mode: Option<u32>, reader: &mut dyn Read) -> io::Result<()> {
+        let destination = self.destination(path)?;
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Extractor::replace(&destination)?;
+        // Never follows a link created at the same path in the meantime, the file has to be new
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&destination)?;
+        let mut buffer = [0; 128 * 1024];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buffer[..read])?;
+            self.written += read as u64;
+            (self.progress)(self.written)?;
+        }
+        file.flush()?;
+        set_mode(&file, mode)
+    }
+
+    fn symlink(&mut self, path: &str, link: &str) -> io::Result<()> {
+        if link_escapes(path, link) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Refusing to extract {}, its link {} points outside of the target directory", path, link),
+            ));
+        }
+        let destination = self.destination(path)?;
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Extractor::replace(&destination)?;
+        make_symlink(link, &destination)
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        // Only the permission bits, setuid and friends are not kept
+        Some(mode) => file.set_permissions(std::fs::Permissions::from_mode(mode & 0o777)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_symlink(link: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link, path)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_link: &str, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Extract the entries of an archive into `target`. `select` gives the path an entry is extracted to
+/// or None to skip it, `progress` gets the number of bytes written so far
+fn extract_entries(
+    archive_path: &Path,
+    format: ArchiveFormat,
+    tar: Box<dyn Read + Send>,
+    target: &Path,
+    select: &dyn Fn(&str) -> Option<String>,
+    progress: &mut dyn FnMut(u64) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut extractor = Extractor {
+        target,
+        written: 0,
+        progress,
+    };
+    match format {
+        ArchiveFormat::Zip => {
+            let mut archive = open_zip(archive_path)?;
+            for index in 0..archive.len() {
+                let mut file = match archive.by_index(index) {
+                    Ok(file) => file,
+                    Err(e) => return Err(zip_error(e)),
+                };
+                let path = match select(file.name().trim_end_matches('/')) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                if file.is_dir() {
+                    extractor.directory(&path)?;
+                } else if file.is_symlink() {
+                    let mut link = String::new();
+                    file.read_to_string(&mut link)?;
+                    extractor.symlink(&path, &link)?;
+                } else {
+                    let mode = file.unix_mode();
+                    extractor.file(&path, mode, &mut file)?;
+                }
+            }
+        }
+        _ => {
+            let mut archive = tar::Archive::new(tar);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let entry_path = entry.path()?.to_string_lossy().trim_end_matches('/').to_string();
+                let path = match select(&entry_path) {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let entry_type = entry.header().entry_type();
+                if entry_type.is_dir() {
+                    extractor.directory(&path)?;
+                } else if entry_type.is_symlink() {
+                    let link = match entry.link_name()? {
+                        Some(link) => link.to_string_lossy().to_string(),
+                        None => continue,
+                    };
+                    extractor.symlink(&path, &link)?;
+                } else if entry_type.is_file() || entry_type.is_gnu_sparse() {
+                    let mode = entry.header().mode().ok();
+                    extractor.file(&path, mode, &mut entry)?;
+                }
+                // Hard links, devices and fifos are skipped
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy a member of an archive, and everything in it when it is a directory, into `target`
+pub fn extract_member(member: &ArchiveMember, target: &Path, progress: mpsc::Sender<u64>) -> io::Result<()> {
+    let tar = match member.format {
+        ArchiveFormat::Zip => Box::new(io::empty()),
+        format => tar_stream(&member.archive, format)?,
+    };
+    let select = |entry_path: &str| extracted_path(member, entry_path);
+    let mut report = |written: u64| match progress.send(written) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(io::Error::new(io::ErrorKind::Interrupted, "Extraction cancelled")),
+    };
+    extract_entries(&member.archive, member.format, tar, target, &select, &mut report)
+}
+
+/// Counts the bytes read from a file, to report how far the decompression of a tar archive got
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Extract a whole archive into `target`. `progress` gets the number of bytes done and the total,
+/// the uncompressed size for a zip archive and the size of the archive file for a tar
+pub fn extract_archive(
+    path: &Path,
+    format: ArchiveFormat,
+    target: &Path,
+    progress: &mut dyn FnMut(u64, u64) -> io::Result<()>,
+) -> io::Result<()> {
+    let select = |entry_path: &str| match entry_path.is_empty() || entry_path == "." {
+        true => None,
+        false => Some(entry_path.to_string()),
+    };
+    match format {
+        ArchiveFormat::Zip => {
+            let index = ArchiveIndex::read(path, format)?;
+            // The target of a link is stored as its content but it is not written as a file
+            let total = index
+                .entries
+                .iter()
+                .filter(|entry| entry.link.is_none())
+                .map(|entry| entry.size)
+                .sum();
+            let mut report = |written: u64| progress(written, total);
+            extract_entries(path, format, Box::new(io::empty()), target, &select, &mut report)
+        }
+        _ => {
+            let total = std::fs::metadata(path)?.len();
+            let count = Arc::new(AtomicU64::new(0));
+            let reader = CountingReader {
+                inner: File::open(path)?,
+                count: Arc::clone(&count),
+            };
+            let tar = decompress(BufReader::new(reader), format)?;
+            let mut report = |_written: u64| progress(count.load(Ordering::Relaxed), total);
+            extract_entries(path, format, tar, target, &select, &mut report)
+        }
+    }
+}
+
+/// Formats archives can be created in
+pub const CREATE_FORMATS: [ArchiveFormat; 3] = [ArchiveFormat::Zip, ArchiveFormat::TarGz, ArchiveFormat::TarZst];
+
+/// A file, directory or link to be added to a new archive
+struct SourceEntry {
+    path: PathBuf,
+    name: String, // the path inside the archive
+    metadata: std::fs::Metadata,
+}
+
+/// Everything under `sources`, with the names they get in the archive, links are not followed
+fn collect_sources(sources: &[PathBuf], skip: &Path) -> io::Result<Vec<SourceEntry>> {
+    let mut entries = Vec::new();
+    let mut pending: Vec<(PathBuf, String)> = Vec::new();
+    for source in sources.iter().rev() {
+        let name = match source.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        pending.push((source.clone(), name));
+    }
+    while let Some((path, name)) = pending.pop() {
+        // The archive being written can be in one of the directories added to it
+        if path == skip {
+            continue;
+        }
+        let metadata = std::fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            let mut children: Vec<(PathBuf, String)> = Vec::new();
+            for child in std::fs::read_dir(&path)? {
+                let child = child?;
+                let child_name = format!("{}/{}", name, child.file_name().to_string_lossy());
+                children.push((child.path(), child_name));
+            }
+            children.sort_by(|a, b| b.1.cmp(&a.1));
+            pending.extend(children);
+        }
+        entries.push(SourceEntry { path, name, metadata });
+    }
+    Ok(entries)
+}
+
+/// Reports the bytes read from the files added to an archive
+struct ProgressReader<'a, R> {
+    inner: R,
+    done: &'a mut u64,
+    total: u64,
+    progress: &'a mut dyn FnMut(u64, u64) -> io::Result<()>,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        *self.done += read as u64;
+        (self.progress)(*self.done, self.total)?;
+        Ok(read)
+    }
+}
+
+/// Create an archive at `destination` with `sources` and everything in them. `progress` gets the number
+/// of bytes added so far and the total, a partly written archive is removed when it fails
+pub fn create_archive(
+    sources: &[PathBuf],
+    destination: &Path,
+    format: ArchiveFormat,
+    progress: &mut dyn FnMut(u64, u64) -> io::Result<()>,
+) -> io::Result<()> {
+    if destination.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", destination.to_string_lossy()),
+        ));
+    }
+    let entries = collect_sources(sources, destination)?;
+    let total = entries
+        .iter()
+        .filter(|entry| entry.metadata.is_file())
+        .map(|entry| entry.metadata.len())
+        .sum();
+    let file = File::create(destination)?;
+    let result = match format {
+        ArchiveFormat::Zip => write_zip(file, &entries, total, progress),
+        ArchiveFormat::TarGz => {
+            let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            write_tar(encoder, &entries, total, progress).and_then(|encoder| encoder.finish().map(|_| ()))
+        }
+        ArchiveFormat::TarZst => {
+            let encoder = zstd::stream::write::Encoder::new(file, 0)?;
+            write_tar(encoder, &entries, total, progress).and_then(|encoder| encoder.finish().map(|_| ()))
+        }
+        ArchiveFormat::Tar => write_tar(file, &entries, total, progress).map(|_| ()),
+        ArchiveFormat::TarXz => {
+            let encoder = xz2::write::XzEncoder::new(file, 6);
+            write_tar(encoder, &entries, total, progress).and_then(|encoder| encoder.finish().map(|_| ()))
+        }
+    };
+    if result.is_err() {
+        let _ = std::fs::remove_file(destination);
+    }
+    result
+}
+
+fn write_tar<W: Write>(
+    writer: W,
+    entries: &[SourceEntry],
+    total: u64,
+    progress: &mut dyn FnMut(u64, u64) -> io::Result<()>,
+) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    let mut done = 0;
+    for entry in entries.iter() {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&entry.metadata);
+        if entry.metadata.file_type().is_symlink() {
+            let link = std::fs::read_link(&entry.path)?;
+            header.set_size(0);
+            builder.append_link(&mut header, &entry.name, link)?;
+        } else if entry.metadata.is_dir() {
+            header.set_size(0);
+            builder.append_data(&mut header, &entry.name, io::empty())?;
+        } else if entry.metadata.is_file() {
+            let reader = ProgressReader {
+                inner: File::open(&entry.path)?,
+                done: &mut done,
+                total,
+                progress: &mut *progress,
+            };
+            builder.append_data(&mut header, &entry.name, reader)?;
+        }
+        // Sockets, fifos and devices are left out
+    }
+    builder.into_inner()
+}
+
+fn write_zip(
+    file: File,
+    entries: &[SourceEntry],
+    total: u64,
+    progress: &mut dyn FnMut(u64, u64) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut writer = zip::ZipWriter::new(file);
+    let mut done = 0;
+    for entry in entries.iter() {
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(entry.metadata.len() >= u32::MAX as u64);
+        if let Some(mode) = unix_mode(&entry.metadata) {
+            options = options.unix_permissions(mode);
+        }
+        if let Some(modified) = zip_time(&entry.metadata) {
+            options = options.last_modified_time(modified);
+        }
+        let result = if entry.metadata.file_type().is_symlink() {
+            let link = std::fs::read_link(&entry.path)?;
+            writer.add_symlink(entry.name.clone(), link.to_string_lossy(), options)
+        } else if entry.metadata.is_dir() {
+            writer.add_directory(entry.name.clone(), options)
+        } else if entry.metadata.is_file() {
+            match writer.start_file(entry.name.clone(), options) {
+                Ok(_) => {
+                    let mut reader = ProgressReader {
+                        inner: File::open(&entry.path)?,
+                        done: &mut done,
+                        total,
+                        progress: &mut *progress,
+                    };
+                    io::copy(&mut reader, &mut writer)?;
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            return Err(zip_error(e));
+        }
+    }
+    match writer.finish() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(zip_error(e)),
+    }
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Modification time of a file in the local time zip archives use
+fn zip_time(metadata: &std::fs::Metadata) -> Option<zip::DateTime> {
+    let modified: chrono::DateTime<Local> = metadata.modified().ok()?.into();
+    zip::DateTime::from_date_and_time(
+        modified.year() as u16,
+        modified.month() as u8,
+        modified.day() as u8,
+        modified.hour() as u8,
+        modified.minute() as u8,
+        modified.second() as u8,
+    )
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for a test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stfm-archive-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        dir
+    }
+
+    /// Write a tar archive in `dir`, the names and links are written as they are without being checked
+    fn write_tar(dir: &Path, entries: &[(&str, EntryType, &str)]) -> PathBuf {
+        let path = dir.join("archive.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (name, entry_type, content) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            let data = match entry_type {
+                EntryType::Symlink => {
+                    header.as_old_mut().linkname[..content.len()].copy_from_slice(content.as_bytes());
+                    &[][..]
+                }
+                _ => content.as_bytes(),
+            };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    fn extract(dir: &Path, entries: &[(&str, EntryType, &str)]) -> io::Result<()> {
+        let archive = write_tar(dir, entries);
+        extract_archive(&archive, ArchiveFormat::Tar, &dir.join("target"), &mut |_, _| Ok(()))
+    }
+
+    #[test]
+    fn refuses_parent_paths() {
+        let dir = scratch_dir("parent");
+        assert!(extract(&dir, &[("../victim", EntryType::Regular, "owned")]).is_err());
+        assert!(!dir.join("victim").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_absolute_paths() {
+        let dir = scratch_dir("absolute");
+        let victim = dir.join("victim");
+        let name = victim.to_string_lossy().to_string();
+        assert!(extract(&dir, &[(&name, EntryType::Regular, "owned")]).is_err());
+        assert!(!victim.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_links_pointing_outside() {
+        let dir = scratch_dir("outside");
+        assert!(extract(&dir, &[("up", EntryType::Symlink, "../victim")]).is_err());
+        assert!(extract(&dir, &[("abs", EntryType::Symlink, "/etc/passwd")]).is_err());
+        assert!(std::fs::symlink_metadata(dir.join("target/up")).is_err());
+        assert!(std::fs::symlink_metadata(dir.join("target/abs")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_chained_links() {
+        let dir = scratch_dir("chained");
+        let entries = [
+            ("x", EntryType::Symlink, "."),
+            ("z", EntryType::Symlink, "x/../victim"),
+            ("z", EntryType::Regular, "owned"),
+        ];
+        assert!(extract(&dir, &entries).is_err());
+        assert!(!dir.join("victim").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_links_already_there() {
+        let dir = scratch_dir("replace");
+        make_symlink("../victim", &dir.join("target/z")).unwrap();
+        extract(&dir, &[("z", EntryType::Regular, "data")]).unwrap();
+        assert!(!dir.join("victim").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("target/z")).unwrap(), "data");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extracts_links_inside() {
+        let dir = scratch_dir("inside");
+        let entries = [
+            ("a.txt", EntryType::Regular, "hello"),
+            ("d/link", EntryType::Symlink, "../a.txt"),
+            ("d/here", EntryType::Symlink, "./link"),
+        ];
+        extract(&dir, &entries).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("target/d/here")).unwrap(), "hello");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_chained_links_in_members() {
+        let dir = scratch_dir("member");
+        let archive = write_tar(
+            &dir,
+            &[
+                ("d/x", EntryType::Symlink, "."),
+                ("d/z", EntryType::Symlink, "x/../../victim"),
+                ("d/z", EntryType::Regular, "owned"),
+            ],
+        );
+        let member = ArchiveMember {
+            archive,
+            format: ArchiveFormat::Tar,
+            path: "d".to_string(),
+        };
+        let (sender, _receiver) = mpsc::channel();
+        assert!(extract_member(&member, &dir.join("target"), sender).is_err());
+        assert!(!dir.join("victim").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
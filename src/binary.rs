@@ -0,0 +1,251 @@
+use crate::files::format_size;
+use goblin::elf::header::{et_to_str, machine_to_str, EM_NONE};
+use goblin::elf::section_header::{sht_to_str, SHN_UNDEF};
+use goblin::elf::sym::{STB_GLOBAL, STB_WEAK};
+use goblin::elf::Elf;
+use goblin::pe::PE;
+use goblin::pe::header::machine_to_str as pe_machine_to_str;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Executable formats summarized in the preview
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryFormat {
+    Elf,
+    Pe,
+}
+
+impl BinaryFormat {
+    /// Detect the format from the magic bytes at the start of the file. "MZ" starts many text files too,
+    /// a PE binary also has the PE signature where its DOS header points to
+    pub fn from_path(path: &Path) -> Option<BinaryFormat> {
+        let mut file = File::open(path).ok()?;
+        let mut header = Vec::new();
+        (&mut file).take(0x40).read_to_end(&mut header).ok()?;
+        if header.starts_with(b"\x7fELF") {
+            return Some(BinaryFormat::Elf);
+        }
+        if !header.starts_with(b"MZ") {
+            return None;
+        }
+        let pe_offset = u32::from_le_bytes(header.get(0x3C..0x40)?.try_into().ok()?);
+        let mut signature = [0; 4];
+        file.seek(SeekFrom::Start(pe_offset as u64)).ok()?;
+        file.read_exact(&mut signature).ok()?;
+        match &signature {
+            b"PE\0\0" => Some(BinaryFormat::Pe),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BinaryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BinaryFormat::Elf => "ELF",
+            BinaryFormat::Pe => "PE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A section of a binary as listed in the summary
+struct Section {
+    name: String,
+    kind: String,
+    size: u64,
+    address: u64,
+}
+
+/// What the summary shows of a binary, gathered from the format specific headers
+struct Summary {
+    format: String,
+    kind: String,
+    architecture: String,
+    entry: String,
+    interpreter: Option<String>,
+    name: Option<String>, // the soname of a shared object or the name of a DLL
+    stripped: bool,
+    exported: usize,
+    libraries: Vec<String>,
+    sections: Vec<Section>,
+}
+
+/// Parse the executable or shared object at `path` and describe it
+pub fn summary(path: &Path) -> Result<Text<'static>, String> {
+    let format = match BinaryFormat::from_path(path) {
+        Some(format) => format,
+        None => return Err("Not an ELF or PE binary".to_string()),
+    };
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let summary = match format {
+        BinaryFormat::Elf => elf_summary(&bytes)?,
+        BinaryFormat::Pe => pe_summary(&bytes)?,
+    };
+    Ok(render(&summary, bytes.len() as u64))
+}
+
+fn elf_summary(bytes: &[u8]) -> Result<Summary, String> {
+    let elf = Elf::parse(bytes).map_err(|e| format!("Invalid ELF binary: {}", e))?;
+    let architecture = match elf.header.e_machine {
+        EM_NONE => "none".to_string(),
+        machine => machine_to_str(machine).to_lowercase(),
+    };
+    let endianness = match elf.little_endian {
+        true => "little endian",
+        false => "big endian",
+    };
+    let bits = match elf.is_64 {
+        true => 64,
+        false => 32,
+    };
+    let sections = elf
+        .section_headers
+        .iter()
+        .filter(|header| header.sh_name != 0)
+        .map(|header| Section {
+            name: elf.shdr_strtab.get_at(header.sh_name).unwrap_or("?").to_string(),
+            kind: sht_to_str(header.sh_type).trim_start_matches("SHT_").to_lowercase(),
+            size: header.sh_size,
+            address: header.sh_addr,
+        })
+        .collect::<Vec<Section>>();
+    // Exported symbols are the global and weak ones of the dynamic table that are defined in this binary
+    let exported = elf
+        .dynsyms
+        .iter()
+        .filter(|sym| sym.st_shndx != SHN_UNDEF as usize)
+        .filter(|sym| sym.st_bind() == STB_GLOBAL || sym.st_bind() == STB_WEAK)
+        .count();
+    Ok(Summary {
+        format: format!("ELF{} {}", bits, endianness),
+        kind: et_to_str(elf.header.e_type).trim_start_matches("ET_").to_lowercase(),
+        architecture,
+        entry: format!("{:#x}", elf.entry),
+        interpreter: elf.interpreter.map(|interpreter| interpreter.to_string()),
+        name: elf.soname.map(|soname| soname.to_string()),
+        stripped: !sections.iter().any(|section| section.name == ".symtab"),
+        exported,
+        libraries: elf.libraries.iter().map(|library| library.to_string()).collect(),
+        sections,
+    })
+}
+
+fn pe_summary(bytes: &[u8]) -> Result<Summary, String> {
+    let pe = PE::parse(bytes).map_err(|e| format!("Invalid PE binary: {}", e))?;
+    let sections = pe
+        .sections
+        .iter()
+        .map(|section| Section {
+            name: section.name().unwrap_or("?").to_string(),
+            kind: section_kind(section.characteristics).to_string(),
+            size: section.size_of_raw_data as u64,
+            address: section.virtual_address as u64,
+        })
+        .collect();
+    let kind = match pe.is_lib {
+        true => "dll",
+        false => "exec",
+    };
+    let bits = match pe.is_64 {
+        true => "PE32+",
+        false => "PE32",
+    };
+    Ok(Summary {
+        format: bits.to_string(),
+        kind: kind.to_string(),
+        architecture: pe_machine_to_str(pe.header.coff_header.machine).to_lowercase(),
+        entry: format!("{:#x}", pe.image_base + pe.entry),
+        interpreter: None,
+        name: pe.name.map(|name| name.to_string()),
+        // Without debug information or COFF symbols there is nothing left to map addresses to names
+        stripped: pe.debug_data.is_none() && pe.header.coff_header.number_of_symbol_table == 0,
+        exported: pe.exports.len(),
+        libraries: pe.libraries.iter().map(|library| library.to_string()).collect(),
+        sections,
+    })
+}
+
+/// Short description of what a PE section holds, from its characteristics flags
+fn section_kind(characteristics: u32) -> &'static str {
+    use goblin::pe::section_table::{
+        IMAGE_SCN_CNT_CODE, IMAGE_SCN_CNT_INITIALIZED_DATA, IMAGE_SCN_CNT_UNINITIALIZED_DATA,
+    };
+    if characteristics & IMAGE_SCN_CNT_CODE != 0 {
+        "code"
+    } else if characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0 {
+        "bss"
+    } else if characteristics & IMAGE_SCN_CNT_INITIALIZED_DATA != 0 {
+        "data"
+    } else {
+        "other"
+    }
+}
+
+fn render(summary: &Summary, file_size: u64) -> Text<'static> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let heading = Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<14}", name), dim),
+            Span::raw(value),
+        ])
+    };
+
+    let mut lines = vec![
+        field("Format", summary.format.clone()),
+        field("Type", summary.kind.clone()),
+        field("Architecture", summary.architecture.clone()),
+        field("Entry point", summary.entry.clone()),
+        field("Size", format_size(file_size)),
+    ];
+    if let Some(interpreter) = &summary.interpreter {
+        lines.push(field("Interpreter", interpreter.clone()));
+    }
+    if let Some(name) = &summary.name {
+        lines.push(field("Name", name.clone()));
+    }
+    let stripped = match summary.stripped {
+        true => "yes",
+        false => "no",
+    };
+    lines.push(field("Stripped", stripped.to_string()));
+    lines.push(field("Exported", format!("{} symbols", summary.exported)));
+
+    lines.push(Line::default());
+    lines.push(Line::styled(format!("Dependencies ({})", summary.libraries.len()), heading));
+    if summary.libraries.is_empty() {
+        lines.push(Line::styled("  none", dim));
+    }
+    for library in summary.libraries.iter() {
+        lines.push(Line::styled(format!("  {}", library), Style::default().fg(Color::Green)));
+    }
+
+    lines.push(Line::default());
+    lines.push(Line::styled(format!("Sections ({})", summary.sections.len()), heading));
+    let name_width = summary
+        .sections
+        .iter()
+        .map(|section| section.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let kind_width = summary
+        .sections
+        .iter()
+        .map(|section| section.kind.chars().count())
+        .max()
+        .unwrap_or(0);
+    for section in summary.sections.iter() {
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {:<width$} ", section.name, width = name_width), Style::default().fg(Color::Yellow)),
+            Span::styled(format!("{:<width$} ", section.kind, width = kind_width), dim),
+            Span::raw(format!("{:>9} ", format_size(section.size))),
+            Span::styled(format!("{:#x}", section.address), dim),
+        ]));
+    }
+    Text::from(lines)
+}
@@ -0,0 +1,88 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Settings read from the configuration file, every section is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub previewer: Option<PreviewerConfig>,
+    #[serde(rename = "opener")]
+    pub openers: Vec<OpenerConfig>, // the [[opener]] rules, the first matching rule opens a file with 'Enter'
+    #[serde(rename = "command")]
+    pub commands: Vec<CommandConfig>, // the [[command]] custom commands
+}
+
+/// An external command that builds the preview of some files, like the previewer of lf or ranger
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PreviewerConfig {
+    pub command: String, // run with the path of the file and the width and height of the preview
+    #[serde(default = "default_previewer_timeout")]
+    pub timeout: u64, // in milliseconds
+    #[serde(default)]
+    pub extensions: Vec<String>, // extensions of the files shown with the previewer
+    #[serde(default)]
+    pub mime_types: Vec<String>, // MIME types of the files shown with the previewer, like "image/*"
+}
+
+/// A command opening the files matching some globs or MIME types, like a picture viewer for "*.png"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpenerConfig {
+    pub name: Option<String>, // shown in the "Open with" menu instead of the command
+    pub command: String, // %f, %d and %s are replaced by the file, the current directory and the selection
+    #[serde(default)]
+    pub globs: Vec<String>, // matched against the name of the file, or its path when they contain a /
+    #[serde(default)]
+    pub mime_types: Vec<String>, // like "image/*"
+    #[serde(default)]
+    pub foreground: bool, // the command takes over the terminal until it exits, like an editor
+    #[serde(default)]
+    pub detach: bool, // the command is started and forgotten, it keeps running after stfm exits
+}
+
+/// A named command run from its key or from the commands menu
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandConfig {
+    pub name: String,
+    pub key: Option<String>, // like "F", "ctrl-f" or "f5", it takes over a built-in key of the main screen
+    pub run: Vec<String>, // the names of built-in actions, or shell commands starting with !
+}
+
+fn default_previewer_timeout() -> u64 {
+    2000
+}
+
+impl Config {
+    /// Where the configuration file is read from: $XDG_CONFIG_HOME/stfm/config.toml or ~/.config/stfm/config.toml
+    pub fn path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(base) if !base.is_empty() => PathBuf::from(base),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("stfm").join("config.toml"))
+    }
+
+    /// Read the configuration file, a missing file gives the default configuration
+    pub fn load() -> Result<Config, String> {
+        let path = match Config::path() {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        };
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        toml::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e.message()))
+    }
+}
+
+/// Expand a leading ~ to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
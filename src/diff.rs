@@ -0,0 +1,456 @@
+use crate::encoding::decode;
+use crate::preview::{line_at, line_starts};
+use similar::{Algorithm, ChangeTag, DiffOp, TextDiff};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::time::Duration;
+
+/// Files larger than this are compared byte by byte instead of line by line
+pub const DIFF_TEXT_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// Time after which the line diff settles for a less minimal result, for files with a lot of changes
+const DIFF_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of differing byte ranges listed for binary files, the comparison stops after them
+const BYTE_RANGE_LIMIT: usize = 1000;
+
+/// Number of added lines searched for one similar to a removed line
+const PAIR_WINDOW: usize = 20;
+
+/// Number of unchanged rows kept above a hunk when jumping to it
+const HUNK_CONTEXT: usize = 3;
+
+/// Changed lines whose words are less similar than this are shown without the words that changed
+const INLINE_RATIO: f32 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowKind {
+    Equal,
+    Removed, // a line only in the old file, paired with the line replacing it in the unified view
+    Added, // a line only in the new file, paired with the line it replaces in the unified view
+    Changed, // a line of the old file next to the line replacing it in the side by side view
+}
+
+/// A row of the diff: indices of the lines of each file shown on it
+#[derive(Debug, Clone, Copy)]
+pub struct Row {
+    pub kind: RowKind,
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+}
+
+/// Text of a line split in parts, the words changed inside a line replaced by another one are true
+pub type Segments = Vec<(bool, String)>;
+
+#[derive(Debug)]
+pub enum DiffContent {
+    Lines {
+        old: String,
+        old_starts: Vec<usize>,
+        new: String,
+        new_starts: Vec<usize>,
+        side_by_side: Vec<Row>,
+        unified: Vec<Row>,
+        side_by_side_hunks: Vec<usize>, // the first row of every hunk
+        unified_hunks: Vec<usize>,
+        added: usize,
+        removed: usize,
+    },
+    Bytes {
+        old_size: u64,
+        new_size: u64,
+        ranges: Vec<(u64, u64)>, // the differing bytes, start included and end excluded
+        truncated: bool, // if the comparison stopped after BYTE_RANGE_LIMIT ranges
+        reason: String, // why the files are compared byte by byte
+    },
+}
+
+/// Two files compared line by line, or byte by byte for binary files.
+/// The diff is computed in the background, the rows are only styled when they are drawn
+#[derive(Debug)]
+pub struct DiffView {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    pub content: Option<Result<DiffContent, String>>,
+    receiver: Option<mpsc::Receiver<Result<DiffContent, String>>>,
+    pub side_by_side: bool,
+    pub scroll: usize,
+    pub horizontal_scroll: usize,
+    pub height: usize, // the number of visible rows, updated on every draw
+}
+
+impl DiffView {
+    pub fn open(old_path: &Path, new_path: &Path) -> DiffView {
+        let (sender, receiver) = mpsc::channel();
+        let (old, new) = (old_path.to_path_buf(), new_path.to_path_buf());
+        std::thread::spawn(move || {
+            // The view may have been closed and dropped the receiver, the result is not needed then
+            let _ = sender.send(compare(&old, &new));
+        });
+        DiffView {
+            old_path: old_path.to_path_buf(),
+            new_path: new_path.to_path_buf(),
+            content: None,
+            receiver: Some(receiver),
+            side_by_side: true,
+            scroll: 0,
+            horizontal_scroll: 0,
+            height: 0,
+        }
+    }
+
+    pub fn poll(&mut self) {
+        let receiver = match &self.receiver {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        match receiver.try_recv() {
+            Ok(content) => {
+                self.content = Some(content);
+                self.receiver = None;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.content = Some(Err("The comparison stopped unexpectedly".to_string()));
+                self.receiver = None;
+            }
+        }
+    }
+
+    /// The rows of the current layout, empty for binary files
+    pub fn rows(&self) -> &[Row] {
+        match &self.content {
+            Some(Ok(DiffContent::Lines { side_by_side, unified, .. })) => match self.side_by_side {
+                true => side_by_side,
+                false => unified,
+            },
+            _ => &[],
+        }
+    }
+
+    /// The first row of every hunk in the current layout, or every line of the byte ranges for binary files
+    pub fn hunks(&self) -> Vec<usize> {
+        match &self.content {
+            Some(Ok(DiffContent::Lines {
+                side_by_side_hunks,
+                unified_hunks,
+                ..
+            })) => match self.side_by_side {
+                true => side_by_side_hunks.clone(),
+                false => unified_hunks.clone(),
+            },
+            Some(Ok(DiffContent::Bytes { ranges, .. })) => (0..ranges.len()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn row_count(&self) -> usize {
+        match &self.content {
+            Some(Ok(DiffContent::Bytes { ranges, .. })) => ranges.len(),
+            _ => self.rows().len(),
+        }
+    }
+
+    /// The hunk shown at the top of the view, counting from 1, 0 before the first one
+    pub fn current_hunk(&self) -> usize {
+        self.hunks()
+            .iter()
+            .take_while(|start| start.saturating_sub(HUNK_CONTEXT) <= self.scroll)
+            .count()
+    }
+
+    pub fn down(&mut self, count: usize) {
+        let last = self.row_count().saturating_sub(self.height.max(1));
+        self.scroll = self.scroll.saturating_add(count).min(last);
+    }
+
+    pub fn up(&mut self, count: usize) {
+        self.scroll = self.scroll.saturating_sub(count);
+    }
+
+    pub fn first(&mut self) {
+        self.scroll = 0;
+    }
+
+    pub fn last(&mut self) {
+        self.down(usize::MAX);
+    }
+
+    pub fn scroll_right(&mut self) {
+        self.horizontal_scroll += 4;
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.horizontal_scroll = self.horizontal_scroll.saturating_sub(4);
+    }
+
+    pub fn next_hunk(&mut self) {
+        let scroll = self.scroll;
+        if let Some(start) = self.hunks().into_iter().find(|start| start.saturating_sub(HUNK_CONTEXT) > scroll) {
+            self.scroll = start.saturating_sub(HUNK_CONTEXT);
+        }
+    }
+
+    pub fn previous_hunk(&mut self) {
+        let scroll = self.scroll;
+        if let Some(start) = self.hunks().into_iter().rev().find(|start| start.saturating_sub(HUNK_CONTEXT) < scroll) {
+            self.scroll = start.saturating_sub(HUNK_CONTEXT);
+        }
+    }
+
+    /// Switch between the side by side and the unified layouts, staying on the same hunk
+    pub fn toggle_layout(&mut self) {
+        let hunk = self.current_hunk();
+        self.side_by_side = !self.side_by_side;
+        self.scroll = match hunk.checked_sub(1).and_then(|index| self.hunks().get(index).copied()) {
+            Some(start) => start.saturating_sub(HUNK_CONTEXT),
+            None => 0,
+        };
+    }
+
+    /// The text of a row for the old file, or the new one when `new` is true, None when the row has no line there.
+    /// Lines replaced by a similar one are split where their words changed
+    pub fn segments(&self, row: &Row, new: bool) -> Option<Segments> {
+        let (old_text, old_starts, new_text, new_starts) = match &self.content {
+            Some(Ok(DiffContent::Lines {
+                old,
+                old_starts,
+                new,
+                new_starts,
+                ..
+            })) => (old, old_starts, new, new_starts),
+            _ => return None,
+        };
+        let old_line = row.old.map(|index| line_at(old_text, old_starts, index));
+        let new_line = row.new.map(|index| line_at(new_text, new_starts, index));
+        let shown = match new {
+            true => new_line?,
+            false => old_line?,
+        };
+        let (old_line, new_line) = match (row.kind, old_line, new_line) {
+            (RowKind::Equal, _, _) => return Some(vec![(false, shown.to_string())]),
+            (_, Some(old_line), Some(new_line)) => (old_line, new_line),
+            _ => return Some(vec![(false, shown.to_string())]),
+        };
+        let words = TextDiff::from_words(old_line, new_line);
+        if words.ratio() < INLINE_RATIO {
+            return Some(vec![(false, shown.to_string())]);
+        }
+        let mut segments: Segments = Vec::new();
+        for change in words.iter_all_changes() {
+            let changed = match (change.tag(), new) {
+                (ChangeTag::Equal, _) => false,
+                (ChangeTag::Delete, false) | (ChangeTag::Insert, true) => true,
+                _ => continue,
+            };
+            match segments.last_mut() {
+                Some((last, text)) if *last == changed => text.push_str(change.value()),
+                _ => segments.push((changed, change.value().to_string())),
+            }
+        }
+        Some(segments)
+    }
+}
+
+/// Compare two files line by line when both are text, byte by byte otherwise
+fn compare(old_path: &Path, new_path: &Path) -> Result<DiffContent, String> {
+    let old_size = std::fs::metadata(old_path).map_err(|e| format!("{}: {}", old_path.display(), e))?.len();
+    let new_size = std::fs::metadata(new_path).map_err(|e| format!("{}: {}", new_path.display(), e))?.len();
+    if old_size > DIFF_TEXT_LIMIT || new_size > DIFF_TEXT_LIMIT {
+        return compare_bytes(old_path, new_path, "Too large for a line diff".to_string());
+    }
+    let old_bytes = std::fs::read(old_path).map_err(|e| format!("{}: {}", old_path.display(), e))?;
+    let new_bytes = std::fs::read(new_path).map_err(|e| format!("{}: {}", new_path.display(), e))?;
+    let (old, new) = match (decode(&old_bytes, None), decode(&new_bytes, None)) {
+        (Ok(old), Ok(new)) => (old.text, new.text),
+        _ => return compare_bytes(old_path, new_path, "Binary files".to_string()),
+    };
+
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Patience)
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(&old, &new);
+    let (old_starts, new_starts) = (line_starts(&old), line_starts(&new));
+    let mut side_by_side = Vec::new();
+    let mut unified = Vec::new();
+    let mut side_by_side_hunks = Vec::new();
+    let mut unified_hunks = Vec::new();
+    let (mut added, mut removed) = (0, 0);
+    // Consecutive changes, like a deletion followed by an insertion, make one hunk
+    let mut hunk: Option<(Range<usize>, Range<usize>)> = None;
+    let ops = diff.ops();
+    for (index, op) in ops.iter().enumerate() {
+        let (old_range, new_range) = (op.old_range(), op.new_range());
+        if let DiffOp::Equal { .. } = op {
+            for (old_index, new_index) in old_range.zip(new_range) {
+                let row = Row {
+                    kind: RowKind::Equal,
+                    old: Some(old_index),
+                    new: Some(new_index),
+                };
+                side_by_side.push(row);
+                unified.push(row);
+            }
+            continue;
+        }
+        hunk = match hunk {
+            Some((old_hunk, new_hunk)) => Some((old_hunk.start..old_range.end, new_hunk.start..new_range.end)),
+            None => Some((old_range, new_range)),
+        };
+        if matches!(ops.get(index + 1), Some(DiffOp::Equal { .. }) | None) {
+            let (old_hunk, new_hunk) = match hunk.take() {
+                Some(hunk) => hunk,
+                None => continue,
+            };
+            removed += old_hunk.len();
+            added += new_hunk.len();
+            side_by_side_hunks.push(side_by_side.len());
+            unified_hunks.push(unified.len());
+            let old_line = |index: usize| line_at(&old, &old_starts, index);
+            let new_line = |index: usize| line_at(&new, &new_starts, index);
+            let pairs = pair_lines(old_hunk.clone(), new_hunk.clone(), &old_line, &new_line);
+            let mut paired_new = HashMap::new(); // the added line shown next to a removed one
+            for (old_index, new_index) in pairs.iter() {
+                let kind = match (old_index, new_index) {
+                    (Some(old_index), Some(new_index)) => {
+                        paired_new.insert(*old_index, *new_index);
+                        RowKind::Changed
+                    }
+                    (Some(_), None) => RowKind::Removed,
+                    _ => RowKind::Added,
+                };
+                side_by_side.push(Row {
+                    kind,
+                    old: *old_index,
+                    new: *new_index,
+                });
+            }
+            let paired_old: HashMap<usize, usize> = paired_new.iter().map(|(old, new)| (*new, *old)).collect();
+            for old_index in old_hunk {
+                unified.push(Row {
+                    kind: RowKind::Removed,
+                    old: Some(old_index),
+                    new: paired_new.get(&old_index).copied(),
+                });
+            }
+            for new_index in new_hunk {
+                unified.push(Row {
+                    kind: RowKind::Added,
+                    old: paired_old.get(&new_index).copied(),
+                    new: Some(new_index),
+                });
+            }
+        }
+    }
+    Ok(DiffContent::Lines {
+        old_starts,
+        new_starts,
+        old,
+        new,
+        side_by_side,
+        unified,
+        side_by_side_hunks,
+        unified_hunks,
+        added,
+        removed,
+    })
+}
+
+/// Line up the removed and added lines of a hunk, a removed line is shown next to the first similar added line
+/// that follows the lines already paired, the lines left over are shown alone
+fn pair_lines<'a>(
+    old_range: Range<usize>,
+    new_range: Range<usize>,
+    old_line: &dyn Fn(usize) -> &'a str,
+    new_line: &dyn Fn(usize) -> &'a str,
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut pairs = Vec::new();
+    let mut next_new = new_range.start;
+    for old_index in old_range {
+        let similar = (next_new..new_range.end).take(PAIR_WINDOW).find(|new_index| {
+            TextDiff::from_words(old_line(old_index), new_line(*new_index)).ratio() >= INLINE_RATIO
+        });
+        match similar {
+            Some(new_index) => {
+                pairs.extend((next_new..new_index).map(|added| (None, Some(added))));
+                pairs.push((Some(old_index), Some(new_index)));
+                next_new = new_index + 1;
+            }
+            None => pairs.push((Some(old_index), None)),
+        }
+    }
+    pairs.extend((next_new..new_range.end).map(|added| (None, Some(added))));
+    pairs
+}
+
+/// Find the ranges of bytes that differ between two files, reading them side by side.
+/// When the sizes differ the bytes past the end of the shorter file are the last range
+fn compare_bytes(old_path: &Path, new_path: &Path, reason: String) -> Result<DiffContent, String> {
+    let open = |path: &Path| -> Result<(BufReader<File>, u64), String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let size = file.metadata().map_err(|e| format!("{}: {}", path.display(), e))?.len();
+        Ok((BufReader::new(file), size))
+    };
+    let (mut old, old_size) = open(old_path)?;
+    let (mut new, new_size) = open(new_path)?;
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    let mut start: Option<u64> = None; // the start of the range being read
+    let mut offset = 0;
+    let mut truncated = false;
+    let mut old_buffer = vec![0; 64 * 1024];
+    let mut new_buffer = vec![0; 64 * 1024];
+    'read: loop {
+        let old_read = read_full(&mut old, &mut old_buffer).map_err(|e| e.to_string())?;
+        let new_read = read_full(&mut new, &mut new_buffer).map_err(|e| e.to_string())?;
+        let common = old_read.min(new_read);
+        for index in 0..common {
+            match (old_buffer[index] != new_buffer[index], start) {
+                (true, None) => start = Some(offset + index as u64),
+                (false, Some(range_start)) => {
+                    ranges.push((range_start, offset + index as u64));
+                    start = None;
+                    if ranges.len() >= BYTE_RANGE_LIMIT {
+                        truncated = true;
+                        break 'read;
+                    }
+                }
+                _ => {}
+            }
+        }
+        offset += common as u64;
+        if common < old_buffer.len() {
+            break;
+        }
+    }
+    if !truncated {
+        let end = old_size.max(new_size);
+        match start {
+            Some(range_start) => ranges.push((range_start, end.max(offset))),
+            None if old_size != new_size => ranges.push((offset, end)),
+            None => {}
+        }
+    }
+    Ok(DiffContent::Bytes {
+        old_size,
+        new_size,
+        ranges,
+        truncated,
+        reason,
+    })
+}
+
+/// Fill `buffer` as far as the reader goes, returns how many bytes were read
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
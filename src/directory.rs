@@ -0,0 +1,283 @@
+use crate::files::{format_size, is_hidden};
+use chrono::{DateTime, Utc};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span, Text};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::time::SystemTime;
+
+/// Number of largest children listed in the summary
+const LARGEST_COUNT: usize = 5;
+
+/// Number of extensions counted by name in the summary, the other files are counted together
+const EXTENSION_COUNT: usize = 4;
+
+/// Number of entries walked in a subdirectory between two updates of its size
+const SIZE_UPDATE_INTERVAL: usize = 2000;
+
+/// An entry directly inside the summarized directory
+#[derive(Debug)]
+pub struct Child {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64, // the size of everything below it for a directory, as far as it was walked
+}
+
+/// The entries of the directory, read before their sizes
+#[derive(Debug, Default)]
+struct Listing {
+    directories: usize,
+    files: usize,
+    links: usize,
+    others: usize,
+    extensions: Vec<(String, usize)>, // the most common extensions of the files first
+    newest: Option<(String, SystemTime)>,
+    oldest: Option<(String, SystemTime)>,
+    children: Vec<Child>,
+}
+
+/// What the background thread sends while it reads the directory
+enum Update {
+    Listed(Listing),
+    Size(usize, u64), // the index of a child directory and its size so far
+}
+
+/// Counts, modification times and sizes of the entries of a directory, for the preview pane.
+/// The entries are read and the subdirectories are walked in the background
+#[derive(Debug)]
+pub struct DirectorySummary {
+    pub path: String,
+    listing: Option<Listing>,
+    receiver: Option<mpsc::Receiver<Update>>,
+}
+
+impl DirectorySummary {
+    pub fn open(path: &str, show_hidden: bool) -> DirectorySummary {
+        let (sender, receiver) = mpsc::channel();
+        let dir = PathBuf::from(path);
+        std::thread::spawn(move || {
+            let listing = match read_listing(&dir, show_hidden) {
+                Ok(listing) => listing,
+                Err(_) => return,
+            };
+            let directories: Vec<(usize, PathBuf)> = listing
+                .children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| child.is_dir)
+                .map(|(index, child)| (index, dir.join(&child.name)))
+                .collect();
+            if sender.send(Update::Listed(listing)).is_err() {
+                return;
+            }
+            // The summary is dropped when the selection moves on, the walk stops at its next update then
+            for (index, path) in directories {
+                let mut walked = 0;
+                let size = walk_size(&path, &mut walked, &mut |size| sender.send(Update::Size(index, size)).is_ok());
+                match size {
+                    Some(size) => {
+                        if sender.send(Update::Size(index, size)).is_err() {
+                            return;
+                        }
+                    }
+                    None => return,
+                }
+            }
+        });
+        DirectorySummary {
+            path: path.to_string(),
+            listing: None,
+            receiver: Some(receiver),
+        }
+    }
+
+    /// Receive the entries and the sizes of the subdirectories from the background thread
+    pub fn poll(&mut self) {
+        let receiver = match &self.receiver {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok(Update::Listed(listing)) => self.listing = Some(listing),
+                Ok(Update::Size(index, size)) => {
+                    if let Some(child) = self.listing.as_mut().and_then(|listing| listing.children.get_mut(index)) {
+                        child.size = size;
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The summary shown above the entries in the preview pane
+    pub fn text(&self) -> Text<'static> {
+        let dim = Style::default().fg(Color::DarkGray);
+        let listing = match &self.listing {
+            Some(listing) => listing,
+            None => match self.receiver {
+                Some(_) => return Text::styled("Reading the directory...", dim),
+                None => return Text::styled("The directory could not be read", Style::default().fg(Color::Red)),
+            },
+        };
+        let field = |name: &str, value: String| {
+            Line::from(vec![Span::styled(format!("{:<12}", name), dim), Span::raw(value)])
+        };
+
+        let mut items = Vec::new();
+        for (count, singular, plural) in [
+            (listing.directories, "directory", "directories"),
+            (listing.files, "file", "files"),
+            (listing.links, "link", "links"),
+            (listing.others, "other", "others"),
+        ] {
+            match count {
+                0 => {}
+                1 => items.push(format!("1 {}", singular)),
+                count => items.push(format!("{} {}", count, plural)),
+            }
+        }
+        if items.is_empty() {
+            items.push("empty".to_string());
+        }
+        let mut lines = vec![field("Items", items.join(", "))];
+
+        if !listing.extensions.is_empty() {
+            let mut types: Vec<String> = listing
+                .extensions
+                .iter()
+                .take(EXTENSION_COUNT)
+                .map(|(extension, count)| format!("{} {}", count, extension))
+                .collect();
+            let others: usize = listing.extensions.iter().skip(EXTENSION_COUNT).map(|(_, count)| count).sum();
+            if others > 0 {
+                types.push(format!("{} other", others));
+            }
+            lines.push(field("Types", types.join(", ")));
+        }
+
+        let total: u64 = listing.children.iter().map(|child| child.size).sum();
+        let mut size = field("Total size", format_size(total));
+        if self.receiver.is_some() {
+            size.push_span(Span::styled(" (counting...)", dim));
+        }
+        lines.push(size);
+
+        for (name, entry) in [("Newest", &listing.newest), ("Oldest", &listing.oldest)] {
+            if let Some((child, time)) = entry {
+                let datetime: DateTime<Utc> = (*time).into();
+                let mut line = field(name, datetime.format("%Y-%m-%d %H:%M:%S").to_string());
+                line.push_span(Span::styled(format!("  {}", child), dim));
+                lines.push(line);
+            }
+        }
+
+        let mut largest: Vec<&Child> = listing.children.iter().filter(|child| child.size > 0).collect();
+        largest.sort_by_key(|child| std::cmp::Reverse(child.size));
+        for (index, child) in largest.iter().take(LARGEST_COUNT).enumerate() {
+            let name = match index {
+                0 => "Largest",
+                _ => "",
+            };
+            let style = match child.is_dir {
+                true => Style::default().fg(Color::Cyan),
+                false => Style::default().fg(Color::Green),
+            };
+            lines.push(Line::from(vec![
+                Span::styled(format!("{:<12}", name), dim),
+                Span::raw(format!("{:>8}  ", format_size(child.size))),
+                Span::styled(child.name.clone(), style),
+            ]));
+        }
+        lines.push(Line::default());
+        Text::from(lines)
+    }
+}
+
+/// Read the entries directly inside `dir`, links are counted as links and not followed
+fn read_listing(dir: &Path, show_hidden: bool) -> std::io::Result<Listing> {
+    let mut listing = Listing::default();
+    let mut extensions: HashMap<String, usize> = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        let path = entry.path();
+        if !show_hidden && is_hidden(&path).unwrap_or(false) {
+            continue;
+        }
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            listing.links += 1;
+        } else if file_type.is_dir() {
+            listing.directories += 1;
+        } else if file_type.is_file() {
+            listing.files += 1;
+            let extension = match path.extension() {
+                Some(extension) => extension.to_string_lossy().to_lowercase(),
+                None => "no extension".to_string(),
+            };
+            *extensions.entry(extension).or_insert(0) += 1;
+        } else {
+            listing.others += 1;
+        }
+        if let Ok(modified) = metadata.modified() {
+            if listing.newest.as_ref().is_none_or(|(_, newest)| modified > *newest) {
+                listing.newest = Some((name.clone(), modified));
+            }
+            if listing.oldest.as_ref().is_none_or(|(_, oldest)| modified < *oldest) {
+                listing.oldest = Some((name.clone(), modified));
+            }
+        }
+        listing.children.push(Child {
+            name,
+            is_dir: file_type.is_dir(),
+            size: match file_type.is_file() {
+                true => metadata.len(),
+                false => 0,
+            },
+        });
+    }
+    listing.extensions = extensions.into_iter().collect();
+    listing.extensions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(listing)
+}
+
+/// Total size of the files below `dir`, without following links. `update` is called with the size so far
+/// every `SIZE_UPDATE_INTERVAL` entries, the walk stops and returns None when it returns false
+fn walk_size(dir: &Path, walked: &mut usize, update: &mut dyn FnMut(u64) -> bool) -> Option<u64> {
+    let mut size = 0;
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Some(0),
+    };
+    for entry in entries.flatten() {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        *walked += 1;
+        if walked.is_multiple_of(SIZE_UPDATE_INTERVAL) && !update(size) {
+            return None;
+        }
+        if metadata.is_dir() {
+            let mut partial = |below: u64| update(size + below);
+            size += walk_size(&entry.path(), walked, &mut partial)?;
+        } else if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Some(size)
+}
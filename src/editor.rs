@@ -0,0 +1,501 @@
+use crate::encoding::{decode, LineEnding};
+use crate::preview::{char_width, TAB_WIDTH};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Files larger than this are not opened in the editor
+pub const EDIT_SIZE_LIMIT: u64 = 1024 * 1024;
+
+/// Number of changes that can be undone
+const UNDO_LIMIT: usize = 200;
+
+/// The content of the editor before a change, to undo it
+#[derive(Debug)]
+struct Snapshot {
+    lines: Vec<String>,
+    cursor: (usize, usize),
+    revision: u64,
+}
+
+/// Kind of the last change, consecutive changes of the same kind are undone together
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EditKind {
+    Insert,
+    Delete,
+}
+
+/// A small UTF-8 text file opened in the editor screen
+#[derive(Debug)]
+pub struct Editor {
+    pub path: PathBuf,
+    pub lines: Vec<String>,
+    pub row: usize, // the line of the cursor
+    pub col: usize, // the character of the cursor in its line
+    pub scroll_row: usize,
+    pub scroll_col: usize, // the first displayed column
+    pub height: usize, // the number of visible lines, updated on every draw
+    pub line_ending: LineEnding,
+    final_newline: bool, // if the file ends with a line ending
+    was_empty: bool, // if the file was empty, a single empty line is then saved without a line ending
+    bom: bool, // if the file starts with a byte order mark
+    undo: Vec<Snapshot>,
+    last_edit: Option<EditKind>, // the kind of the last change, None once the cursor moved
+    revision: u64, // changes every time the content changes
+    next_revision: u64,
+    saved_revision: u64,
+    pub search_input: String,
+    pub message: Option<String>, // the result of the last save or search
+}
+
+impl Editor {
+    /// Open a text file, binary files, files that are not UTF-8 and files larger than `EDIT_SIZE_LIMIT` are refused
+    pub fn open(path: &Path) -> Result<Editor, String> {
+        let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+        if metadata.len() > EDIT_SIZE_LIMIT {
+            return Err(format!(
+                "The file is too large to be edited, the limit is {} KB",
+                EDIT_SIZE_LIMIT / 1024
+            ));
+        }
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let line_ending = match decode(&bytes, None) {
+            Ok(decoded) => decoded.line_ending,
+            Err(_) => return Err("Binary files cannot be edited".to_string()),
+        };
+        let mut text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(_) => return Err("Only UTF-8 files can be edited".to_string()),
+        };
+        // Saving would have to pick one line ending and change every other line
+        if line_ending == LineEnding::Mixed {
+            return Err("Files with mixed line endings cannot be edited".to_string());
+        }
+        let bom = text.starts_with('\u{feff}');
+        if bom {
+            text.remove(0);
+        }
+        let final_newline = text.is_empty() || text.ends_with('\n');
+        let terminator = match line_ending {
+            LineEnding::Crlf => "\r\n",
+            _ => "\n",
+        };
+        let body = text.strip_suffix(terminator).unwrap_or(&text);
+        let lines = body.split(terminator).map(|line| line.to_string()).collect();
+        Ok(Editor {
+            path: path.to_path_buf(),
+            lines,
+            row: 0,
+            col: 0,
+            scroll_row: 0,
+            scroll_col: 0,
+            height: 0,
+            line_ending,
+            final_newline,
+            was_empty: text.is_empty(),
+            bom,
+            undo: Vec::new(),
+            last_edit: None,
+            revision: 0,
+            next_revision: 1,
+            saved_revision: 0,
+            search_input: String::new(),
+            message: None,
+        })
+    }
+
+    /// If the content changed since it was opened or saved
+    pub fn is_dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+
+    /// Column of the cursor on screen, tabs take `TAB_WIDTH` columns and wide characters two
+    pub fn cursor_column(&self) -> usize {
+        self.lines[self.row]
+            .chars()
+            .take(self.col)
+            .map(|c| match c {
+                '\t' => TAB_WIDTH,
+                c => char_width(c),
+            })
+            .sum()
+    }
+
+    /// Scroll so that the cursor is visible in a view of `width` columns
+    pub fn scroll_to_cursor(&mut self, width: usize) {
+        let height = self.height.max(1);
+        if self.row < self.scroll_row {
+            self.scroll_row = self.row;
+        } else if self.row >= self.scroll_row + height {
+            self.scroll_row = self.row + 1 - height;
+        }
+        let column = self.cursor_column();
+        let width = width.max(1);
+        if column < self.scroll_col {
+            self.scroll_col = column;
+        } else if column >= self.scroll_col + width {
+            self.scroll_col = column + 1 - width;
+        }
+    }
+
+    fn line_length(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+
+    /// Byte offset of the character `col` in line `row`
+    fn byte_index(&self, row: usize, col: usize) -> usize {
+        match self.lines[row].char_indices().nth(col) {
+            Some((index, _)) => index,
+            None => self.lines[row].len(),
+        }
+    }
+
+    fn moved(&mut self) {
+        self.last_edit = None;
+        self.message = None;
+    }
+
+    pub fn left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_length(self.row);
+        }
+        self.moved();
+    }
+
+    pub fn right(&mut self) {
+        if self.col < self.line_length(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+        self.moved();
+    }
+
+    pub fn up(&mut self, count: usize) {
+        self.row = self.row.saturating_sub(count);
+        self.col = self.col.min(self.line_length(self.row));
+        self.moved();
+    }
+
+    pub fn down(&mut self, count: usize) {
+        self.row = self.row.saturating_add(count).min(self.lines.len() - 1);
+        self.col = self.col.min(self.line_length(self.row));
+        self.moved();
+    }
+
+    pub fn line_start(&mut self) {
+        self.col = 0;
+        self.moved();
+    }
+
+    pub fn line_end(&mut self) {
+        self.col = self.line_length(self.row);
+        self.moved();
+    }
+
+    pub fn first(&mut self) {
+        self.row = 0;
+        self.col = 0;
+        self.moved();
+    }
+
+    pub fn last(&mut self) {
+        self.row = self.lines.len() - 1;
+        self.col = self.line_length(self.row);
+        self.moved();
+    }
+
+    /// Remember the content before a change so it can be undone, unless it continues the last change
+    fn checkpoint(&mut self, kind: Option<EditKind>) {
+        if kind.is_none() || kind != self.last_edit {
+            self.undo.push(Snapshot {
+                lines: self.lines.clone(),
+                cursor: (self.row, self.col),
+                revision: self.revision,
+            });
+            if self.undo.len() > UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+        }
+        self.last_edit = kind;
+        self.revision = self.next_revision;
+        self.next_revision += 1;
+        self.message = None;
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.checkpoint(Some(EditKind::Insert));
+        let index = self.byte_index(self.row, self.col);
+        self.lines[self.row].insert(index, c);
+        self.col += 1;
+    }
+
+    /// Split the line at the cursor
+    pub fn insert_newline(&mut self) {
+        self.checkpoint(None);
+        let index = self.byte_index(self.row, self.col);
+        let rest = self.lines[self.row].split_off(index);
+        self.lines.insert(self.row + 1, rest);
+        self.row += 1;
+        self.col = 0;
+    }
+
+    /// Delete the character before the cursor, or join the line with the previous one
+    pub fn backspace(&mut self) {
+        if self.col == 0 && self.row == 0 {
+            return;
+        }
+        self.checkpoint(Some(EditKind::Delete));
+        if self.col > 0 {
+            let index = self.byte_index(self.row, self.col - 1);
+            self.lines[self.row].remove(index);
+            self.col -= 1;
+        } else {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_length(self.row);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    /// Delete the character under the cursor, or join the next line with this one
+    pub fn delete(&mut self) {
+        if self.col == self.line_length(self.row) && self.row + 1 == self.lines.len() {
+            return;
+        }
+        self.checkpoint(Some(EditKind::Delete));
+        if self.col < self.line_length(self.row) {
+            let index = self.byte_index(self.row, self.col);
+            self.lines[self.row].remove(index);
+        } else {
+            let line = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn undo(&mut self) {
+        match self.undo.pop() {
+            Some(snapshot) => {
+                self.lines = snapshot.lines;
+                (self.row, self.col) = snapshot.cursor;
+                self.revision = snapshot.revision;
+                self.moved();
+            }
+            None => self.message = Some("Nothing to undo".to_string()),
+        }
+    }
+
+    /// Move the cursor to the next match of the search after it, going back to the top at the end of the file.
+    /// The search ignores case unless it has an uppercase letter
+    pub fn find_next(&mut self) {
+        if self.search_input.is_empty() {
+            return;
+        }
+        let ignore_case = !self.search_input.chars().any(char::is_uppercase);
+        let normalize = |text: &str| match ignore_case {
+            true => text.to_lowercase(),
+            false => text.to_string(),
+        };
+        let pattern = normalize(&self.search_input);
+        let count = self.lines.len();
+        // The line of the cursor is searched again last, for the matches before the cursor
+        for step in 0..=count {
+            let row = (self.row + step) % count;
+            let line = normalize(&self.lines[row]);
+            let start = match step {
+                0 => self.byte_index(row, self.col + 1).min(line.len()),
+                _ => 0,
+            };
+            let found = match line.get(start..).and_then(|rest| rest.find(&pattern)) {
+                Some(found) => start + found,
+                None => continue,
+            };
+            if step == count && found >= self.byte_index(row, self.col + 1) {
+                break;
+            }
+            self.moved();
+            self.row = row;
+            self.col = line[..found].chars().count();
+            return;
+        }
+        self.message = Some(format!("Not found: {}", self.search_input));
+    }
+
+    /// Write the content to a temporary file next to the file and rename it over the file, so the file is never
+    /// left half written. The permissions, owner and group of the file are kept, a link is followed to write
+    /// the file it points to
+    pub fn save(&mut self) -> Result<(), String> {
+        let path = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let terminator = match self.line_ending {
+            LineEnding::Crlf => "\r\n",
+            _ => "\n",
+        };
+        let mut content = String::new();
+        if self.bom {
+            content.push('\u{feff}');
+        }
+        content.push_str(&self.lines.join(terminator));
+        if self.final_newline && !(self.was_empty && self.lines.len() == 1 && self.lines[0].is_empty()) {
+            content.push_str(terminator);
+        }
+
+        let dir = match path.parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => return Err("The file has no parent directory".to_string()),
+        };
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => return Err("The file has no name".to_string()),
+        };
+        // Renaming over the file would replace it even when it cannot be written
+        let metadata = fs::metadata(&path).ok();
+        if metadata.as_ref().is_some_and(|metadata| metadata.permissions().readonly()) {
+            return Err(format!("{} is read-only", path.display()));
+        }
+        let (mut file, temporary) =
+            create_temporary(&dir, &name).map_err(|e| format!("Could not save {}: {}", path.display(), e))?;
+        let mut write = || -> io::Result<()> {
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+            if let Some(metadata) = &metadata {
+                // The owner first, changing it clears the setuid and setgid bits
+                keep_owner(&file, metadata)?;
+                file.set_permissions(metadata.permissions())?;
+            }
+            fs::rename(&temporary, &path)
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&temporary);
+            return Err(format!("Could not save {}: {}", path.display(), e));
+        }
+        self.saved_revision = self.revision;
+        self.message = Some(format!("Saved {} lines", self.lines.len()));
+        Ok(())
+    }
+}
+
+/// Create a new temporary file in `dir` for the file `name`. It has to be new, a file or a link someone put
+/// at its name is never written through
+fn create_temporary(dir: &Path, name: &str) -> io::Result<(File, PathBuf)> {
+    let mut attempt = 0;
+    loop {
+        let temporary = dir.join(format!(".{}.stfm-{}-{}.tmp", name, std::process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temporary) {
+            Ok(file) => return Ok((file, temporary)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Give the temporary file the owner and group of the file it replaces, like when root edits the file of a service.
+/// The file is not saved when they cannot be kept
+#[cfg(unix)]
+fn keep_owner(file: &File, metadata: &fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{fchown, MetadataExt};
+    let created = file.metadata()?;
+    if created.uid() == metadata.uid() && created.gid() == metadata.gid() {
+        return Ok(());
+    }
+    fchown(file, Some(metadata.uid()), Some(metadata.gid()))
+        .map_err(|e| io::Error::new(e.kind(), format!("its owner and group cannot be kept: {}", e)))
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _metadata: &fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for a test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stfm-editor-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Open a file holding `bytes`, save it without changing it and read it back
+    fn save_unchanged(dir: &Path, bytes: &[u8]) -> Vec<u8> {
+        let path = dir.join("file.txt");
+        fs::write(&path, bytes).unwrap();
+        Editor::open(&path).unwrap().save().unwrap();
+        fs::read(&path).unwrap()
+    }
+
+    #[test]
+    fn saves_unchanged_files_as_they_were() {
+        let dir = scratch_dir("unchanged");
+        let files: [&[u8]; 7] = [
+            b"one\ntwo\n",
+            b"one\r\ntwo\r\n",
+            "\u{feff}one\ntwo\n".as_bytes(),
+            b"one\ntwo",
+            b"one\r\ntwo",
+            b"",
+            b"\n",
+        ];
+        for bytes in files {
+            assert_eq!(save_unchanged(&dir, bytes), bytes);
+        }
+        // The temporary file was renamed over the file
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saves_with_the_line_ending_of_the_file() {
+        let dir = scratch_dir("line-ending");
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let mut editor = Editor::open(&path).unwrap();
+        editor.lines.push("three".to_string());
+        editor.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\r\ntwo\r\nthree\r\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_mixed_line_endings() {
+        let dir = scratch_dir("mixed");
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\r\ntwo\n").unwrap();
+        assert!(Editor::open(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_the_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("mode");
+        let path = dir.join("file.txt");
+        fs::write(&path, "secret\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        Editor::open(&path).unwrap().save().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_writes_through_a_planted_temporary_file() {
+        let dir = scratch_dir("planted");
+        let path = dir.join("file.txt");
+        let victim = dir.join("victim");
+        fs::write(&path, "one\n").unwrap();
+        fs::write(&victim, "untouched").unwrap();
+        std::os::unix::fs::symlink(&victim, dir.join(format!(".file.txt.stfm-{}-0.tmp", std::process::id()))).unwrap();
+        Editor::open(&path).unwrap().save().unwrap();
+        assert_eq!(fs::read_to_string(&victim).unwrap(), "untouched");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
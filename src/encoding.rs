@@ -0,0 +1,126 @@
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use std::fmt;
+
+/// Encodings offered in the encoding picker of the preview
+pub const PICKER_ENCODINGS: [&Encoding; 13] = [
+    encoding_rs::UTF_8,
+    encoding_rs::UTF_16LE,
+    encoding_rs::UTF_16BE,
+    encoding_rs::WINDOWS_1252,
+    encoding_rs::ISO_8859_15,
+    encoding_rs::ISO_8859_2,
+    encoding_rs::WINDOWS_1251,
+    encoding_rs::KOI8_R,
+    encoding_rs::SHIFT_JIS,
+    encoding_rs::EUC_JP,
+    encoding_rs::GBK,
+    encoding_rs::BIG5,
+    encoding_rs::EUC_KR,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    None,
+    Lf,
+    Crlf,
+    Mixed,
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineEnding::None => write!(f, "no EOL"),
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::Crlf => write!(f, "CRLF"),
+            LineEnding::Mixed => write!(f, "mixed EOL"),
+        }
+    }
+}
+
+/// Text decoded from the bytes of a file
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub line_ending: LineEnding,
+}
+
+/// Name of an encoding as shown to the user
+pub fn encoding_name(encoding: &'static Encoding) -> &'static str {
+    if encoding == encoding_rs::WINDOWS_1252 {
+        return "windows-1252 (Latin-1)";
+    }
+    encoding.name()
+}
+
+/// Guess the encoding of `bytes` from its BOM, UTF-8 validity or the byte distribution,
+/// returns None when it looks like binary data
+pub fn detect(bytes: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return Some(encoding);
+    }
+    if let Some(encoding) = detect_utf16(bytes) {
+        return Some(encoding);
+    }
+    if bytes.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => return Some(encoding_rs::UTF_8),
+        // The bytes may be a prefix of the file that cuts a character in half
+        Err(e) if e.error_len().is_none() => return Some(encoding_rs::UTF_8),
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    Some(detector.guess(None, true))
+}
+
+/// UTF-16 text without a BOM has a zero in almost every other byte when it is mostly ASCII
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(4096) & !1];
+    if sample.len() < 4 {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let even_zeros = sample.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|byte| **byte == 0).count();
+    if odd_zeros * 10 >= pairs * 7 && even_zeros * 10 <= pairs {
+        return Some(encoding_rs::UTF_16LE);
+    }
+    if even_zeros * 10 >= pairs * 7 && odd_zeros * 10 <= pairs {
+        return Some(encoding_rs::UTF_16BE);
+    }
+    None
+}
+
+/// Find out if the lines of `text` end with LF, CRLF or a mix of both
+pub fn detect_line_ending(text: &str) -> LineEnding {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    match (lf, crlf) {
+        (0, 0) => LineEnding::None,
+        (_, 0) => LineEnding::Lf,
+        (0, _) => LineEnding::Crlf,
+        _ => LineEnding::Mixed,
+    }
+}
+
+/// Decode `bytes` with `forced` or, when it is None, with the detected encoding
+pub fn decode(bytes: &[u8], forced: Option<&'static Encoding>) -> Result<Decoded, String> {
+    let encoding = match forced {
+        Some(encoding) => encoding,
+        None => match detect(bytes) {
+            Some(encoding) => encoding,
+            None => return Err("Cannot preview binary file".to_string()),
+        },
+    };
+    let (text, _) = encoding.decode_with_bom_removal(bytes);
+    let text = text.into_owned();
+    Ok(Decoded {
+        line_ending: detect_line_ending(&text),
+        text,
+        encoding,
+    })
+}
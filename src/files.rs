@@ -132,11 +132,11 @@ pub fn delete_file(file_name: &PathBuf) -> Result<bool, Error> {
 }
 
 /// Read a file
-pub fn read_file(file_name: &str) -> Result<String, Error> {
+pub fn read_file(file_name: &str) -> Result<Vec<u8>, Error> {
     match std::fs::File::open(file_name) {
         Ok(mut file) => {
-            let mut contents = String::new();
-            match file.read_to_string(&mut contents) {
+            let mut contents = Vec::new();
+            match file.read_to_end(&mut contents) {
                 Ok(_) => Ok(contents),
                 Err(e) => Err(e),
             }
@@ -145,6 +145,21 @@ pub fn read_file(file_name: &str) -> Result<String, Error> {
     }
 }
 
+/// Size in bytes as a short human readable string, like 1.5K or 20.0M
+pub fn format_size(size: u64) -> String {
+    let units = ["K", "M", "G", "T"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < units.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, units[unit])
+}
+
 /// Change the current directory
 pub fn change_dir(dir_name: &PathBuf) -> Result<bool, Error> {
     match std::env::set_current_dir(dir_name) {
@@ -0,0 +1,75 @@
+use std::io;
+use std::sync::mpsc::{self, TryRecvError};
+
+/// What a background job sends to the main thread
+enum JobEvent {
+    Progress(u64, u64), // the amount of work done and the total
+    Finished(Result<(), String>),
+}
+
+/// Handed to the work of a job to report how far it got
+pub struct JobProgress {
+    sender: mpsc::Sender<JobEvent>,
+}
+
+impl JobProgress {
+    /// Report `done` out of `total`, fails when the job was dropped so the work stops
+    pub fn report(&self, done: u64, total: u64) -> io::Result<()> {
+        match self.sender.send(JobEvent::Progress(done, total)) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(io::Error::new(io::ErrorKind::Interrupted, "Job cancelled")),
+        }
+    }
+}
+
+/// Work running in a background thread, like creating or extracting an archive
+#[derive(Debug)]
+pub struct Job {
+    pub title: String,
+    pub done: u64,
+    pub total: u64,
+    receiver: mpsc::Receiver<JobEvent>,
+}
+
+impl Job {
+    pub fn spawn<F>(title: String, work: F) -> Job
+    where
+        F: FnOnce(&JobProgress) -> Result<(), String> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let progress = JobProgress { sender };
+            let result = work(&progress);
+            let _ = progress.sender.send(JobEvent::Finished(result));
+        });
+        Job {
+            title,
+            done: 0,
+            total: 0,
+            receiver,
+        }
+    }
+
+    /// Receive what the job sent since the last poll, returns its result once it finished
+    pub fn poll(&mut self) -> Option<Result<(), String>> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(JobEvent::Progress(done, total)) => {
+                    self.done = done;
+                    self.total = total;
+                }
+                Ok(JobEvent::Finished(result)) => return Some(result),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => return Some(Err(format!("{} stopped", self.title))),
+            }
+        }
+    }
+
+    /// How much of the job is done, from 0 to 100
+    pub fn percent(&self) -> u64 {
+        match self.total {
+            0 => 0,
+            total => (self.done * 100 / total).min(100),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::action::{Action, Context, Key};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+const fn char(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        modifiers: KeyModifiers::NONE,
+    }
+}
+
+const fn ctrl(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        modifiers: KeyModifiers::CONTROL,
+    }
+}
+
+const fn code(code: KeyCode) -> Key {
+    Key {
+        code,
+        modifiers: KeyModifiers::NONE,
+    }
+}
+
+const fn ctrl_code(code: KeyCode) -> Key {
+    Key {
+        code,
+        modifiers: KeyModifiers::CONTROL,
+    }
+}
+
+/// The actions of a context with their keys, in the order they are listed in the command palette
+type Bindings = &'static [(Action, &'static [Key])];
+
+const MAIN: Bindings = &[
+    (Action::Open, &[code(KeyCode::Enter), code(KeyCode::Right)]),
+    (Action::Back, &[code(KeyCode::Backspace), code(KeyCode::Left)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::Search, &[char('/')]),
+    (Action::NewFile, &[char('n')]),
+    (Action::Delete, &[char('d')]),
+    (Action::Rename, &[char('r')]),
+    (Action::Copy, &[char('c')]),
+    (Action::Paste, &[char('p')]),
+    (Action::ToggleMark, &[char(' ')]),
+    (Action::ToggleHidden, &[char('.')]),
+    (Action::TogglePreviewPane, &[char('v')]),
+    (Action::Compress, &[char('z')]),
+    (Action::Extract, &[char('x')]),
+    (Action::Diff, &[char('D')]),
+    (Action::Edit, &[char('e')]),
+    (Action::ExternalEditor, &[char('E')]),
+    (Action::Pager, &[char('P')]),
+    (Action::OpenWith, &[char('o')]),
+    (Action::Shell, &[char('S')]),
+    (Action::CommandPrompt, &[char('!')]),
+    (Action::CommandOutput, &[char('O')]),
+    (Action::Refresh, &[]),
+    (Action::Palette, &[char(':'), ctrl('p')]),
+    (Action::Help, &[char('h')]),
+    (Action::Quit, &[char('q'), code(KeyCode::Esc)]),
+];
+
+const PREVIEW: Bindings = &[
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::Left, &[code(KeyCode::Left)]),
+    (Action::Right, &[code(KeyCode::Right)]),
+    (Action::First, &[code(KeyCode::Home), char('g')]),
+    (Action::Last, &[code(KeyCode::End), char('G')]),
+    (Action::GoToLine, &[char(':')]),
+    (Action::Search, &[char('/')]),
+    (Action::NextMatch, &[char('n')]),
+    (Action::PreviousMatch, &[char('N')]),
+    (Action::ToggleLineNumbers, &[char('l')]),
+    (Action::ToggleWrap, &[char('w')]),
+    (Action::ToggleFollow, &[char('f')]),
+    (Action::PickEncoding, &[char('e')]),
+    (Action::ToggleMarkdown, &[char('m')]),
+    (Action::ToggleTree, &[char('t')]),
+    (Action::Palette, &[ctrl('p')]),
+    (Action::Close, &[char('q'), code(KeyCode::Esc)]),
+];
+
+const TABLE: Bindings = &[
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::Left, &[code(KeyCode::Left)]),
+    (Action::Right, &[code(KeyCode::Right)]),
+    (Action::First, &[code(KeyCode::Home), char('g')]),
+    (Action::Last, &[code(KeyCode::End), char('G')]),
+    (Action::ToggleSummary, &[char('s')]),
+    (Action::Palette, &[ctrl('p')]),
+    (Action::Close, &[char('q'), code(KeyCode::Esc)]),
+];
+
+const DATABASE: Bindings = &[
+    (Action::Open, &[code(KeyCode::Enter), code(KeyCode::Right)]),
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::First, &[code(KeyCode::Home), char('g')]),
+    (Action::Last, &[code(KeyCode::End), char('G')]),
+    (Action::Palette, &[ctrl('p')]),
+    (Action::Close, &[char('q'), code(KeyCode::Esc)]),
+];
+
+const TREE: Bindings = &[
+    (Action::ToggleNode, &[code(KeyCode::Enter), char(' ')]),
+    (Action::Expand, &[code(KeyCode::Right)]),
+    (Action::Collapse, &[code(KeyCode::Left)]),
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::First, &[code(KeyCode::Home), char('g')]),
+    (Action::Last, &[code(KeyCode::End), char('G')]),
+    (Action::ToggleTree, &[char('t')]),
+    (Action::Palette, &[ctrl('p')]),
+    (Action::Close, &[char('q'), code(KeyCode::Esc)]),
+];
+
+const DIFF: Bindings = &[
+    (Action::NextHunk, &[char('n')]),
+    (Action::PreviousHunk, &[char('N')]),
+    (Action::ToggleLayout, &[char('u')]),
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::Left, &[code(KeyCode::Left)]),
+    (Action::Right, &[code(KeyCode::Right)]),
+    (Action::First, &[code(KeyCode::Home), char('g')]),
+    (Action::Last, &[code(KeyCode::End), char('G')]),
+    (Action::Palette, &[ctrl('p')]),
+    (Action::Close, &[char('q'), code(KeyCode::Esc)]),
+];
+
+// Keys typing text in the editor are handled with the editor screen
+const EDIT: Bindings = &[
+    (Action::Save, &[ctrl('s')]),
+    (Action::Undo, &[ctrl('z')]),
+    (Action::Search, &[ctrl('f')]),
+    (Action::FindNext, &[ctrl('n')]),
+    (Action::Left, &[code(KeyCode::Left)]),
+    (Action::Right, &[code(KeyCode::Right)]),
+    (Action::Up, &[code(KeyCode::Up)]),
+    (Action::Down, &[code(KeyCode::Down)]),
+    (Action::PageUp, &[code(KeyCode::PageUp)]),
+    (Action::PageDown, &[code(KeyCode::PageDown)]),
+    (Action::LineStart, &[code(KeyCode::Home)]),
+    (Action::LineEnd, &[code(KeyCode::End)]),
+    (Action::First, &[ctrl_code(KeyCode::Home)]),
+    (Action::Last, &[ctrl_code(KeyCode::End)]),
+    (Action::Palette, &[ctrl('p')]),
+    (Action::Close, &[code(KeyCode::Esc), ctrl('q')]),
+];
+
+/// The actions of `context` with their keys
+pub fn bindings(context: Context) -> Bindings {
+    match context {
+        Context::Main => MAIN,
+        Context::Preview => PREVIEW,
+        Context::Table => TABLE,
+        Context::Database => DATABASE,
+        Context::Tree => TREE,
+        Context::Diff => DIFF,
+        Context::Edit => EDIT,
+    }
+}
+
+/// The action bound to the key of `event` in `context`
+pub fn lookup(context: Context, event: &KeyEvent) -> Option<Action> {
+    bindings(context)
+        .iter()
+        .find(|(_, keys)| keys.iter().any(|key| key.matches(event)))
+        .map(|(action, _)| *action)
+}
@@ -2,18 +2,43 @@ use ratatui::{
     backend::{Backend, CrosstermBackend},
     crossterm::{
         self,
-        event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+        cursor::Show,
+        event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
         execute,
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     },
     Terminal,
 };
+use std::process::{Command, ExitStatus};
 use std::sync::mpsc::TryRecvError;
 use std::{error::Error, io, time::Duration, time::Instant};
+mod action;
+mod ansi;
 mod app;
+mod archive;
+mod binary;
+mod config;
+mod diff;
+mod directory;
+mod editor;
+mod encoding;
 mod files;
+mod jobs;
+mod keymap;
+mod markdown;
+mod media;
+mod opener;
+mod palette;
+mod pdf;
+mod preview;
+mod previewer;
+mod shell;
+mod sqlite;
+mod table;
+mod tree;
 mod ui;
 use crate::{
+    action::Context,
     app::{App, CurrentScreen},
     ui::ui,
 };
@@ -41,11 +66,45 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Give the terminal to a program like an editor or a pager: leave raw mode and the alternate screen set up in
+/// `main`, wait for the program to exit and set the terminal up again
+fn run_foreground<B: Backend>(terminal: &mut Terminal<B>, command: &mut Command) -> io::Result<ExitStatus> {
+    disable_raw_mode()?;
+    execute!(io::stderr(), LeaveAlternateScreen, DisableMouseCapture, Show)?;
+    let status = command.status();
+    enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen, EnableMouseCapture)?;
+    // The program drew over the screen, everything has to be drawn again
+    terminal.clear()?;
+    status
+}
+
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<bool> {
     let mut last_tick = Instant::now();
     let tick_rate = Duration::from_millis(1); // Adjust tick rate for UI refresh
     loop {
+        if app.quit {
+            break Ok(true);
+        }
+        if let Some(mut foreground) = app.foreground_command.take() {
+            let program = foreground.command.get_program().to_string_lossy().to_string();
+            let status = run_foreground(terminal, &mut foreground.command);
+            // The program may have changed the files
+            app.refresh();
+            match status {
+                Ok(status) if status.success() || !foreground.check_status => {}
+                Ok(status) => {
+                    app.error_message = Some(format!("{} exited with {}", program, status));
+                    app.current_screen = CurrentScreen::ErrorPopUp;
+                }
+                Err(e) => {
+                    app.error_message = Some(format!("Could not run {}: {}", program, e));
+                    app.current_screen = CurrentScreen::ErrorPopUp;
+                }
+            }
+        }
         terminal.draw(|f| ui(f, app))?;
+        app.tick();
         if let Some(ref receiver) = app.progress_receiver {
             match receiver.try_recv() {
                 Ok(progress) => {
@@ -66,6 +125,10 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     app.file_to_copy = None;
                     app.readed_bytes = 0;
                     app.reset();
+                    if let Some(e) = app.copy_error.lock().unwrap().take() {
+                        app.error_message = Some(e);
+                        app.current_screen = CurrentScreen::ErrorPopUp;
+                    }
                 }
             }
         }
@@ -74,153 +137,128 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                 if key.kind == event::KeyEventKind::Release {
                     continue;
                 }
+                // The screens with keys in the keymap do the action bound to the key
+                if let Some(context) = app.context() {
+                    // Keys of custom commands take over the built-in keys of the main screen
+                    let custom = match context {
+                        Context::Main => app
+                            .custom_commands
+                            .iter()
+                            .position(|command| command.key.is_some_and(|custom| custom.matches(&key))),
+                        _ => None,
+                    };
+                    if let Some(index) = custom {
+                        app.run_custom_command(index);
+                        continue;
+                    }
+                    if let Some(action) = keymap::lookup(context, &key) {
+                        app.perform(action);
+                        continue;
+                    }
+                }
                 match app.current_screen {
+                    // Only the keys of the keymap do something in these screens
+                    CurrentScreen::Main | CurrentScreen::Preview | CurrentScreen::Diff => {}
 
-                    CurrentScreen::Main => match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => {
-                            break Ok(true);
+                    CurrentScreen::Search => match key.code {
+                        KeyCode::Esc => {
+                            app.reset();
                         }
-                        
-                        KeyCode::Char('n') => {
-                            app.current_screen = CurrentScreen::IsNewFileADir;
+                        KeyCode::Backspace => {
+                            app.search_input.pop();
+                            app.search();
                         }
-
-                        KeyCode::Char('h') => {
-                            app.current_screen = CurrentScreen::Help;
+                        KeyCode::Enter => {
+                            app.search();
+                            app.current_screen = CurrentScreen::Main;
                         }
-
-                        KeyCode::Char('d') => match app.selected_file.clone() {
-                            Some(_) => {
-                                app.current_screen = CurrentScreen::ConfirmDelete;
-                            }
-                            None => {
-                                app.error_message = Some("No file selected".to_string());
-                                app.current_screen = CurrentScreen::ErrorPopUp;
-
-                            }
+                        KeyCode::Delete => {
+                            app.search_input.clear();
+                            app.search();
                         }
-                        KeyCode::Char('c') => match app.selected_file.clone() {
-                            Some(file) => {
-                                if file.type_name == "File"{
-                                    app.file_to_copy = Some(file);
+                        _ => match key.code.to_string().chars().last() {
+                            Some(c) => {
+                                if c.is_alphanumeric() || c == '.' {
+                                    app.search_input.push(c);
+                                    app.search();
                                 }
                             }
-                            None => {
-                                app.error_message = Some("No file selected".to_string());
-                                app.current_screen = CurrentScreen::ErrorPopUp;
-                            }
-
+                            _ => {}
+                        },
+                    },
+                    CurrentScreen::PreviewSearch => match key.code {
+                        KeyCode::Esc => {
+                            app.current_screen = CurrentScreen::Preview;
                         }
 
-                        KeyCode::Char('p') => match app.file_to_copy.clone() {
-                            Some(_) => {
-                                app.current_screen = CurrentScreen::ConfirmCopyingPopUp;
-                            }
-                            None => {
-                                app.error_message = Some("No file selected".to_string());
-                                app.current_screen = CurrentScreen::ErrorPopUp;
+                        KeyCode::Enter => {
+                            app.preview_search();
+                            if app.preview_search_error.is_none() {
+                                app.current_screen = CurrentScreen::Preview;
                             }
                         }
 
-                        KeyCode::Char('r') => match app.selected_file.clone() {
-                            Some(_) => {
-                                app.current_screen = CurrentScreen::Rename;
-                            }
-                            None => {
-                                app.error_message = Some("No file selected".to_string());
-                                app.current_screen = CurrentScreen::ErrorPopUp;
-                            }
+                        KeyCode::Backspace => {
+                            app.preview_search_input.pop();
                         }
 
-                        KeyCode::Char('/') => {
-                            app.search_input.clear();
-                            app.current_screen = CurrentScreen::Search;
+                        KeyCode::Delete => {
+                            app.preview_search_input.clear();
+                        }
+
+                        KeyCode::Tab => {
+                            app.preview_search_ignore_case = !app.preview_search_ignore_case;
                         }
 
-                        KeyCode::Char('.') => {
-                            app.toggle_hidden();
+                        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app.preview_search_regex = !app.preview_search_regex;
                         }
 
-                        KeyCode::PageDown => {
-                            app.page_down();
+                        KeyCode::Char(c) => {
+                            app.preview_search_input.push(c);
                         }
 
-                        KeyCode::PageUp => {
-                            app.page_up();
+                        _ => {}
+                    },
+                    CurrentScreen::EncodingPicker => match key.code {
+                        KeyCode::Esc | KeyCode::Char('q') => {
+                            app.current_screen = CurrentScreen::Preview;
                         }
 
                         KeyCode::Down => {
-                            app.down();
+                            app.encoding_list_state.select_next();
                         }
-                        
+
                         KeyCode::Up => {
-                            app.up();
+                            app.encoding_list_state.select_previous();
                         }
 
-                        KeyCode::Enter | KeyCode::Right => {
-                            app.handle_selected_file();
-                        }
-                        KeyCode::Backspace | KeyCode::Left => {
-                            app.previus_dir();
+                        KeyCode::Enter => {
+                            app.pick_encoding();
                         }
 
                         _ => {}
                     },
-                    CurrentScreen::Search => match key.code {
+                    CurrentScreen::GoToLine => match key.code {
                         KeyCode::Esc => {
-                            app.reset();
-                        }
-                        KeyCode::Backspace => {
-                            app.search_input.pop();
-                            app.search();
+                            app.goto_line_input.clear();
+                            app.current_screen = CurrentScreen::Preview;
                         }
+
                         KeyCode::Enter => {
-                            app.search();
-                            app.current_screen = CurrentScreen::Main;
-                        }
-                        KeyCode::Delete => {
-                            app.search_input.clear();
-                            app.search();
-                        }
-                        _ => match key.code.to_string().chars().last() {
-                            Some(c) => {
-                                if c.is_alphanumeric() || c == '.' {
-                                    app.search_input.push(c);
-                                    app.search();
-                                }
+                            if let Ok(line) = app.goto_line_input.parse::<usize>() {
+                                app.go_to_line(line);
                             }
-                            _ => {}
-                        },
-                    },
-                    CurrentScreen::Preview => match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => {
-                            app.current_screen = CurrentScreen::Main;
-                        }
-                        
-                        KeyCode::Down => {
-                            app.scroll_down(10);
-                        }
-
-                        KeyCode::PageDown => {
-                            app.scroll_down(50);
-                        }
-                        
-                        KeyCode::Up => {
-                            app.scroll_up(10);
+                            app.goto_line_input.clear();
+                            app.current_screen = CurrentScreen::Preview;
                         }
 
-                        KeyCode::PageUp => {
-                            app.scroll_up(50);
+                        KeyCode::Backspace => {
+                            app.goto_line_input.pop();
                         }
 
-
-                        
-                        KeyCode::Left => {
-                            app.scroll_left();
-                        }
-                        
-                        KeyCode::Right => {
-                            app.scroll_right();
+                        KeyCode::Char(c) if c.is_ascii_digit() => {
+                            app.goto_line_input.push(c);
                         }
 
                         _ => {}
@@ -288,6 +326,260 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                         }
                     }
 
+                    CurrentScreen::Compress => match key.code {
+                        KeyCode::Esc => {
+                            app.new_file.clear();
+                            app.compress_sources.clear();
+                            app.current_screen = CurrentScreen::Main;
+                        }
+
+                        KeyCode::Enter => {
+                            app.compress();
+                        }
+
+                        KeyCode::Tab | KeyCode::Down => {
+                            app.next_compress_format();
+                        }
+
+                        KeyCode::BackTab | KeyCode::Up => {
+                            app.previous_compress_format();
+                        }
+
+                        KeyCode::Backspace => {
+                            app.new_file.pop();
+                        }
+
+                        KeyCode::Char(c) => {
+                            app.new_file.push(c);
+                        }
+
+                        _ => {}
+                    }
+
+                    CurrentScreen::ExtractTarget => match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => {
+                            app.current_screen = CurrentScreen::Main;
+                        }
+
+                        KeyCode::Up | KeyCode::Down => {
+                            let next = match app.extract_list_state.selected() {
+                                Some(0) => 1,
+                                _ => 0,
+                            };
+                            app.extract_list_state.select(Some(next));
+                        }
+
+                        KeyCode::Enter => {
+                            app.extract();
+                        }
+
+                        _ => {}
+                    }
+
+                    CurrentScreen::OpenWith => match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => {
+                            app.current_screen = CurrentScreen::Main;
+                        }
+
+                        KeyCode::Up => {
+                            let index = app.open_with_state.selected().unwrap_or(0);
+                            app.open_with_state.select(Some(index.saturating_sub(1)));
+                        }
+
+                        KeyCode::Down => {
+                            let index = app.open_with_state.selected().unwrap_or(0);
+                            app.open_with_state.select(Some((index + 1).min(app.open_with.len().saturating_sub(1))));
+                        }
+
+                        KeyCode::Enter => {
+                            app.choose_open_with();
+                        }
+
+                        _ => {}
+                    }
+
+                    CurrentScreen::CommandPrompt => match key.code {
+                        KeyCode::Esc => {
+                            app.current_screen = CurrentScreen::Main;
+                        }
+
+                        KeyCode::Enter => {
+                            app.run_command();
+                        }
+
+                        KeyCode::Backspace => {
+                            app.command_input.pop();
+                        }
+
+                        KeyCode::Char(c) => {
+                            app.command_input.push(c);
+                        }
+
+                        _ => {}
+                    }
+
+                    CurrentScreen::CommandOutput => {
+                        let command = match app.shell_command.as_mut() {
+                            Some(command) => command,
+                            None => continue,
+                        };
+                        match key.code {
+                            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                app.cancel_command();
+                            }
+
+                            // A running command keeps running once the output is closed, 'O' shows it again
+                            KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => {
+                                app.current_screen = CurrentScreen::Main;
+                            }
+
+                            KeyCode::Down => {
+                                command.down(1);
+                            }
+
+                            KeyCode::Up => {
+                                command.up(1);
+                            }
+
+                            KeyCode::PageDown => {
+                                let height = command.height;
+                                command.down(height);
+                            }
+
+                            KeyCode::PageUp => {
+                                let height = command.height;
+                                command.up(height);
+                            }
+
+                            KeyCode::Home | KeyCode::Char('g') => {
+                                command.first();
+                            }
+
+                            KeyCode::End | KeyCode::Char('G') => {
+                                command.last();
+                            }
+
+                            _ => {}
+                        }
+                    }
+
+                    CurrentScreen::Edit => {
+                        let editor = match app.editor.as_mut() {
+                            Some(editor) => editor,
+                            None => continue,
+                        };
+                        match key.code {
+                            KeyCode::Enter => {
+                                editor.insert_newline();
+                            }
+
+                            KeyCode::Backspace => {
+                                editor.backspace();
+                            }
+
+                            KeyCode::Delete => {
+                                editor.delete();
+                            }
+
+                            KeyCode::Tab => {
+                                editor.insert('\t');
+                            }
+
+                            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                editor.insert(c);
+                            }
+
+                            _ => {}
+                        }
+                    }
+
+                    CurrentScreen::Palette => {
+                        let palette = match app.palette.as_mut() {
+                            Some(palette) => palette,
+                            None => continue,
+                        };
+                        match key.code {
+                            KeyCode::Esc => {
+                                app.close_palette();
+                            }
+
+                            KeyCode::Enter => {
+                                app.run_palette();
+                            }
+
+                            KeyCode::Up => {
+                                palette.up();
+                            }
+
+                            KeyCode::Down => {
+                                palette.down();
+                            }
+
+                            KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                palette.up();
+                            }
+
+                            KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                palette.down();
+                            }
+
+                            KeyCode::Backspace => {
+                                palette.pop();
+                            }
+
+                            KeyCode::Char(c) => {
+                                palette.push(c);
+                            }
+
+                            _ => {}
+                        }
+                    }
+
+                    CurrentScreen::EditSearch => {
+                        let editor = match app.editor.as_mut() {
+                            Some(editor) => editor,
+                            None => continue,
+                        };
+                        match key.code {
+                            KeyCode::Esc => {
+                                app.current_screen = CurrentScreen::Edit;
+                            }
+
+                            KeyCode::Enter => {
+                                editor.find_next();
+                                app.current_screen = CurrentScreen::Edit;
+                            }
+
+                            KeyCode::Backspace => {
+                                editor.search_input.pop();
+                            }
+
+                            KeyCode::Delete => {
+                                editor.search_input.clear();
+                            }
+
+                            KeyCode::Char(c) => {
+                                editor.search_input.push(c);
+                            }
+
+                            _ => {}
+                        }
+                    }
+
+                    CurrentScreen::ConfirmEditExit => match key.code {
+                        KeyCode::Char('y') if app.save_editor() => {
+                            app.close_editor(true);
+                        }
+
+                        KeyCode::Char('n') => {
+                            app.close_editor(true);
+                        }
+
+                        _ => {
+                            app.current_screen = CurrentScreen::Edit;
+                        }
+                    },
+
                     CurrentScreen::ConfirmCopyingPopUp => match key.code {
                         KeyCode::Char('y') => {
                             app.current_screen = CurrentScreen::CopyingProgressBar;
@@ -354,6 +646,10 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
                     }
 
                     CurrentScreen::Help => match key.code {
+                        KeyCode::Down => app.help_scroll = app.help_scroll.saturating_add(1),
+                        KeyCode::Up => app.help_scroll = app.help_scroll.saturating_sub(1),
+                        KeyCode::PageDown => app.help_scroll = app.help_scroll.saturating_add(10),
+                        KeyCode::PageUp => app.help_scroll = app.help_scroll.saturating_sub(10),
                         _ => {
                             app.current_screen = CurrentScreen::Main;
                         }
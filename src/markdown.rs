@@ -0,0 +1,350 @@
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+/// Extensions of the files rendered as Markdown in the preview
+pub const MARKDOWN_EXTENSIONS: [&str; 3] = ["md", "markdown", "mdown"];
+
+/// Cells of a table being rendered, the table is only drawn at its end when all column widths are known
+struct TableState {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<Vec<Span<'static>>>>,
+    cell: Vec<Span<'static>>,
+    header_rows: usize,
+}
+
+/// Turns the events of the Markdown parser into styled lines
+struct Renderer {
+    lines: Vec<Line<'static>>,
+    spans: Vec<Span<'static>>, // the line being built
+    styles: Vec<Style>, // the inline styles that are open, the last one is applied to new text
+    lists: Vec<Option<u64>>, // the open lists, with the next number of the ordered ones
+    bullet: Option<String>, // the bullet of a list item that still has to be written
+    quote_depth: usize,
+    code_block: bool,
+    links: Vec<String>, // the destinations of the open links and images
+    table: Option<TableState>,
+}
+
+/// Render Markdown source as styled text
+pub fn render(source: &str) -> Text<'static> {
+    let mut renderer = Renderer {
+        lines: Vec::new(),
+        spans: Vec::new(),
+        styles: Vec::new(),
+        lists: Vec::new(),
+        bullet: None,
+        quote_depth: 0,
+        code_block: false,
+        links: Vec::new(),
+        table: None,
+    };
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    for event in Parser::new_ext(source, options) {
+        renderer.event(event);
+    }
+    renderer.flush_line();
+    while let Some(true) = renderer.lines.last().map(|line| line.spans.is_empty()) {
+        renderer.lines.pop();
+    }
+    Text::from(renderer.lines)
+}
+
+impl Renderer {
+    fn style(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+
+    fn push_style(&mut self, style: Style) {
+        self.styles.push(self.style().patch(style));
+    }
+
+    /// Spans written at the start of every line, for block quotes and list indentation
+    fn line_prefix(&mut self) -> Vec<Span<'static>> {
+        let mut prefix = Vec::new();
+        for _ in 0..self.quote_depth {
+            prefix.push(Span::styled("▌ ", Style::default().fg(Color::DarkGray)));
+        }
+        if !self.lists.is_empty() {
+            let indent = "  ".repeat(self.lists.len() - 1);
+            match self.bullet.take() {
+                Some(bullet) => prefix.push(Span::styled(
+                    format!("{}{}", indent, bullet),
+                    Style::default().fg(Color::Yellow),
+                )),
+                None => prefix.push(Span::raw(format!("{}  ", indent))),
+            }
+        }
+        prefix
+    }
+
+    fn push_span(&mut self, text: String, style: Style) {
+        if let Some(table) = self.table.as_mut() {
+            table.cell.push(Span::styled(text, style));
+            return;
+        }
+        if self.spans.is_empty() {
+            self.spans = self.line_prefix();
+        }
+        self.spans.push(Span::styled(text, style));
+    }
+
+    fn flush_line(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+        self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+    }
+
+    /// Separate blocks with an empty line, without stacking several of them
+    fn blank_line(&mut self) {
+        self.flush_line();
+        match self.lines.last() {
+            Some(line) if !line.spans.is_empty() => self.lines.push(Line::default()),
+            _ => {}
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if self.code_block {
+                    for line in text.lines() {
+                        self.push_span("│ ".to_string(), Style::default().fg(Color::DarkGray));
+                        self.push_span(line.replace('\t', "    "), Style::default().fg(Color::Green));
+                        self.flush_line();
+                    }
+                } else {
+                    self.push_span(text.to_string(), self.style());
+                }
+            }
+            Event::Code(code) => {
+                self.push_span(code.to_string(), self.style().fg(Color::Yellow));
+            }
+            Event::InlineMath(math) | Event::DisplayMath(math) => {
+                self.push_span(math.to_string(), self.style().fg(Color::Yellow));
+            }
+            Event::Html(html) | Event::InlineHtml(html) => {
+                self.push_span(html.trim_end().to_string(), Style::default().fg(Color::DarkGray));
+            }
+            Event::FootnoteReference(name) => {
+                self.push_span(format!("[^{}]", name), Style::default().fg(Color::Blue));
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if self.table.is_some() {
+                    self.push_span(" ".to_string(), self.style());
+                } else {
+                    self.flush_line();
+                }
+            }
+            Event::Rule => {
+                self.flush_line();
+                self.push_span("─".repeat(40), Style::default().fg(Color::DarkGray));
+                self.blank_line();
+            }
+            Event::TaskListMarker(checked) => {
+                let marker = match checked {
+                    true => "[x] ",
+                    false => "[ ] ",
+                };
+                self.push_span(marker.to_string(), Style::default().fg(Color::Yellow));
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush_line();
+                let style = match level {
+                    HeadingLevel::H1 => Style::default()
+                        .fg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+                    HeadingLevel::H2 => Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                    _ => Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+                };
+                self.push_style(style);
+            }
+            Tag::BlockQuote(_) => {
+                self.flush_line();
+                self.quote_depth += 1;
+                self.push_style(Style::default().add_modifier(Modifier::ITALIC));
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush_line();
+                self.code_block = true;
+                if let CodeBlockKind::Fenced(language) = kind {
+                    if !language.is_empty() {
+                        self.push_span(format!("╭ {}", language), Style::default().fg(Color::DarkGray));
+                        self.flush_line();
+                    }
+                }
+            }
+            Tag::List(start) => {
+                self.flush_line();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush_line();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.bullet = Some(bullet);
+            }
+            Tag::Table(alignments) => {
+                self.flush_line();
+                self.table = Some(TableState {
+                    alignments,
+                    rows: Vec::new(),
+                    cell: Vec::new(),
+                    header_rows: 0,
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = self.table.as_mut() {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(table) = self.table.as_mut() {
+                    table.cell.clear();
+                }
+            }
+            Tag::Emphasis => self.push_style(Style::default().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.push_style(Style::default().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self.push_style(Style::default().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link { dest_url, .. } => {
+                self.links.push(dest_url.to_string());
+                self.push_style(Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED));
+            }
+            Tag::Image { dest_url, .. } => {
+                self.links.push(dest_url.to_string());
+                self.push_span("[image: ".to_string(), Style::default().fg(Color::DarkGray));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush_line();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.blank_line();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.styles.pop();
+                self.flush_line();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.blank_line();
+            }
+            TagEnd::CodeBlock => {
+                self.code_block = false;
+                self.blank_line();
+            }
+            TagEnd::List(_) => {
+                self.flush_line();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            TagEnd::Item => {
+                self.flush_line();
+                self.bullet = None;
+            }
+            TagEnd::TableHead => {
+                if let Some(table) = self.table.as_mut() {
+                    table.header_rows = table.rows.len();
+                }
+            }
+            TagEnd::TableCell => {
+                if let Some(table) = self.table.as_mut() {
+                    let cell = std::mem::take(&mut table.cell);
+                    if let Some(row) = table.rows.last_mut() {
+                        row.push(cell);
+                    }
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.render_table(table);
+                }
+                self.blank_line();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                if let Some(url) = self.links.pop() {
+                    if !url.starts_with('#') {
+                        self.push_span(format!(" ({})", url), Style::default().fg(Color::DarkGray));
+                    }
+                }
+            }
+            TagEnd::Image => {
+                let url = self.links.pop().unwrap_or_default();
+                self.push_span(format!("] ({})", url), Style::default().fg(Color::DarkGray));
+            }
+            _ => {}
+        }
+    }
+
+    fn render_table(&mut self, table: TableState) {
+        let columns = table.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in table.rows.iter() {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(cell_width(cell));
+            }
+        }
+        let border = Style::default().fg(Color::DarkGray);
+        for (index, row) in table.rows.into_iter().enumerate() {
+            let header = index < table.header_rows;
+            let mut spans = self.line_prefix();
+            for (column, width) in widths.iter().enumerate() {
+                if column > 0 {
+                    spans.push(Span::styled(" │ ", border));
+                }
+                let cell = row.get(column).cloned().unwrap_or_default();
+                let padding = width - cell_width(&cell);
+                let (left, right) = match table.alignments.get(column) {
+                    Some(Alignment::Right) => (padding, 0),
+                    Some(Alignment::Center) => (padding / 2, padding - padding / 2),
+                    _ => (0, padding),
+                };
+                spans.push(Span::raw(" ".repeat(left)));
+                for span in cell {
+                    match header {
+                        true => spans.push(span.patch_style(Style::default().add_modifier(Modifier::BOLD))),
+                        false => spans.push(span),
+                    }
+                }
+                spans.push(Span::raw(" ".repeat(right)));
+            }
+            self.lines.push(Line::from(spans));
+            if header && index + 1 == table.header_rows {
+                let separator: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                let mut spans = self.line_prefix();
+                spans.push(Span::styled(separator.join("─┼─"), border));
+                self.lines.push(Line::from(spans));
+            }
+        }
+    }
+}
+
+fn cell_width(cell: &[Span]) -> usize {
+    cell.iter().map(|span| span.content.chars().count()).sum()
+}
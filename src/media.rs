@@ -0,0 +1,787 @@
+use crate::files::format_size;
+use exif::{In, Reader, Tag, Value};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, CODEC_TYPE_OPUS};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+/// Extensions of the photos whose size and EXIF data are shown in the preview
+pub const IMAGE_EXTENSIONS: [&str; 10] = ["jpg", "jpeg", "png", "tif", "tiff", "webp", "heic", "heif", "gif", "bmp"];
+
+/// Extensions of the audio files whose tags and stream are shown in the preview
+pub const AUDIO_EXTENSIONS: [&str; 12] = [
+    "mp3", "flac", "ogg", "oga", "opus", "wav", "aif", "aiff", "m4a", "aac", "caf", "mka",
+];
+
+/// Extensions of the video files whose container and streams are shown in the preview
+pub const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "m4v", "mov", "mkv", "webm"];
+
+/// Largest MP4 `moov` box or Matroska header element read into memory
+const HEADER_READ_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// Kind of media file, picked from the extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+    Video,
+}
+
+impl MediaKind {
+    pub fn from_path(path: &Path) -> Option<MediaKind> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            Some(MediaKind::Image)
+        } else if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+            Some(MediaKind::Audio)
+        } else if VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            Some(MediaKind::Video)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MediaKind::Image => "image",
+            MediaKind::Audio => "audio",
+            MediaKind::Video => "video",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Read the metadata of the photo, audio or video file at `path`
+pub fn summary(path: &Path) -> Result<Text<'static>, String> {
+    let kind = match MediaKind::from_path(path) {
+        Some(kind) => kind,
+        None => return Err("Not a media file".to_string()),
+    };
+    let file_size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let mut lines = vec![field("Size", format_size(file_size))];
+    match kind {
+        MediaKind::Image => image_summary(path, &mut lines),
+        MediaKind::Audio => audio_summary(path, file_size, &mut lines)?,
+        MediaKind::Video => video_summary(path, file_size, &mut lines)?,
+    }
+    Ok(Text::from(lines))
+}
+
+fn field(name: &str, value: String) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{:<14}", name), Style::default().fg(Color::DarkGray)),
+        Span::raw(value),
+    ])
+}
+
+fn heading(title: String) -> Line<'static> {
+    Line::styled(title, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+}
+
+fn note(text: String) -> Line<'static> {
+    Line::styled(text, Style::default().fg(Color::DarkGray))
+}
+
+/// Duration as h:mm:ss, m:ss when it is shorter than an hour or in seconds when shorter than a minute
+fn format_duration(seconds: f64) -> String {
+    if seconds < 60.0 {
+        return format!("{:.1}s", seconds);
+    }
+    let total = seconds.round() as u64;
+    match total / 3600 {
+        0 => format!("{}:{:02}", total / 60, total % 60),
+        hours => format!("{}:{:02}:{:02}", hours, total / 60 % 60, total % 60),
+    }
+}
+
+/// Average bitrate of a file of `size` bytes playing for `seconds`
+fn format_bitrate(size: u64, seconds: f64) -> String {
+    format!("{:.0} kb/s", size as f64 * 8.0 / seconds / 1000.0)
+}
+
+/*IMAGES */
+
+fn image_summary(path: &Path, lines: &mut Vec<Line<'static>>) {
+    let mut dimensions = match imagesize::size(path) {
+        Ok(size) => Some(format!("{}x{}", size.width, size.height)),
+        Err(_) => None,
+    };
+    let exif = match File::open(path) {
+        Ok(file) => Reader::new().read_from_container(&mut BufReader::new(file)),
+        Err(e) => Err(exif::Error::Io(e)),
+    };
+    let exif = match exif {
+        Ok(exif) => exif,
+        Err(e) => {
+            if let Some(dimensions) = dimensions {
+                lines.push(field("Dimensions", dimensions));
+            }
+            lines.push(Line::default());
+            match e {
+                exif::Error::NotFound(_) => lines.push(note("No EXIF data".to_string())),
+                e => lines.push(note(format!("Invalid EXIF data: {}", e))),
+            }
+            return;
+        }
+    };
+    let text = |tag: Tag| exif_text(&exif, tag);
+
+    if dimensions.is_none() {
+        if let (Some(width), Some(height)) = (text(Tag::PixelXDimension), text(Tag::PixelYDimension)) {
+            dimensions = Some(format!("{}x{}", width, height));
+        }
+    }
+    if let Some(dimensions) = dimensions {
+        lines.push(field("Dimensions", dimensions));
+    }
+    let camera = match (text(Tag::Make), text(Tag::Model)) {
+        // Most models already start with the name of the maker
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    let fields = [
+        ("Camera", camera),
+        ("Lens", text(Tag::LensModel)),
+        ("Taken", text(Tag::DateTimeOriginal).or_else(|| text(Tag::DateTime))),
+        ("Exposure", text(Tag::ExposureTime)),
+        ("Aperture", text(Tag::FNumber)),
+        ("ISO", text(Tag::PhotographicSensitivity)),
+        ("Focal length", text(Tag::FocalLength)),
+        ("Orientation", text(Tag::Orientation)),
+        ("Software", text(Tag::Software)),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            lines.push(field(name, value));
+        }
+    }
+    let latitude = gps_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef);
+    let longitude = gps_coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef);
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        lines.push(field("GPS", format!("{:.6}, {:.6}", latitude, longitude)));
+        if let Some(altitude) = text(Tag::GPSAltitude) {
+            lines.push(field("Altitude", altitude));
+        }
+    }
+    lines.push(Line::default());
+    lines.push(note(format!("{} EXIF fields", exif.fields().len())));
+}
+
+/// Value of an EXIF field as text, strings are shown without the quotes the exif crate adds
+fn exif_text(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let text = match &field.value {
+        Value::Ascii(values) => String::from_utf8_lossy(values.first()?).trim().to_string(),
+        _ => field.display_value().with_unit(exif).to_string(),
+    };
+    match text.is_empty() {
+        true => None,
+        false => Some(text),
+    }
+}
+
+/// A GPS coordinate in decimal degrees, negative to the south and west
+fn gps_coordinate(exif: &exif::Exif, tag: Tag, reference: Tag) -> Option<f64> {
+    let parts = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(parts) if parts.len() == 3 => parts.clone(),
+        _ => return None,
+    };
+    let degrees = parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0;
+    match exif_text(exif, reference).as_deref() {
+        Some("S") | Some("W") => Some(-degrees),
+        _ => Some(degrees),
+    }
+}
+
+/*AUDIO */
+
+/// Tags shown in the preview, with the label they are shown with
+const TAGS: [(StandardTagKey, &str); 7] = [
+    (StandardTagKey::TrackTitle, "Title"),
+    (StandardTagKey::Artist, "Artist"),
+    (StandardTagKey::Album, "Album"),
+    (StandardTagKey::AlbumArtist, "Album artist"),
+    (StandardTagKey::Date, "Date"),
+    (StandardTagKey::Genre, "Genre"),
+    (StandardTagKey::TrackNumber, "Track"),
+];
+
+fn audio_summary(path: &Path, file_size: u64, lines: &mut Vec<Line<'static>>) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension() {
+        hint.with_extension(&extension.to_string_lossy());
+    }
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| format!("Unsupported audio file: {}", e))?;
+
+    let track = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .map(|track| track.codec_params.clone());
+    let mut duration = None;
+    if let Some(params) = &track {
+        let codec = match symphonia::default::get_codecs().get_codec(params.codec) {
+            Some(descriptor) => descriptor.short_name.to_string(),
+            None if params.codec == CODEC_TYPE_OPUS => "opus".to_string(),
+            None => "unknown".to_string(),
+        };
+        lines.push(field("Codec", codec));
+        if let Some(sample_rate) = params.sample_rate {
+            lines.push(field("Sample rate", format!("{} Hz", sample_rate)));
+        }
+        if let Some(channels) = params.channels {
+            lines.push(field("Channels", channels.count().to_string()));
+        }
+        if let Some(bits) = params.bits_per_sample {
+            lines.push(field("Bit depth", format!("{} bits", bits)));
+        }
+        duration = match (params.time_base, params.n_frames, params.sample_rate) {
+            (Some(time_base), Some(frames), _) => {
+                let time = time_base.calc_time(frames);
+                Some(time.seconds as f64 + time.frac)
+            }
+            (None, Some(frames), Some(sample_rate)) => Some(frames as f64 / sample_rate as f64),
+            _ => None,
+        };
+    }
+    if let Some(seconds) = duration.filter(|seconds| *seconds > 0.0) {
+        lines.push(field("Duration", format_duration(seconds)));
+        lines.push(field("Bitrate", format_bitrate(file_size, seconds)));
+    }
+
+    // Tags can be found before the stream, like ID3 in MP3 files, or inside the container
+    let mut tags = Vec::new();
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            collect_tags(revision, &mut tags);
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        collect_tags(revision, &mut tags);
+    }
+    lines.push(Line::default());
+    lines.push(heading("Tags".to_string()));
+    if tags.is_empty() {
+        lines.push(note("  none".to_string()));
+    }
+    for (name, value) in tags {
+        lines.push(field(&format!("  {}", name), value));
+    }
+    Ok(())
+}
+
+fn collect_tags(revision: &MetadataRevision, tags: &mut Vec<(&'static str, String)>) {
+    for (key, name) in TAGS {
+        if tags.iter().any(|(found, _)| *found == name) {
+            continue;
+        }
+        let tag = revision.tags().iter().find(|tag| tag.std_key == Some(key));
+        if let Some(tag) = tag {
+            // RIFF INFO strings keep their NUL terminators
+            let value = tag.value.to_string().trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string();
+            if !value.is_empty() {
+                tags.push((name, value));
+            }
+        }
+    }
+    // Keep the order of TAGS whatever the revision the tags came from
+    tags.sort_by_key(|(name, _)| TAGS.iter().position(|(_, label)| label == name));
+}
+
+/*VIDEO */
+
+/// A stream of a video container
+struct Stream {
+    kind: &'static str,
+    codec: String,
+    details: Option<String>, // the dimensions of a video or the sample rate and channels of an audio stream
+    language: Option<String>,
+}
+
+/// What the header of a video container tells about it
+struct Container {
+    format: String,
+    duration: Option<f64>,
+    streams: Vec<Stream>,
+}
+
+fn video_summary(path: &Path, file_size: u64, lines: &mut Vec<Line<'static>>) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic).map_err(|_| "Not a video file".to_string())?;
+    let container = if &magic[4..8] == b"ftyp" {
+        read_mp4(&mut file)?
+    } else if magic[..4] == [0x1A, 0x45, 0xDF, 0xA3] {
+        read_matroska(&mut file)?
+    } else {
+        return Err("Unknown video container".to_string());
+    };
+
+    lines.push(field("Container", container.format));
+    if let Some(seconds) = container.duration.filter(|seconds| *seconds > 0.0) {
+        lines.push(field("Duration", format_duration(seconds)));
+        lines.push(field("Bitrate", format_bitrate(file_size, seconds)));
+    }
+    lines.push(Line::default());
+    lines.push(heading(format!("Streams ({})", container.streams.len())));
+    for (index, stream) in container.streams.iter().enumerate() {
+        let mut spans = vec![
+            Span::styled(format!("  #{} ", index), Style::default().fg(Color::DarkGray)),
+            Span::styled(format!("{:<9}", stream.kind), Style::default().fg(Color::Yellow)),
+            Span::styled(stream.codec.clone(), Style::default().fg(Color::Green)),
+        ];
+        if let Some(details) = &stream.details {
+            spans.push(Span::raw(format!(" {}", details)));
+        }
+        if let Some(language) = &stream.language {
+            spans.push(Span::styled(format!(" [{}]", language), Style::default().fg(Color::DarkGray)));
+        }
+        lines.push(Line::from(spans));
+    }
+    Ok(())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// Read `size` bytes at `offset`, refusing headers too big to be real
+fn read_at(file: &mut File, offset: u64, size: u64) -> Result<Vec<u8>, String> {
+    if size > HEADER_READ_LIMIT {
+        return Err(format!("Container header is too big ({})", format_size(size)));
+    }
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    file.take(size).read_to_end(&mut data).map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+/// The boxes of MP4 data, with their type and body
+fn mp4_boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+    while let (Some(size), Some(kind)) = (read_u32(data, offset), data.get(offset + 4..offset + 8)) {
+        let (header, size) = match size {
+            0 => (8, (data.len() - offset) as u64),
+            1 => (16, read_u64(data, offset + 8).unwrap_or(0)),
+            size => (8, size as u64),
+        };
+        if size < header as u64 || offset as u64 + size > data.len() as u64 {
+            break;
+        }
+        boxes.push((kind, &data[offset + header..offset + size as usize]));
+        offset += size as usize;
+    }
+    boxes
+}
+
+fn mp4_child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    mp4_boxes(data).into_iter().find(|(found, _)| *found == kind).map(|(_, body)| body)
+}
+
+/// Time scale and duration of a `mvhd` or `mdhd` box, in seconds
+fn mp4_duration(body: &[u8]) -> Option<f64> {
+    let (timescale, duration) = match body.first()? {
+        1 => (read_u32(body, 20)?, read_u64(body, 24)?),
+        _ => (read_u32(body, 12)?, read_u32(body, 16)? as u64),
+    };
+    match timescale {
+        0 => None,
+        _ => Some(duration as f64 / timescale as f64),
+    }
+}
+
+/// Only the top level boxes are walked on disk, the `moov` box describing the streams is read whole
+fn read_mp4(file: &mut File) -> Result<Container, String> {
+    let length = file.metadata().map_err(|e| e.to_string())?.len();
+    let mut offset: u64 = 0;
+    let mut brand = String::new();
+    let mut moov = None;
+    while offset.saturating_add(8) <= length {
+        let header = read_at(file, offset, 16)?;
+        let (header_size, size) = match read_u32(&header, 0) {
+            Some(0) => (8, length - offset),
+            Some(1) => (16, read_u64(&header, 8).unwrap_or(0)),
+            Some(size) => (8, size as u64),
+            None => break,
+        };
+        if size < header_size {
+            break;
+        }
+        // The file can be shorter than its boxes say
+        let kind = match header.get(4..8) {
+            Some(kind) => kind,
+            None => break,
+        };
+        match kind {
+            b"ftyp" => {
+                if let Some(major) = header.get(8..12) {
+                    brand = String::from_utf8_lossy(major).trim().to_string();
+                }
+            }
+            b"moov" => {
+                moov = Some(read_at(file, offset + header_size, size - header_size)?);
+                break;
+            }
+            _ => {}
+        }
+        offset = offset.saturating_add(size);
+    }
+    let moov = match moov {
+        Some(moov) => moov,
+        None => return Err("MP4 file without a movie header".to_string()),
+    };
+
+    let mut streams = Vec::new();
+    for (kind, trak) in mp4_boxes(&moov) {
+        if kind != b"trak" {
+            continue;
+        }
+        let mdia = match mp4_child(trak, b"mdia") {
+            Some(mdia) => mdia,
+            None => continue,
+        };
+        let handler = mp4_child(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)).unwrap_or_default();
+        let kind = match handler {
+            b"vide" => "video",
+            b"soun" => "audio",
+            b"subt" | b"sbtl" | b"text" => "subtitle",
+            _ => "data",
+        };
+        let language = mp4_child(mdia, b"mdhd").and_then(mp4_language);
+        let entry = mp4_child(mdia, b"minf")
+            .and_then(|minf| mp4_child(minf, b"stbl"))
+            .and_then(|stbl| mp4_child(stbl, b"stsd"))
+            .and_then(|stsd| mp4_boxes(stsd.get(8..)?).into_iter().next());
+        let (codec, details) = match entry {
+            Some((fourcc, entry)) => {
+                let fourcc = String::from_utf8_lossy(fourcc).to_string();
+                let details = match kind {
+                    "video" => match (read_u16(entry, 24), read_u16(entry, 26)) {
+                        (Some(width), Some(height)) => Some(format!("{}x{}", width, height)),
+                        _ => None,
+                    },
+                    "audio" => match (read_u32(entry, 24), read_u16(entry, 16)) {
+                        (Some(rate), Some(channels)) => Some(format!("{} Hz, {} channels", rate >> 16, channels)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                (mp4_codec(&fourcc), details)
+            }
+            None => ("unknown".to_string(), None),
+        };
+        streams.push(Stream {
+            kind,
+            codec,
+            details,
+            language,
+        });
+    }
+    let format = match brand.as_str() {
+        "qt" => "QuickTime".to_string(),
+        "" => "MP4".to_string(),
+        brand => format!("MP4 ({})", brand),
+    };
+    Ok(Container {
+        format,
+        duration: mp4_child(&moov, b"mvhd").and_then(mp4_duration),
+        streams,
+    })
+}
+
+/// Language of a `mdhd` box, three letters packed in 5 bits each
+fn mp4_language(mdhd: &[u8]) -> Option<String> {
+    let offset = match mdhd.first()? {
+        1 => 32,
+        _ => 20,
+    };
+    let packed = read_u16(mdhd, offset)?;
+    let language: String = [10, 5, 0]
+        .iter()
+        .map(|shift| (((packed >> shift) & 0x1F) as u8 + 0x60) as char)
+        .collect();
+    match language.as_str() {
+        "und" => None,
+        _ if language.chars().all(|letter| letter.is_ascii_lowercase()) => Some(language),
+        _ => None,
+    }
+}
+
+fn mp4_codec(fourcc: &str) -> String {
+    let codec = match fourcc {
+        "avc1" | "avc3" => "h264",
+        "hvc1" | "hev1" => "hevc",
+        "av01" => "av1",
+        "vp08" => "vp8",
+        "vp09" => "vp9",
+        "mp4v" => "mpeg4",
+        "mp4a" => "aac",
+        "Opus" => "opus",
+        "fLaC" => "flac",
+        "ac-3" => "ac3",
+        "ec-3" => "eac3",
+        ".mp3" => "mp3",
+        "alac" => "alac",
+        codec => codec,
+    };
+    codec.trim().to_string()
+}
+
+/// A variable length integer of EBML and its length, the length marker is kept in element IDs
+fn ebml_vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let length = first.leading_zeros() as usize + 1;
+    if length > 8 || data.len() < length {
+        return None;
+    }
+    let mut value = match keep_marker {
+        true => first as u64,
+        false => first as u64 & (0xFF >> length),
+    };
+    for byte in &data[1..length] {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, length))
+}
+
+/// ID, size and header length of the EBML element at the start of `data`, the size is None when unknown
+fn ebml_header(data: &[u8]) -> Option<(u64, Option<u64>, usize)> {
+    let (id, id_length) = ebml_vint(data, true)?;
+    let (size, size_length) = ebml_vint(&data[id_length..], false)?;
+    let unknown = size == (1 << (7 * size_length)) - 1;
+    Some((id, Some(size).filter(|_| !unknown), id_length + size_length))
+}
+
+/// The child elements of EBML data, with their ID and body
+fn ebml_elements(data: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut elements = Vec::new();
+    let mut offset = 0;
+    while let Some((id, size, header)) = ebml_header(&data[offset..]) {
+        let start = offset + header;
+        let end = match size {
+            Some(size) => start.saturating_add(size as usize).min(data.len()),
+            None => data.len(),
+        };
+        elements.push((id, &data[start..end]));
+        offset = end;
+    }
+    elements
+}
+
+fn ebml_child(data: &[u8], id: u64) -> Option<&[u8]> {
+    ebml_elements(data).into_iter().find(|(found, _)| *found == id).map(|(_, body)| body)
+}
+
+fn ebml_uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+fn ebml_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn ebml_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches('\0').to_string()
+}
+
+const EBML_HEADER: u64 = 0x1A45DFA3;
+const EBML_DOC_TYPE: u64 = 0x4282;
+const MKV_SEGMENT: u64 = 0x18538067;
+const MKV_INFO: u64 = 0x1549A966;
+const MKV_TIMESTAMP_SCALE: u64 = 0x2AD7B1;
+const MKV_DURATION: u64 = 0x4489;
+const MKV_TRACKS: u64 = 0x1654AE6B;
+const MKV_TRACK_ENTRY: u64 = 0xAE;
+const MKV_TRACK_TYPE: u64 = 0x83;
+const MKV_CODEC_ID: u64 = 0x86;
+const MKV_LANGUAGE: u64 = 0x22B59C;
+const MKV_VIDEO: u64 = 0xE0;
+const MKV_PIXEL_WIDTH: u64 = 0xB0;
+const MKV_PIXEL_HEIGHT: u64 = 0xBA;
+const MKV_AUDIO: u64 = 0xE1;
+const MKV_SAMPLING_FREQUENCY: u64 = 0xB5;
+const MKV_CHANNELS: u64 = 0x9F;
+const MKV_CLUSTER: u64 = 0x1F43B675;
+
+/// The children of the segment are walked on disk until the clusters holding the frames, only the
+/// info and tracks elements are read
+fn read_matroska(file: &mut File) -> Result<Container, String> {
+    let length = file.metadata().map_err(|e| e.to_string())?.len();
+    let invalid = || "Invalid Matroska file".to_string();
+    let header = read_at(file, 0, 64)?;
+    let (id, size, header_length) = ebml_header(&header).ok_or_else(invalid)?;
+    let ebml_size = size.ok_or_else(invalid)?;
+    if id != EBML_HEADER {
+        return Err(invalid());
+    }
+    let ebml = read_at(file, header_length as u64, ebml_size)?;
+    let format = match ebml_child(&ebml, EBML_DOC_TYPE).map(ebml_string).as_deref() {
+        Some("webm") => "WebM",
+        _ => "Matroska",
+    };
+
+    let mut offset = header_length as u64 + ebml_size;
+    let segment = read_at(file, offset, 12)?;
+    let (id, _, segment_header) = ebml_header(&segment).ok_or_else(invalid)?;
+    if id != MKV_SEGMENT {
+        return Err(invalid());
+    }
+    offset += segment_header as u64;
+    let mut info = None;
+    let mut tracks = None;
+    while offset < length && (info.is_none() || tracks.is_none()) {
+        let header = read_at(file, offset, 12)?;
+        let (id, size, header_length) = match ebml_header(&header) {
+            Some(header) => header,
+            None => break,
+        };
+        let size = match size {
+            Some(size) => size,
+            None => break,
+        };
+        match id {
+            MKV_INFO => info = Some(read_at(file, offset + header_length as u64, size)?),
+            MKV_TRACKS => tracks = Some(read_at(file, offset + header_length as u64, size)?),
+            MKV_CLUSTER if info.is_some() && tracks.is_some() => break,
+            _ => {}
+        }
+        offset += header_length as u64 + size;
+    }
+
+    let duration = info.as_ref().and_then(|info| {
+        let scale = ebml_child(info, MKV_TIMESTAMP_SCALE).map(ebml_uint).unwrap_or(1_000_000);
+        let duration = ebml_child(info, MKV_DURATION).and_then(ebml_float)?;
+        Some(duration * scale as f64 / 1_000_000_000.0)
+    });
+    let mut streams = Vec::new();
+    for (id, entry) in ebml_elements(tracks.as_deref().unwrap_or_default()) {
+        if id != MKV_TRACK_ENTRY {
+            continue;
+        }
+        let kind = match ebml_child(entry, MKV_TRACK_TYPE).map(ebml_uint) {
+            Some(1) => "video",
+            Some(2) => "audio",
+            Some(17) => "subtitle",
+            _ => "data",
+        };
+        let details = match kind {
+            "video" => ebml_child(entry, MKV_VIDEO).and_then(|video| {
+                let width = ebml_uint(ebml_child(video, MKV_PIXEL_WIDTH)?);
+                let height = ebml_uint(ebml_child(video, MKV_PIXEL_HEIGHT)?);
+                Some(format!("{}x{}", width, height))
+            }),
+            "audio" => ebml_child(entry, MKV_AUDIO).map(|audio| {
+                let rate = ebml_child(audio, MKV_SAMPLING_FREQUENCY).and_then(ebml_float).unwrap_or(8000.0);
+                let channels = ebml_child(audio, MKV_CHANNELS).map(ebml_uint).unwrap_or(1);
+                format!("{} Hz, {} channels", rate.round(), channels)
+            }),
+            _ => None,
+        };
+        let codec = ebml_child(entry, MKV_CODEC_ID).map(ebml_string).unwrap_or_default();
+        streams.push(Stream {
+            kind,
+            codec: matroska_codec(&codec),
+            details,
+            language: ebml_child(entry, MKV_LANGUAGE).map(ebml_string).filter(|language| language != "und"),
+        });
+    }
+    Ok(Container {
+        format: format.to_string(),
+        duration,
+        streams,
+    })
+}
+
+fn matroska_codec(codec_id: &str) -> String {
+    let codec = match codec_id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_AV1" => "av1",
+        "V_VP8" => "vp8",
+        "V_VP9" => "vp9",
+        "V_THEORA" => "theora",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_FLAC" => "flac",
+        "A_MPEG/L3" => "mp3",
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_DTS" => "dts",
+        "S_TEXT/UTF8" => "srt",
+        "S_TEXT/ASS" | "S_TEXT/SSA" => "ass",
+        "S_HDMV/PGS" => "pgs",
+        codec if codec.starts_with("A_AAC") => "aac",
+        codec if codec.starts_with("A_PCM") => "pcm",
+        "" => "unknown",
+        codec => codec,
+    };
+    codec.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `read_mp4` on a file holding `data`
+    fn read_mp4_bytes(name: &str, data: &[u8]) -> Result<Container, String> {
+        let path = std::env::temp_dir().join(format!("stfm-media-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        let result = read_mp4(&mut File::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn short_ftyp_box() {
+        assert!(read_mp4_bytes("ftyp", b"\x00\x00\x00\x08ftyp").is_err());
+    }
+
+    #[test]
+    fn truncated_file() {
+        let mut data = b"\x00\x00\x00\x14ftypisom".to_vec();
+        assert!(read_mp4_bytes("truncated-ftyp", &data).is_err());
+        // A movie header announcing more than the file holds
+        data.extend_from_slice(b"\x00\x00\x00\x0cisom\x00\x00\x01\x00moov\x00\x00\x00\x40trak");
+        let container = read_mp4_bytes("truncated-moov", &data).unwrap();
+        assert!(container.streams.is_empty());
+        // A 64 bit size running past the end of the file
+        assert!(read_mp4_bytes("largesize", b"\x00\x00\x00\x01free\xff\xff\xff\xff\xff\xff\xff\xff").is_err());
+    }
+
+    #[test]
+    fn truncated_boxes() {
+        assert!(mp4_boxes(b"\x00\x00\x00\x20trak\x00\x00").is_empty());
+        assert!(mp4_boxes(b"\x00\x00\x00").is_empty());
+        assert!(mp4_boxes(b"\x00\x00\x00\x01free\x00\x00").is_empty());
+        let boxes = mp4_boxes(b"\x00\x00\x00\x0afree\x01\x02\x00\x00\x00\x08skip\x00\x00\x00\x30moov");
+        assert_eq!(boxes, vec![(&b"free"[..], &b"\x01\x02"[..]), (&b"skip"[..], &b""[..])]);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::{expand_home, OpenerConfig};
+use crate::previewer::{mime_matches, mime_type};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// How the command of a rule is run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpenMode {
+    Foreground, // the command takes over the terminal until it exits
+    Background, // the command runs as a job, an error is shown when it fails
+    Detach,     // the command is started and forgotten
+}
+
+/// An opener rule of the configuration, with its globs compiled
+#[derive(Debug)]
+pub struct Opener {
+    pub name: String,
+    pub mode: OpenMode,
+    words: Vec<String>, // the command split like a shell would
+    names: GlobSet,     // the globs matched against the name of the file
+    paths: GlobSet,     // the globs with a / matched against the path of the file
+    mime_types: Vec<String>,
+}
+
+impl Opener {
+    pub fn new(config: &OpenerConfig) -> Result<Opener, String> {
+        let words = shell_words::split(&config.command).map_err(|e| format!("opener \"{}\": {}", config.command, e))?;
+        if words.is_empty() {
+            return Err("an opener has an empty command".to_string());
+        }
+        let mode = match (config.foreground, config.detach) {
+            (true, true) => {
+                return Err(format!(
+                    "opener \"{}\" cannot run both in the foreground and detached",
+                    config.command
+                ))
+            }
+            (true, false) => OpenMode::Foreground,
+            (false, true) => OpenMode::Detach,
+            (false, false) => OpenMode::Background,
+        };
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for glob in &config.globs {
+            let pattern = expand_home(glob).to_string_lossy().to_string();
+            let compiled = GlobBuilder::new(&pattern)
+                .case_insensitive(true)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("opener \"{}\": {}", config.command, e))?;
+            match glob.contains('/') {
+                true => paths.add(compiled),
+                false => names.add(compiled),
+            };
+        }
+        let build = |set: GlobSetBuilder| set.build().map_err(|e| format!("opener \"{}\": {}", config.command, e));
+        Ok(Opener {
+            name: config.name.clone().unwrap_or_else(|| config.command.clone()),
+            mode,
+            words,
+            names: build(names)?,
+            paths: build(paths)?,
+            mime_types: config.mime_types.clone(),
+        })
+    }
+
+    /// If the rule opens the file at `path`, a rule without globs and MIME types opens every file.
+    /// The MIME type is detected for the first rule that needs it and kept in `mime` for the next ones
+    pub fn matches(&self, path: &Path, mime: &mut Option<String>) -> bool {
+        if self.names.is_empty() && self.paths.is_empty() && self.mime_types.is_empty() {
+            return true;
+        }
+        if path.file_name().is_some_and(|name| self.names.is_match(name)) || self.paths.is_match(path) {
+            return true;
+        }
+        if self.mime_types.is_empty() {
+            return false;
+        }
+        let mime = mime.get_or_insert_with(|| mime_type(path));
+        self.mime_types.iter().any(|pattern| mime_matches(pattern, mime))
+    }
+
+    /// The command opening `file` from the directory `dir`, `selection` being the marked entries.
+    /// A %s word alone gives one argument per entry, without any placeholder the file is added at the end
+    pub fn command(&self, file: &str, dir: &str, selection: &[String]) -> Command {
+        let mut arguments = Vec::new();
+        let mut replaced = false;
+        for word in &self.words {
+            if word == "%s" {
+                arguments.extend(selection.iter().cloned());
+                replaced = true;
+                continue;
+            }
+            let (expanded, placeholder) = expand(word, file, dir, selection);
+            arguments.push(expanded);
+            replaced |= placeholder;
+        }
+        if !replaced {
+            arguments.push(file.to_string());
+        }
+
+        let mut command = Command::new(expand_home(&arguments[0]));
+        command.args(&arguments[1..]).current_dir(dir);
+        // Only a command in the foreground can use the terminal, the others would draw over the screen
+        if self.mode != OpenMode::Foreground {
+            command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        // In a group of its own the command is not stopped with stfm by a Ctrl-C or a closed terminal
+        #[cfg(unix)]
+        if self.mode == OpenMode::Detach {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        command
+    }
+}
+
+/// Replace %f, %d and %s in `word` by the file, the directory and the selection, %% is a single %.
+/// Also returns if there was a placeholder
+pub fn expand(word: &str, file: &str, dir: &str, selection: &[String]) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut replaced = false;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => expanded.push_str(file),
+            Some('d') => expanded.push_str(dir),
+            Some('s') => expanded.push_str(&selection.join(" ")),
+            Some('%') => {
+                expanded.push('%');
+                continue;
+            }
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+                continue;
+            }
+            None => {
+                expanded.push('%');
+                continue;
+            }
+        }
+        replaced = true;
+    }
+    (expanded, replaced)
+}
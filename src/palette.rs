@@ -0,0 +1,137 @@
+use crate::action::{Action, Context, CustomCommand};
+use crate::app::CurrentScreen;
+use crate::keymap;
+use ratatui::widgets::ListState;
+
+/// What an entry of the palette runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteTarget {
+    Action(Action),
+    Custom(usize), // the index of a custom command of the configuration
+}
+
+/// An action of the palette with the text it is matched against
+#[derive(Debug)]
+pub struct PaletteEntry {
+    pub target: PaletteTarget,
+    pub title: String,
+    pub keys: String, // the keys running it, to learn them
+}
+
+/// The command palette: every action of the screen it was opened from, filtered by fuzzy matching as you type
+#[derive(Debug)]
+pub struct Palette {
+    pub screen: CurrentScreen, // the screen the palette was opened from, it goes back to it
+    pub input: String,
+    pub entries: Vec<PaletteEntry>,
+    pub matches: Vec<(usize, Vec<usize>)>, // the matching entries, best first, with the positions of the matched characters
+    pub state: ListState,
+}
+
+impl Palette {
+    pub fn new(screen: CurrentScreen, context: Context, custom_commands: &[CustomCommand]) -> Palette {
+        let mut entries: Vec<PaletteEntry> = keymap::bindings(context)
+            .iter()
+            .filter(|(action, _)| *action != Action::Palette)
+            .map(|(action, keys)| PaletteEntry {
+                target: PaletteTarget::Action(*action),
+                title: action.description().to_string(),
+                keys: keys.iter().map(|key| key.to_string()).collect::<Vec<String>>().join(" "),
+            })
+            .collect();
+        // Custom commands run in the main screen
+        if context == Context::Main {
+            for (index, command) in custom_commands.iter().enumerate() {
+                entries.push(PaletteEntry {
+                    target: PaletteTarget::Custom(index),
+                    title: command.name.clone(),
+                    keys: command.key.map(|key| key.to_string()).unwrap_or_default(),
+                });
+            }
+        }
+        let mut palette = Palette {
+            screen,
+            input: String::new(),
+            entries,
+            matches: Vec::new(),
+            state: ListState::default(),
+        };
+        palette.filter();
+        palette
+    }
+
+    /// Match the entries against the input again, the best match is selected
+    pub fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize, Vec<usize>)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                fuzzy_match(&self.input, &entry.title).map(|(score, positions)| (score, index, positions))
+            })
+            .collect();
+        // Entries matching as well keep the order of the keymap
+        scored.sort_by_key(|(score, index, _)| (std::cmp::Reverse(*score), *index));
+        self.matches = scored.into_iter().map(|(_, index, positions)| (index, positions)).collect();
+        self.state.select(match self.matches.is_empty() {
+            true => None,
+            false => Some(0),
+        });
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.filter();
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.filter();
+    }
+
+    pub fn down(&mut self) {
+        if let Some(index) = self.state.selected() {
+            self.state.select(Some((index + 1).min(self.matches.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn up(&mut self) {
+        if let Some(index) = self.state.selected() {
+            self.state.select(Some(index.saturating_sub(1)));
+        }
+    }
+
+    /// What the selected entry runs
+    pub fn selected(&self) -> Option<PaletteTarget> {
+        let (index, _) = self.matches.get(self.state.selected()?)?;
+        Some(self.entries[*index].target)
+    }
+}
+
+/// Match the characters of `pattern` in order in `text`, ignoring case. The score is higher for characters
+/// following each other and for characters starting words, the positions are the matched characters of `text`
+fn fuzzy_match(pattern: &str, text: &str) -> Option<(i64, Vec<usize>)> {
+    let text: Vec<char> = text.chars().collect();
+    let mut positions = Vec::new();
+    let mut score = 0;
+    let mut start = 0;
+    for wanted in pattern.chars().filter(|c| !c.is_whitespace()) {
+        let wanted = wanted.to_lowercase().next().unwrap_or(wanted);
+        let offset = text[start..]
+            .iter()
+            .position(|c| c.to_lowercase().next().unwrap_or(*c) == wanted)?;
+        let position = start + offset;
+        score += 1;
+        if position == 0 || !text[position - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if positions.last().is_some_and(|last| last + 1 == position) {
+            score += 5;
+        }
+        // Characters far from the previous one are worth less
+        score -= offset.min(10) as i64;
+        positions.push(position);
+        start = position + 1;
+    }
+    Some((score, positions))
+}
@@ -0,0 +1,227 @@
+use crate::preview::display_line;
+use lopdf::content::Content;
+use lopdf::{decode_text_string, Document, Encoding, Object, ObjectId};
+use std::collections::BTreeMap;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use std::path::Path;
+
+/// Number of pages whose text is extracted for the preview, the rest of the document is skipped
+pub const PDF_PAGE_LIMIT: usize = 20;
+
+/// Number of pages whose text is extracted for the preview pane
+pub const PDF_PANE_PAGE_LIMIT: usize = 1;
+
+/// Fields of the document information dictionary shown when no text can be extracted
+const INFO_FIELDS: [(&str, &str); 8] = [
+    ("Title", "Title"),
+    ("Author", "Author"),
+    ("Subject", "Subject"),
+    ("Keywords", "Keywords"),
+    ("Creator", "Creator"),
+    ("Producer", "Producer"),
+    ("CreationDate", "Created"),
+    ("ModDate", "Modified"),
+];
+
+/// If the file at `path` is shown as the text of a PDF
+pub fn handles(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) => extension.to_string_lossy().eq_ignore_ascii_case("pdf"),
+        None => false,
+    }
+}
+
+/// Extract the text of the first `page_limit` pages of the PDF at `path`, with a separator before every page.
+/// The metadata of the document is shown instead when none of the pages has text.
+/// Returns the label of the preview, with the page count, and the text
+pub fn extract(path: &Path, page_limit: usize) -> Result<(String, Text<'static>), String> {
+    let document = Document::load(path).map_err(|e| format!("Invalid PDF: {}", e))?;
+    let pages = document.get_pages();
+    let label = match pages.len() {
+        1 => "PDF, 1 page".to_string(),
+        count => format!("PDF, {} pages", count),
+    };
+
+    let separator = Style::default().fg(Color::DarkGray);
+    let mut lines = Vec::new();
+    let mut found_text = false;
+    if !document.is_encrypted() {
+        for (number, page_id) in pages.iter().take(page_limit) {
+            lines.push(Line::styled(format!("── Page {}/{} ──", number, pages.len()), separator));
+            match page_text(&document, *page_id) {
+                Ok(text) => {
+                    let text = text.trim_end();
+                    if text.trim().is_empty() {
+                        lines.push(Line::styled("(no text on this page)", separator));
+                    }
+                    for line in text.lines() {
+                        lines.push(Line::from(display_line(line)));
+                    }
+                    found_text |= !text.trim().is_empty();
+                }
+                Err(e) => lines.push(Line::styled(format!("(text could not be extracted: {})", e), separator)),
+            }
+            lines.push(Line::default());
+        }
+        if pages.len() > page_limit {
+            lines.push(Line::styled(
+                format!("{} more pages not shown", pages.len() - page_limit),
+                separator,
+            ));
+        }
+    }
+    if !found_text {
+        lines = metadata(&document, pages.len());
+    }
+    Ok((label, Text::from(lines)))
+}
+
+/// Kerning in a TJ array wider than this, in thousandths of the font size, is taken as a space between words
+const WORD_GAP: f32 = 200.0;
+
+/// Text shown by the content stream of a page. Lines are broken where the text moves to another line, which
+/// keeps the layout readable where extracting the strings alone would give one long line per text object
+fn page_text(document: &Document, page_id: ObjectId) -> Result<String, lopdf::Error> {
+    let encodings: BTreeMap<Vec<u8>, Encoding> = document
+        .get_page_fonts(page_id)?
+        .into_iter()
+        .filter_map(|(name, font)| font.get_font_encoding(document).ok().map(|encoding| (name, encoding)))
+        .collect();
+    let content = Content::decode(&document.get_page_content(page_id)?)?;
+    let mut text = String::new();
+    let mut encoding = None;
+    let mut y = 0.0; // the vertical position of the text line
+    let mut shown_y = None; // the vertical position of the last text shown
+    let mut moved = false; // if the text position changed since the last text shown
+    for operation in content.operations.iter() {
+        let operand = |index: usize| match operation.operands.get(index) {
+            Some(operand) => operand.as_float().unwrap_or(0.0),
+            None => 0.0,
+        };
+        match operation.operator.as_str() {
+            "Tf" => {
+                encoding = match operation.operands.first().map(|name| name.as_name()) {
+                    Some(Ok(name)) => encodings.get(name),
+                    _ => None,
+                };
+            }
+            "Td" | "TD" => {
+                y += operand(1);
+                moved = true;
+            }
+            "Tm" => {
+                y = operand(5);
+                moved = true;
+            }
+            // T* and the quote operators move to the next line, the distance does not matter here
+            "T*" | "'" | "\"" => {
+                y -= 1.0;
+                moved = true;
+            }
+            _ => {}
+        }
+        let strings: Vec<&Object> = match operation.operator.as_str() {
+            "Tj" | "'" | "\"" => operation.operands.last().into_iter().collect(),
+            "TJ" => match operation.operands.first().map(|operand| operand.as_array()) {
+                Some(Ok(array)) => array.iter().collect(),
+                _ => Vec::new(),
+            },
+            _ => continue,
+        };
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => continue,
+        };
+        match shown_y {
+            Some(shown) if shown != y && !text.is_empty() => text.push('\n'),
+            Some(_) if moved && !text.ends_with(char::is_whitespace) => text.push(' '),
+            _ => {}
+        }
+        shown_y = Some(y);
+        moved = false;
+        for string in strings {
+            match string {
+                Object::String(bytes, _) => {
+                    if let Ok(decoded) = Document::decode_text(encoding, bytes) {
+                        text.push_str(&decoded);
+                    }
+                }
+                kerning => {
+                    let gap = kerning.as_float().unwrap_or(0.0);
+                    if -gap > WORD_GAP && !text.ends_with(char::is_whitespace) {
+                        text.push(' ');
+                    }
+                }
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// The document information dictionary, version and page count of a PDF
+fn metadata(document: &Document, page_count: usize) -> Vec<Line<'static>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let field = |name: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<14}", name), dim),
+            Span::raw(value),
+        ])
+    };
+    let mut lines = vec![
+        field("Version", document.version.clone()),
+        field("Pages", page_count.to_string()),
+    ];
+    if document.is_encrypted() {
+        lines.push(field("Encrypted", "yes".to_string()));
+    }
+    let info = document
+        .trailer
+        .get(b"Info")
+        .and_then(|info| document.dereference(info))
+        .and_then(|(_, info)| info.as_dict());
+    if let Ok(info) = info {
+        for (key, name) in INFO_FIELDS {
+            let value = match info.get(key.as_bytes()).and_then(|value| document.dereference(value)) {
+                Ok((_, value)) => info_string(value),
+                Err(_) => continue,
+            };
+            if let Some(value) = value {
+                lines.push(field(name, value));
+            }
+        }
+    }
+    lines.push(Line::default());
+    lines.push(Line::styled(
+        "No text could be extracted from this document",
+        Style::default().add_modifier(Modifier::ITALIC).fg(Color::DarkGray),
+    ));
+    lines
+}
+
+/// A text string of the information dictionary, dates are shown as "YYYY-MM-DD HH:MM:SS"
+fn info_string(value: &Object) -> Option<String> {
+    let text = decode_text_string(value).ok()?;
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return None;
+    }
+    // Dates are written as D:YYYYMMDDHHmmSS followed by the time zone
+    let digits: String = match text.strip_prefix("D:") {
+        Some(date) => date.chars().take_while(|c| c.is_ascii_digit()).collect(),
+        None => return Some(text),
+    };
+    if digits.len() < 8 {
+        return Some(text);
+    }
+    let part = |start: usize| digits.get(start..start + 2).unwrap_or("00").to_string();
+    Some(format!(
+        "{}-{}-{} {}:{}:{}",
+        &digits[..4],
+        part(4),
+        part(6),
+        part(8),
+        part(10),
+        part(12)
+    ))
+}
@@ -0,0 +1,336 @@
+use crate::archive::{self, ArchiveBrowser, ArchiveFormat, ArchiveIndex};
+use crate::binary::{self, BinaryFormat};
+use crate::encoding::decode;
+use crate::files::list_files;
+use crate::media::{self, MediaKind};
+use crate::pdf::{self, PDF_PANE_PAGE_LIMIT};
+use crate::previewer::Previewer;
+use crate::sqlite;
+use encoding_rs::{Decoder, Encoding};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span, Text};
+use regex::RegexBuilder;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Width in columns a tab is expanded to when the preview is rendered
+pub const TAB_WIDTH: usize = 4;
+
+/// How long the selection has to stay on an entry before the preview pane loads it
+pub const PANE_DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Maximum number of bytes of a file read to fill the preview pane
+pub const PANE_READ_LIMIT: u64 = 64 * 1024;
+
+/// Maximum number of lines shown in the preview pane
+pub const PANE_MAX_LINES: usize = 200;
+
+/// Byte offsets where every line of `text` starts
+pub fn line_starts(text: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    if text.is_empty() {
+        return starts;
+    }
+    starts.push(0);
+    for (i, byte) in text.bytes().enumerate() {
+        if byte == b'\n' && i + 1 < text.len() {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+/// Line `index` of `text` without its terminator, using the offsets returned by `line_starts`
+pub fn line_at<'a>(text: &'a str, starts: &[usize], index: usize) -> &'a str {
+    let start = match starts.get(index) {
+        Some(start) => *start,
+        None => return "",
+    };
+    let end = match starts.get(index + 1) {
+        Some(end) => *end,
+        None => text.len(),
+    };
+    text[start..end].trim_end_matches(['\n', '\r'])
+}
+
+/// Line as it is drawn on screen, with tabs expanded to spaces
+pub fn display_line(line: &str) -> String {
+    line.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+/// Columns `c` takes on screen, wide characters like CJK and emoji take two and control characters none
+pub fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Columns a displayed line takes on screen
+pub fn line_width(line: &str) -> usize {
+    line.width()
+}
+
+/// The characters, with their columns in `widths`, drawn in the `width` columns from column `start`,
+/// as a range of their indices. A wide character cut by either edge is left out
+pub fn column_range(widths: &[usize], start: usize, width: usize) -> (usize, usize) {
+    let mut column = 0;
+    let mut first = 0;
+    while first < widths.len() && column < start {
+        column += widths[first];
+        first += 1;
+    }
+    let mut end = first;
+    while end < widths.len() && column + widths[end] <= start + width {
+        column += widths[end];
+        end += 1;
+    }
+    (first, end)
+}
+
+/// The rows a line with characters of `widths` columns is wrapped into in `width` columns,
+/// as ranges of the indices of the characters. An empty line takes one row
+pub fn wrap_ranges(widths: &[usize], width: usize) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut column = 0;
+    for (index, char_width) in widths.iter().enumerate() {
+        if column + char_width > width && index > start {
+            ranges.push((start, index));
+            start = index;
+            column = 0;
+        }
+        column += char_width;
+    }
+    if start < widths.len() || ranges.is_empty() {
+        ranges.push((start, widths.len()));
+    }
+    ranges
+}
+
+/// A match of the preview search, positions are in columns of the displayed line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreviewMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Find every match of `pattern` in the displayed `lines`, treating it as a literal unless `use_regex` is set
+pub fn find_matches(
+    lines: impl Iterator<Item = String>,
+    pattern: &str,
+    use_regex: bool,
+    ignore_case: bool,
+) -> Result<Vec<PreviewMatch>, String> {
+    let source = match use_regex {
+        true => pattern.to_string(),
+        false => regex::escape(pattern),
+    };
+    let matcher = match RegexBuilder::new(&source).case_insensitive(ignore_case).build() {
+        Ok(matcher) => matcher,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut matches = Vec::new();
+    for (index, line) in lines.enumerate() {
+        for found in matcher.find_iter(&line) {
+            if found.is_empty() {
+                continue;
+            }
+            let start = line[..found.start()].chars().count();
+            matches.push(PreviewMatch {
+                line: index,
+                start,
+                end: start + found.as_str().chars().count(),
+            });
+        }
+    }
+    Ok(matches)
+}
+
+/// How often a followed file is checked for new content
+pub const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// What changed in a followed file since the last poll
+pub enum FollowUpdate {
+    Unchanged,
+    Appended(String),
+    Reopened(String),
+}
+
+/// Follow mode state, tracking how much of a growing file is already in the preview
+pub struct Follow {
+    pub path: PathBuf,
+    pub offset: u64, // the number of bytes of the file already in the preview
+    pub last_check: Instant,
+    file_id: Option<u64>, // identifies the file on disk, so a rotated file can be detected
+    encoding: &'static Encoding,
+    decoder: Decoder, // keeps incomplete characters at the end of a read until the rest arrives
+}
+
+impl fmt::Debug for Follow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Follow")
+            .field("path", &self.path)
+            .field("offset", &self.offset)
+            .field("encoding", &self.encoding)
+            .finish()
+    }
+}
+
+impl Follow {
+    pub fn new(path: &Path, offset: u64, encoding: &'static Encoding) -> io::Result<Follow> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Follow {
+            path: path.to_path_buf(),
+            offset,
+            last_check: Instant::now(),
+            file_id: file_id(&metadata),
+            encoding,
+            decoder: encoding.new_decoder_without_bom_handling(),
+        })
+    }
+
+    /// Read what was appended to the file since the last poll, or the whole file if it was truncated or replaced
+    pub fn poll(&mut self) -> io::Result<FollowUpdate> {
+        self.last_check = Instant::now();
+        let metadata = std::fs::metadata(&self.path)?;
+        let id = file_id(&metadata);
+        if id != self.file_id || metadata.len() < self.offset {
+            let bytes = std::fs::read(&self.path)?;
+            self.file_id = id;
+            self.offset = bytes.len() as u64;
+            self.decoder = self.encoding.new_decoder_without_bom_handling();
+            let (text, _) = self.encoding.decode_with_bom_removal(&bytes);
+            return Ok(FollowUpdate::Reopened(text.into_owned()));
+        }
+        if metadata.len() == self.offset {
+            return Ok(FollowUpdate::Unchanged);
+        }
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = Vec::new();
+        let read = file.take(metadata.len() - self.offset).read_to_end(&mut bytes)?;
+        self.offset += read as u64;
+        let mut text = String::with_capacity(
+            self.decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3),
+        );
+        let _ = self.decoder.decode_to_string(&bytes, &mut text, false);
+        if text.is_empty() {
+            return Ok(FollowUpdate::Unchanged);
+        }
+        Ok(FollowUpdate::Appended(text))
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Content loaded in the background for the preview
+pub enum PreviewContent {
+    File(Vec<u8>), // the bytes of a file, shown like a file read from disk
+    Rendered(Text<'static>), // a view built from the file, like the summary of a binary
+    Labelled(String, Text<'static>), // a view with a label only known once it is built, like the page count of a PDF
+}
+
+/// Content of the preview pane: the first lines of a file, the entries of a directory or the members of an archive.
+/// Paths inside `archive` are read from it
+pub fn pane_content(
+    path: &Path,
+    is_dir: bool,
+    show_hidden: bool,
+    max_lines: usize,
+    archive: Option<ArchiveBrowser>,
+    previewer: Option<(Previewer, (u16, u16))>,
+) -> Text<'static> {
+    let member = match &archive {
+        Some(archive) => archive.member(&path.to_string_lossy()),
+        None => None,
+    };
+    if is_dir {
+        let files = match (&archive, &member) {
+            (Some(archive), Some(member)) => archive.list_files(&member.path, show_hidden),
+            _ => list_files(&path.to_path_buf(), show_hidden),
+        };
+        if files.is_empty() {
+            return Text::styled("Empty directory", Style::default().fg(Color::DarkGray));
+        }
+        let mut lines = Vec::new();
+        for file in files.iter().take(max_lines) {
+            let mut style = Style::default().fg(Color::Green);
+            if file.is_dir {
+                style = Style::default().fg(Color::Cyan);
+            }
+            lines.push(Line::from(Span::styled(
+                format!("[{}] {}", file.extension.to_uppercase(), file.name),
+                style,
+            )));
+        }
+        return Text::from(lines);
+    }
+    if let (None, Some((previewer, (width, height)))) = (&member, &previewer) {
+        if previewer.handles(path) {
+            return match previewer.run(path, *width, *height) {
+                Ok(text) => text,
+                Err(e) => Text::styled(e, Style::default().fg(Color::Red)),
+            };
+        }
+    }
+    if let (None, Some(format)) = (&member, ArchiveFormat::from_path(path)) {
+        return match ArchiveIndex::read(path, format) {
+            Ok(index) => archive::listing(&index),
+            Err(e) => Text::styled(e.to_string(), Style::default().fg(Color::Red)),
+        };
+    }
+    // A binary goblin cannot parse is shown like any other file
+    if member.is_none() && BinaryFormat::from_path(path).is_some() {
+        if let Ok(text) = binary::summary(path) {
+            return text;
+        }
+    }
+    if member.is_none() && sqlite::handles(path) {
+        return sqlite::listing(path);
+    }
+    if member.is_none() && pdf::handles(path) {
+        return match pdf::extract(path, PDF_PANE_PAGE_LIMIT) {
+            Ok((_, text)) => text,
+            Err(e) => Text::styled(e, Style::default().fg(Color::Red)),
+        };
+    }
+    if member.is_none() && MediaKind::from_path(path).is_some() {
+        return match media::summary(path) {
+            Ok(text) => text,
+            Err(e) => Text::styled(e, Style::default().fg(Color::Red)),
+        };
+    }
+    let mut bytes = Vec::new();
+    let read = match &member {
+        Some(member) => archive::read_member(member, PANE_READ_LIMIT).map(|member_bytes| bytes = member_bytes),
+        None => match File::open(path) {
+            Ok(file) => file.take(PANE_READ_LIMIT).read_to_end(&mut bytes).map(|_| ()),
+            Err(e) => Err(e),
+        },
+    };
+    if let Err(e) = read {
+        return Text::styled(e.to_string(), Style::default().fg(Color::Red));
+    }
+    let content = match decode(&bytes, None) {
+        Ok(decoded) => decoded.text,
+        Err(e) => return Text::styled(e, Style::default().fg(Color::DarkGray)),
+    };
+    let lines: Vec<Line> = content
+        .lines()
+        .take(max_lines)
+        .map(|line| Line::from(display_line(line)))
+        .collect();
+    Text::from(lines)
+}
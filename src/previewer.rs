@@ -0,0 +1,182 @@
+use crate::ansi;
+use crate::config::{expand_home, PreviewerConfig};
+use crate::shell::kill_group;
+use ratatui::text::Text;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Number of previews kept in the cache, the oldest one is dropped when it is full
+const CACHE_SIZE: usize = 64;
+
+/// Maximum number of bytes of output read from the previewer
+const OUTPUT_LIMIT: u64 = 4 * 1024 * 1024;
+
+/// A preview built by the previewer, it is only reused for the same size of the preview
+struct CachedPreview {
+    width: u16,
+    height: u16,
+    text: Text<'static>,
+}
+
+/// Previews built by the previewer, keyed by the path and modification time of the file
+#[derive(Default)]
+struct Cache {
+    previews: HashMap<(PathBuf, SystemTime), CachedPreview>,
+    order: VecDeque<(PathBuf, SystemTime)>, // the keys from the oldest to the newest
+}
+
+/// The external previewer set in the configuration, shared by the preview and the preview pane
+#[derive(Clone)]
+pub struct Previewer {
+    config: PreviewerConfig,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl std::fmt::Debug for Previewer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Previewer").field("config", &self.config).finish()
+    }
+}
+
+impl Previewer {
+    pub fn new(config: PreviewerConfig) -> Previewer {
+        Previewer {
+            config,
+            cache: Arc::new(Mutex::new(Cache::default())),
+        }
+    }
+
+    /// If the file at `path` is shown with the previewer instead of the built-in previews.
+    /// Without extensions or MIME types in the configuration every file is
+    pub fn handles(&self, path: &Path) -> bool {
+        if self.config.extensions.is_empty() && self.config.mime_types.is_empty() {
+            return true;
+        }
+        if let Some(extension) = path.extension() {
+            let extension = extension.to_string_lossy();
+            if self.config.extensions.iter().any(|wanted| wanted.eq_ignore_ascii_case(&extension)) {
+                return true;
+            }
+        }
+        if self.config.mime_types.is_empty() {
+            return false;
+        }
+        let mime_type = mime_type(path);
+        self.config.mime_types.iter().any(|pattern| mime_matches(pattern, &mime_type))
+    }
+
+    /// Run the previewer on the file at `path` for a preview of `width` columns and `height` lines,
+    /// or reuse its output if the file did not change since
+    pub fn run(&self, path: &Path, width: u16, height: u16) -> Result<Text<'static>, String> {
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        if let Some(modified) = modified {
+            let cache = self.cache.lock().map_err(|e| e.to_string())?;
+            if let Some(cached) = cache.previews.get(&(path.to_path_buf(), modified)) {
+                if cached.width == width && cached.height == height {
+                    return Ok(cached.text.clone());
+                }
+            }
+        }
+
+        let output = self.output(path, width, height)?;
+        let text = ansi::parse(&String::from_utf8_lossy(&output));
+        if let Some(modified) = modified {
+            let mut cache = self.cache.lock().map_err(|e| e.to_string())?;
+            let key = (path.to_path_buf(), modified);
+            let cached = CachedPreview {
+                width,
+                height,
+                text: text.clone(),
+            };
+            if cache.previews.insert(key.clone(), cached).is_none() {
+                cache.order.push_back(key);
+            }
+            while cache.order.len() > CACHE_SIZE {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.previews.remove(&oldest);
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    /// The output of the previewer, it is killed when it takes longer than the timeout
+    fn output(&self, path: &Path, width: u16, height: u16) -> Result<Vec<u8>, String> {
+        let command = expand_home(&self.config.command);
+        let mut previewer = Command::new(&command);
+        previewer
+            .arg(path)
+            .arg(width.to_string())
+            .arg(height.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        // The programs a previewer script starts are in its group, they are stopped with it and release the output
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            previewer.process_group(0);
+        }
+        let mut child = previewer
+            .spawn()
+            .map_err(|e| format!("Could not run the previewer {}: {}", command.display(), e))?;
+
+        // The output is read in another thread so that a previewer filling the pipe does not block
+        let mut stdout = match child.stdout.take() {
+            Some(stdout) => stdout,
+            None => return Err("The previewer has no output".to_string()),
+        };
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            let read = (&mut stdout).take(OUTPUT_LIMIT).read_to_end(&mut output);
+            let _ = sender.send(read.map(|_| output));
+        });
+        let timeout = Duration::from_millis(self.config.timeout);
+        let output = match receiver.recv_timeout(timeout) {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                kill_group(&mut child);
+                return Err(format!("Could not read the output of the previewer: {}", e));
+            }
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => {
+                kill_group(&mut child);
+                return Err(format!("The previewer took longer than {} ms", self.config.timeout));
+            }
+        };
+        // The output is complete, the previewer may still be exiting or have stopped after the output limit
+        kill_group(&mut child);
+        let status = child.wait().map_err(|e| e.to_string())?;
+        if output.is_empty() && !status.success() {
+            return Err(format!("The previewer failed with {}", status));
+        }
+        Ok(output)
+    }
+}
+
+/// MIME type of a file from its first bytes, files that are not recognized are text unless they contain a NUL byte
+pub fn mime_type(path: &Path) -> String {
+    let mut header = Vec::new();
+    if let Ok(file) = File::open(path) {
+        let _ = file.take(8192).read_to_end(&mut header);
+    }
+    match infer::get(&header) {
+        Some(kind) => kind.mime_type().to_string(),
+        None if header.contains(&0) => "application/octet-stream".to_string(),
+        None => "text/plain".to_string(),
+    }
+}
+
+/// If `mime_type` matches `pattern`, which can end with a * like "image/*"
+pub fn mime_matches(pattern: &str, mime_type: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => mime_type.starts_with(prefix),
+        None => pattern.eq_ignore_ascii_case(mime_type),
+    }
+}
@@ -0,0 +1,209 @@
+use crate::opener::expand;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+#[cfg(unix)]
+use std::time::Duration;
+
+/// Number of lines of output kept, the rest is dropped
+const OUTPUT_LINE_LIMIT: usize = 100_000;
+
+/// Number of 10 ms waits for a cancelled command to exit before it is killed
+#[cfg(unix)]
+const KILL_GRACE_STEPS: usize = 10;
+
+/// Where a line of output was written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// How far the command got
+#[derive(Debug)]
+pub enum CommandState {
+    Running,
+    Exited(ExitStatus),
+    Cancelled,
+    Failed(String), // it could not be waited for
+}
+
+/// A command typed in the '!' prompt, run by the shell in the background with its output collected
+#[derive(Debug)]
+pub struct ShellCommand {
+    pub line: String, // the command as it was typed
+    pub output: Vec<(Stream, String)>,
+    pub state: CommandState,
+    pub truncated: bool, // if output was dropped after `OUTPUT_LINE_LIMIT` lines
+    pub scroll: usize,
+    pub height: usize, // the number of visible lines, updated on every draw
+    follow: bool,      // if the view stays at the end of the output as it grows
+    child: Option<Child>,
+    receiver: mpsc::Receiver<(Stream, String)>,
+}
+
+impl ShellCommand {
+    /// Run `line` with `sh -c` in `dir`. %f, %d and %s are replaced by the quoted file, directory and selection,
+    /// the marked entries or the file when nothing is marked. Like in the subshell they are also in $f, $fs and $fx
+    pub fn run(line: &str, dir: &str, file: &str, marked: &[String]) -> Result<ShellCommand, String> {
+        let selection = match marked.is_empty() {
+            true => vec![file.to_string()],
+            false => marked.to_vec(),
+        };
+        let quoted: Vec<String> = selection.iter().map(|path| shell_words::quote(path).to_string()).collect();
+        let (script, _) = expand(line, &shell_words::quote(file), &shell_words::quote(dir), &quoted);
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&script)
+            .current_dir(dir)
+            .env("f", file)
+            .env("fs", marked.join("\n"))
+            .env("fx", selection.join("\n"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // In a group of its own everything the command starts, like the programs of a pipeline, can be cancelled
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        let mut child = command.spawn().map_err(|e| format!("Could not run the command: {}", e))?;
+
+        // Both outputs are read in threads of their own so that neither pipe fills up and blocks the command
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, Stream::Stdout, sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, Stream::Stderr, sender);
+        }
+        Ok(ShellCommand {
+            line: line.to_string(),
+            output: Vec::new(),
+            state: CommandState::Running,
+            truncated: false,
+            scroll: 0,
+            height: 0,
+            follow: true,
+            child: Some(child),
+            receiver,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, CommandState::Running)
+    }
+
+    pub fn succeeded(&self) -> bool {
+        matches!(&self.state, CommandState::Exited(status) if status.success())
+    }
+
+    /// Receive the output written since the last poll, returns true when the command just exited
+    pub fn poll(&mut self) -> bool {
+        while let Ok(line) = self.receiver.try_recv() {
+            if self.output.len() < OUTPUT_LINE_LIMIT {
+                self.output.push(line);
+            } else {
+                self.truncated = true;
+            }
+        }
+        if self.follow {
+            self.last();
+        }
+        let child = match self.child.as_mut() {
+            Some(child) => child,
+            None => return false,
+        };
+        self.state = match child.try_wait() {
+            Ok(Some(status)) => CommandState::Exited(status),
+            Ok(None) => return false,
+            Err(e) => CommandState::Failed(e.to_string()),
+        };
+        self.child = None;
+        true
+    }
+
+    /// Kill the command and what it started, what it wrote so far is kept
+    pub fn cancel(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            kill_group(&mut child);
+            self.state = CommandState::Cancelled;
+        }
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.output.len().saturating_sub(self.height.max(1))
+    }
+
+    pub fn down(&mut self, count: usize) {
+        self.scroll = self.scroll.saturating_add(count).min(self.max_scroll());
+        self.follow = self.scroll == self.max_scroll();
+    }
+
+    pub fn up(&mut self, count: usize) {
+        self.scroll = self.scroll.saturating_sub(count);
+        self.follow = false;
+    }
+
+    pub fn first(&mut self) {
+        self.scroll = 0;
+        self.follow = false;
+    }
+
+    pub fn last(&mut self) {
+        self.scroll = self.max_scroll();
+        self.follow = true;
+    }
+}
+
+impl Drop for ShellCommand {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Stop a child started in a process group of its own and everything it started: the group is asked to
+/// terminate, then what is left of it after a moment is killed
+#[cfg(unix)]
+pub fn kill_group(child: &mut Child) {
+    let group = -(child.id() as libc::pid_t);
+    // SAFETY: kill only sends a signal, to the group the child leads
+    unsafe { libc::kill(group, libc::SIGTERM) };
+    for _ in 0..KILL_GRACE_STEPS {
+        if let Ok(Some(_)) = child.try_wait() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    // SAFETY: as above, programs ignoring SIGTERM are stopped for good
+    unsafe { libc::kill(group, libc::SIGKILL) };
+    let _ = child.wait();
+}
+
+#[cfg(not(unix))]
+pub fn kill_group(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Send the lines read from `reader` until it is closed, invalid UTF-8 is replaced
+fn read_lines<R: Read + Send + 'static>(reader: R, stream: Stream, sender: mpsc::Sender<(Stream, String)>) {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line).trim_end_matches(['\n', '\r']).replace('\t', "    ");
+                    if sender.send((stream, text)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,222 @@
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::ListState;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+
+/// The first bytes of every SQLite database file
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// If the file at `path` is a SQLite database, from its header
+pub fn handles(path: &Path) -> bool {
+    let mut header = Vec::new();
+    match File::open(path) {
+        Ok(file) => file.take(16).read_to_end(&mut header).is_ok() && header == SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+/// Open the database at `path` so that nothing can write to it.
+/// A database with a -wal file next to it is in use in WAL mode, its rows not checkpointed yet are only read
+/// through that file, so it is opened read-only like by any other reader. Otherwise it is opened as immutable,
+/// so SQLite does not create the -wal and -shm files next to it
+pub fn connect(path: &Path) -> rusqlite::Result<Connection> {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    let parameters = match Path::new(&wal).exists() {
+        true => "mode=ro",
+        false => "mode=ro&immutable=1",
+    };
+    let path = path.to_string_lossy().replace('%', "%25").replace('?', "%3f").replace('#', "%23");
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+    let connection = Connection::open_with_flags(format!("file:{}?{}", path, parameters), flags)?;
+    connection.pragma_update(None, "query_only", true)?;
+    Ok(connection)
+}
+
+/// Quote a table or column name to use it in a statement
+pub fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A value read from the database as it is shown in a table cell
+pub fn value_string(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(value) => value.to_string(),
+        ValueRef::Real(value) => value.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).to_string(),
+        ValueRef::Blob(blob) => format!("<blob {} bytes>", blob.len()),
+    }
+}
+
+/// A table or view of a database
+#[derive(Debug)]
+pub struct SqliteObject {
+    pub name: String,
+    pub kind: String, // "table" or "view"
+    pub sql: String, // the statement that created it
+    pub columns: Vec<(String, String)>, // the name and declared type of every column
+    pub rows: Option<u64>, // the number of rows, once they were counted in the background
+}
+
+/// A SQLite database shown as the list of its tables and views
+#[derive(Debug)]
+pub struct SqliteBrowser {
+    pub path: PathBuf,
+    pub objects: Vec<SqliteObject>,
+    pub state: ListState,
+    count_receiver: Option<mpsc::Receiver<(usize, u64)>>,
+}
+
+impl SqliteBrowser {
+    pub fn open(path: &Path) -> Result<SqliteBrowser, String> {
+        let objects = read_objects(path).map_err(|e| e.to_string())?;
+
+        // Counting the rows of a big table scans it, so it is done in the background
+        let (sender, receiver) = mpsc::channel();
+        let count_path = path.to_path_buf();
+        let names: Vec<String> = objects.iter().map(|object| object.name.clone()).collect();
+        std::thread::spawn(move || {
+            let connection = match connect(&count_path) {
+                Ok(connection) => connection,
+                Err(_) => return,
+            };
+            for (index, name) in names.iter().enumerate() {
+                let count = connection.query_row(&format!("SELECT COUNT(*) FROM {}", quote(name)), [], |row| {
+                    row.get::<_, i64>(0)
+                });
+                if let Ok(count) = count {
+                    if sender.send((index, count as u64)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let mut state = ListState::default();
+        if !objects.is_empty() {
+            state.select(Some(0));
+        }
+        Ok(SqliteBrowser {
+            path: path.to_path_buf(),
+            objects,
+            state,
+            count_receiver: Some(receiver),
+        })
+    }
+
+    /// Receive the row counts from the background thread as they are done
+    pub fn poll_counts(&mut self) {
+        let receiver = match &self.count_receiver {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        loop {
+            match receiver.try_recv() {
+                Ok((index, count)) => {
+                    if let Some(object) = self.objects.get_mut(index) {
+                        object.rows = Some(count);
+                    }
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.count_receiver = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Option<&SqliteObject> {
+        self.objects.get(self.state.selected()?)
+    }
+
+    pub fn down(&mut self, count: usize) {
+        if self.objects.is_empty() {
+            return;
+        }
+        let selected = self.state.selected().unwrap_or(0).saturating_add(count);
+        self.state.select(Some(selected.min(self.objects.len() - 1)));
+    }
+
+    pub fn up(&mut self, count: usize) {
+        if self.objects.is_empty() {
+            return;
+        }
+        let selected = self.state.selected().unwrap_or(0).saturating_sub(count);
+        self.state.select(Some(selected));
+    }
+
+    pub fn first(&mut self) {
+        self.up(usize::MAX);
+    }
+
+    pub fn last(&mut self) {
+        self.down(usize::MAX);
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.objects.iter().filter(|object| object.kind == "table").count()
+    }
+
+    pub fn view_count(&self) -> usize {
+        self.objects.iter().filter(|object| object.kind == "view").count()
+    }
+}
+
+/// The tables and views of the database with their columns, internal sqlite_ tables are left out
+fn read_objects(path: &Path) -> rusqlite::Result<Vec<SqliteObject>> {
+    let connection = connect(path)?;
+    let mut statement = connection.prepare(
+        "SELECT name, type, COALESCE(sql, '') FROM sqlite_master \
+         WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY type, name",
+    )?;
+    let mut objects = statement
+        .query_map([], |row| {
+            Ok(SqliteObject {
+                name: row.get(0)?,
+                kind: row.get(1)?,
+                sql: row.get(2)?,
+                columns: Vec::new(),
+                rows: None,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<SqliteObject>>>()?;
+    for object in objects.iter_mut() {
+        let mut statement = connection.prepare(&format!("PRAGMA table_info({})", quote(&object.name)))?;
+        object.columns = statement
+            .query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+    }
+    Ok(objects)
+}
+
+/// The tables and views of the database at `path`, for the preview pane
+pub fn listing(path: &Path) -> Text<'static> {
+    let objects = match read_objects(path) {
+        Ok(objects) => objects,
+        Err(e) => return Text::styled(e.to_string(), Style::default().fg(Color::Red)),
+    };
+    if objects.is_empty() {
+        return Text::styled("Empty database", Style::default().fg(Color::DarkGray));
+    }
+    let lines: Vec<Line> = objects
+        .iter()
+        .map(|object| {
+            Line::from(vec![
+                Span::styled(format!("{:<6}", object.kind), Style::default().fg(Color::DarkGray)),
+                Span::styled(object.name.clone(), Style::default().fg(Color::Green)),
+                Span::styled(
+                    format!(" ({} columns)", object.columns.len()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ])
+        })
+        .collect();
+    Text::from(lines)
+}
@@ -0,0 +1,572 @@
+use crate::sqlite::{self, quote, value_string};
+use chrono::NaiveDate;
+use csv::{ByteRecord, Position, Reader, ReaderBuilder};
+use rusqlite::Connection;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+
+/// Extensions of the files shown as a table in the preview
+pub const TABLE_EXTENSIONS: [&str; 2] = ["csv", "tsv"];
+
+/// Number of rows read from the file at once, the table is read and kept in pages of this many rows
+const PAGE_ROWS: usize = 500;
+
+/// Number of pages kept in memory around the cursor, the others are read again when the cursor gets back to them
+const WINDOW_PAGES: usize = 4;
+
+/// Widest a column gets, longer values are cut
+pub const MAX_COLUMN_WIDTH: usize = 40;
+
+/// Delimiters tried when detecting the one used by a file
+const DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Where the rows of a table are read from
+#[derive(Debug)]
+enum RowSource {
+    Csv(Reader<File>),
+    Sqlite {
+        connection: Connection,
+        table: String,
+        key: Option<&'static str>, // the name the rowid is read with, None for views and tables without one
+    },
+}
+
+/// Where a page of rows starts, to read it again without reading the rows before it
+#[derive(Debug, Clone)]
+enum PageStart {
+    Csv(Position),    // the position of the first record of the page in the file
+    Rowid(Option<i64>), // the rowid of the last row before the page, None for the first page
+    Offset(usize),      // the number of rows before the page, for tables without rowid
+}
+
+/// A delimited file or a database table shown as a table. Only a few pages of rows around the cursor are kept,
+/// the start of every page read is recorded to jump back to it
+#[derive(Debug)]
+pub struct TableView {
+    pub description: String, // what the rows are, like the delimiter of the file
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>, // the rows of the pages kept, from `first_row`
+    pub first_row: usize, // the number of the first row kept, always at the start of a page
+    pub selected: usize, // the number of the row under the cursor
+    pub scroll: usize, // the number of the first row shown, updated on every draw
+    pub widths: Vec<usize>, // the width of every column in the rows read so far
+    pub total_rows: Option<usize>, // the number of rows, once they were counted in the background or all read
+    pub read_rows: usize, // the number of rows up to the furthest page read
+    pub column_offset: usize, // the first column shown, for horizontal scrolling
+    pub show_summary: bool, // if the column summary is shown instead of the rows
+    pub error: Option<String>, // why rows could not be read, nothing more is read then
+    pub going_to_last: bool, // if the cursor goes to the last row once the rows are counted
+    source: RowSource,
+    starts: Vec<PageStart>, // the start of every page up to the furthest page read, or of all pages once counted
+    count_receiver: Option<mpsc::Receiver<(usize, Vec<PageStart>)>>,
+}
+
+impl TableView {
+    /// If the file at `path` should be shown as a table
+    pub fn handles(path: &Path) -> bool {
+        match path.extension() {
+            Some(extension) => TABLE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()),
+            None => false,
+        }
+    }
+
+    pub fn open(path: &Path) -> io::Result<TableView> {
+        let mut sample = Vec::new();
+        File::open(path)?.take(16 * 1024).read_to_end(&mut sample)?;
+        let is_tsv = match path.extension() {
+            Some(extension) => extension.to_string_lossy().eq_ignore_ascii_case("tsv"),
+            None => false,
+        };
+        let delimiter = match is_tsv {
+            true => b'\t',
+            false => detect_delimiter(&sample),
+        };
+        let mut reader = builder(delimiter).from_reader(File::open(path)?);
+        let headers = match reader.byte_headers() {
+            Ok(headers) => record_strings(headers),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        };
+        let first_start = PageStart::Csv(reader.position().clone());
+
+        // Counting the rows reads the whole file, so it is done in the background, recording where the pages start
+        let (sender, receiver) = mpsc::channel();
+        let count_path = PathBuf::from(path);
+        std::thread::spawn(move || {
+            let file = match File::open(count_path) {
+                Ok(file) => file,
+                Err(_) => return,
+            };
+            let mut reader = builder(delimiter).from_reader(file);
+            if reader.byte_headers().is_err() {
+                return;
+            }
+            let mut starts = vec![PageStart::Csv(reader.position().clone())];
+            let mut record = ByteRecord::new();
+            let mut count = 0;
+            while let Ok(true) = reader.read_byte_record(&mut record) {
+                count += 1;
+                if count % PAGE_ROWS == 0 {
+                    starts.push(PageStart::Csv(reader.position().clone()));
+                }
+            }
+            let _ = sender.send((count, starts));
+        });
+
+        let description = match delimiter {
+            b'\t' => "delimiter tab".to_string(),
+            other => format!("delimiter '{}'", other as char),
+        };
+        Ok(TableView::new(description, headers, RowSource::Csv(reader), first_start, receiver))
+    }
+
+    /// Page through the rows of a table or view of the SQLite database at `path`
+    pub fn open_sqlite(path: &Path, name: &str, kind: &str) -> Result<TableView, String> {
+        let connection = sqlite::connect(path).map_err(|e| e.to_string())?;
+        let headers = {
+            let statement = connection
+                .prepare(&format!("SELECT * FROM {} LIMIT 0", quote(name)))
+                .map_err(|e| e.to_string())?;
+            statement.column_names().iter().map(|name| name.to_string()).collect::<Vec<String>>()
+        };
+
+        // Tables are paged by rowid, a column can take one of its names. Views have no rowid, their pages
+        // are read with OFFSET which goes through all the rows before the page
+        let key = ["rowid", "_rowid_", "oid"].into_iter().find(|key| {
+            !headers.iter().any(|header| header.eq_ignore_ascii_case(key))
+                && connection.prepare(&format!("SELECT {} FROM {} LIMIT 0", key, quote(name))).is_ok()
+        });
+
+        // Counting the rows scans the table, so it is done in the background, recording where the pages start
+        let (sender, receiver) = mpsc::channel();
+        let count_path = PathBuf::from(path);
+        let table = name.to_string();
+        std::thread::spawn(move || {
+            let connection = match sqlite::connect(&count_path) {
+                Ok(connection) => connection,
+                Err(_) => return,
+            };
+            if let Ok(counted) = count_sqlite_rows(&connection, &table, key) {
+                let _ = sender.send(counted);
+            }
+        });
+
+        let first_start = match key {
+            Some(_) => PageStart::Rowid(None),
+            None => PageStart::Offset(0),
+        };
+        let source = RowSource::Sqlite {
+            connection,
+            table: name.to_string(),
+            key,
+        };
+        Ok(TableView::new(format!("{} {}", kind, name), headers, source, first_start, receiver))
+    }
+
+    fn new(
+        description: String,
+        headers: Vec<String>,
+        source: RowSource,
+        first_start: PageStart,
+        count_receiver: mpsc::Receiver<(usize, Vec<PageStart>)>,
+    ) -> TableView {
+        let mut table = TableView {
+            description,
+            widths: headers.iter().map(|header| header.chars().count().min(MAX_COLUMN_WIDTH)).collect(),
+            headers,
+            rows: Vec::new(),
+            first_row: 0,
+            selected: 0,
+            scroll: 0,
+            total_rows: None,
+            read_rows: 0,
+            column_offset: 0,
+            show_summary: false,
+            error: None,
+            going_to_last: false,
+            source,
+            starts: vec![first_start],
+            count_receiver: Some(count_receiver),
+        };
+        table.select_row(0);
+        table
+    }
+
+    /// Where page `page` starts, None when the pages before it were not read or counted yet
+    fn page_start(&self, page: usize) -> Option<PageStart> {
+        match self.source {
+            RowSource::Sqlite { key: None, .. } => Some(PageStart::Offset(page * PAGE_ROWS)),
+            _ => self.starts.get(page).cloned(),
+        }
+    }
+
+    /// Read the rows of page `page`, recording where the next page starts
+    fn read_page(&mut self, page: usize) -> Option<Vec<Vec<String>>> {
+        if self.error.is_some() {
+            return None;
+        }
+        let start = self.page_start(page)?;
+        let read = match (&mut self.source, start) {
+            (RowSource::Csv(reader), PageStart::Csv(position)) => {
+                read_csv_page(reader, position).map_err(|e| e.to_string())
+            }
+            (RowSource::Sqlite { connection, table, key: Some(key) }, PageStart::Rowid(after)) => {
+                read_sqlite_page(connection, table, key, after).map_err(|e| e.to_string())
+            }
+            (RowSource::Sqlite { connection, table, .. }, PageStart::Offset(offset)) => {
+                read_sqlite_rows(connection, table, offset, PAGE_ROWS)
+                    .map(|rows| (rows, PageStart::Offset(offset + PAGE_ROWS)))
+                    .map_err(|e| e.to_string())
+            }
+            _ => return None,
+        };
+        let (rows, next) = match read {
+            Ok(read) => read,
+            Err(e) => {
+                self.error = Some(e);
+                return None;
+            }
+        };
+
+        let end = page * PAGE_ROWS + rows.len();
+        self.read_rows = self.read_rows.max(end);
+        match rows.len() < PAGE_ROWS {
+            true => self.total_rows = Some(end),
+            false if self.starts.len() == page + 1 => self.starts.push(next),
+            false => {}
+        }
+        for row in rows.iter() {
+            for (column, value) in row.iter().enumerate() {
+                let width = value.chars().count().min(MAX_COLUMN_WIDTH);
+                match self.widths.get_mut(column) {
+                    Some(current) => *current = (*current).max(width),
+                    None => self.widths.push(width),
+                }
+            }
+        }
+        Some(rows)
+    }
+
+    /// Make sure the page holding `row` is kept, reading it next to the pages kept or instead of them.
+    /// Pages far from it are dropped
+    fn load_row(&mut self, row: usize) {
+        let page = row / PAGE_ROWS;
+        let first_page = self.first_row / PAGE_ROWS;
+        let end_page = first_page + self.rows.len().div_ceil(PAGE_ROWS);
+        if (first_page..end_page).contains(&page) {
+            return;
+        }
+        if self.total_rows.is_some_and(|total| row >= total) {
+            return;
+        }
+        if page + 1 == first_page {
+            if let Some(rows) = self.read_page(page) {
+                self.rows.splice(0..0, rows);
+                self.first_row = page * PAGE_ROWS;
+                self.rows.truncate(WINDOW_PAGES * PAGE_ROWS);
+            }
+            return;
+        }
+        // Pages after the ones kept are read one after the other, the start of a page is only known
+        // once the page before it was read
+        let mut next_page = end_page;
+        let walk = page > end_page && self.page_start(page).is_none();
+        if page != end_page && !walk {
+            self.rows.clear();
+            next_page = page;
+        }
+        while next_page <= page {
+            let rows = match self.read_page(next_page) {
+                Some(rows) => rows,
+                None => return,
+            };
+            if self.rows.is_empty() {
+                self.first_row = next_page * PAGE_ROWS;
+            }
+            let full = rows.len() == PAGE_ROWS;
+            self.rows.extend(rows);
+            if self.rows.len() > WINDOW_PAGES * PAGE_ROWS {
+                self.rows.drain(..PAGE_ROWS);
+                self.first_row += PAGE_ROWS;
+            }
+            if !full {
+                return;
+            }
+            next_page += 1;
+        }
+    }
+
+    /// Receive the row count and the start of every page from the background thread once it is done
+    pub fn poll_count(&mut self) {
+        if let Some(receiver) = &self.count_receiver {
+            match receiver.try_recv() {
+                Ok((count, starts)) => {
+                    self.total_rows = Some(count);
+                    if starts.len() > self.starts.len() {
+                        self.starts = starts;
+                    }
+                    self.count_receiver = None;
+                    if self.going_to_last {
+                        self.last();
+                    }
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => {
+                    self.count_receiver = None;
+                    self.going_to_last = false;
+                }
+            }
+        }
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.widths.len()
+    }
+
+    /// Move the cursor to `row`, reading the pages around it
+    fn select_row(&mut self, row: usize) {
+        let row = match self.total_rows {
+            Some(total) => row.min(total.saturating_sub(1)),
+            None => row,
+        };
+        // The rows just before and after the cursor are read before it gets to them
+        self.load_row(row.saturating_sub(PAGE_ROWS / 5));
+        self.load_row(row);
+        self.load_row(row + PAGE_ROWS / 5);
+        let last = (self.first_row + self.rows.len()).saturating_sub(1);
+        self.selected = row.clamp(self.first_row, last.max(self.first_row));
+    }
+
+    pub fn down(&mut self, count: usize) {
+        self.going_to_last = false;
+        self.select_row(self.selected.saturating_add(count));
+    }
+
+    pub fn up(&mut self, count: usize) {
+        self.going_to_last = false;
+        self.select_row(self.selected.saturating_sub(count));
+    }
+
+    pub fn first(&mut self) {
+        self.going_to_last = false;
+        self.select_row(0);
+    }
+
+    /// Move to the last row. Without the start of its page, it is done once the rows were counted in the background
+    pub fn last(&mut self) {
+        let total = match self.total_rows {
+            Some(total) => total,
+            None => {
+                self.going_to_last = self.count_receiver.is_some();
+                return;
+            }
+        };
+        let last = total.saturating_sub(1);
+        if self.page_start(last / PAGE_ROWS).is_none() && self.count_receiver.is_some() {
+            self.going_to_last = true;
+            return;
+        }
+        self.going_to_last = false;
+        self.select_row(last);
+    }
+
+    /// The rows kept that fit in `height` lines around the cursor, with the position of the cursor in them
+    pub fn visible_rows(&mut self, height: usize) -> (&[Vec<String>], Option<usize>) {
+        let height = height.max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        let start = self.scroll.max(self.first_row) - self.first_row;
+        let end = (self.scroll + height).saturating_sub(self.first_row).min(self.rows.len()).max(start);
+        let selected = self.selected.checked_sub(self.first_row + start).filter(|index| start + index < end);
+        (&self.rows[start..end], selected)
+    }
+
+    pub fn scroll_right(&mut self) {
+        if self.column_offset + 1 < self.column_count() {
+            self.column_offset += 1;
+        }
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.column_offset = self.column_offset.saturating_sub(1);
+    }
+
+    pub fn toggle_summary(&mut self) {
+        self.show_summary = !self.show_summary;
+    }
+
+    /// Type of the values of `column` in the rows kept
+    pub fn column_type(&self, column: usize) -> &'static str {
+        let mut types = Vec::new();
+        for row in self.rows.iter() {
+            let value = match row.get(column) {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+            if value.is_empty() {
+                continue;
+            }
+            let kind = if value.parse::<i64>().is_ok() {
+                "integer"
+            } else if value.parse::<f64>().is_ok() {
+                "float"
+            } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+                "bool"
+            } else if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+                "date"
+            } else {
+                "text"
+            };
+            if !types.contains(&kind) {
+                types.push(kind);
+            }
+        }
+        match types.as_slice() {
+            [] => "empty",
+            [kind] => kind,
+            // Integers and floats mixed in a column are still numbers
+            _ if types.iter().all(|kind| *kind == "integer" || *kind == "float") => "float",
+            _ => "text",
+        }
+    }
+
+    /// Number of non-empty values of `column` in the rows kept
+    pub fn column_filled(&self, column: usize) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| row.get(column).is_some_and(|value| !value.trim().is_empty()))
+            .count()
+    }
+
+    /// If the rows kept are all the rows of the table
+    pub fn fully_loaded(&self) -> bool {
+        self.first_row == 0 && self.total_rows == Some(self.rows.len())
+    }
+}
+
+/// Read the page of a delimited file starting at `position`, with the start of the next page
+fn read_csv_page(reader: &mut Reader<File>, position: Position) -> csv::Result<(Vec<Vec<String>>, PageStart)> {
+    reader.seek(position)?;
+    let mut rows = Vec::new();
+    let mut record = ByteRecord::new();
+    while rows.len() < PAGE_ROWS && reader.read_byte_record(&mut record)? {
+        rows.push(record_strings(&record));
+    }
+    Ok((rows, PageStart::Csv(reader.position().clone())))
+}
+
+/// Read the page of a database table after the row with the rowid `after`, with the start of the next page.
+/// The rowid is the key of the table so the rows before the page are not read
+fn read_sqlite_page(
+    connection: &Connection,
+    table: &str,
+    key: &str,
+    after: Option<i64>,
+) -> rusqlite::Result<(Vec<Vec<String>>, PageStart)> {
+    let mut statement = match after {
+        Some(_) => connection.prepare_cached(&format!(
+            "SELECT {key}, * FROM {} WHERE {key} > ?2 ORDER BY {key} LIMIT ?1",
+            quote(table)
+        ))?,
+        None => connection.prepare_cached(&format!("SELECT {key}, * FROM {} ORDER BY {key} LIMIT ?1", quote(table)))?,
+    };
+    let columns = statement.column_count();
+    let mut rows = Vec::new();
+    let mut last = after;
+    let mut query = match after {
+        Some(after) => statement.query(rusqlite::params![PAGE_ROWS as i64, after])?,
+        None => statement.query([PAGE_ROWS as i64])?,
+    };
+    while let Some(row) = query.next()? {
+        last = Some(row.get(0)?);
+        rows.push((1..columns).map(|column| row.get_ref(column).map(value_string)).collect::<rusqlite::Result<_>>()?);
+    }
+    Ok((rows, PageStart::Rowid(last)))
+}
+
+/// Read `count` rows of a database table without rowid starting at row `offset`
+fn read_sqlite_rows(connection: &Connection, table: &str, offset: usize, count: usize) -> rusqlite::Result<Vec<Vec<String>>> {
+    let mut statement = connection.prepare_cached(&format!("SELECT * FROM {} LIMIT ?1 OFFSET ?2", quote(table)))?;
+    let columns = statement.column_count();
+    let rows = statement.query_map([count as i64, offset as i64], |row| {
+        (0..columns).map(|column| row.get_ref(column).map(value_string)).collect()
+    })?;
+    rows.collect()
+}
+
+/// Count the rows of a database table, with the start of every page when it is paged by rowid
+fn count_sqlite_rows(
+    connection: &Connection,
+    table: &str,
+    key: Option<&str>,
+) -> rusqlite::Result<(usize, Vec<PageStart>)> {
+    let key = match key {
+        Some(key) => key,
+        None => {
+            let count = connection.query_row(&format!("SELECT COUNT(*) FROM {}", quote(table)), [], |row| {
+                row.get::<_, i64>(0)
+            })?;
+            return Ok((count as usize, Vec::new()));
+        }
+    };
+    let mut statement = connection.prepare(&format!("SELECT {key} FROM {} ORDER BY {key}", quote(table)))?;
+    let mut rows = statement.query([])?;
+    let mut starts = vec![PageStart::Rowid(None)];
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        count += 1;
+        if count % PAGE_ROWS == 0 {
+            starts.push(PageStart::Rowid(Some(row.get(0)?)));
+        }
+    }
+    Ok((count, starts))
+}
+
+fn builder(delimiter: u8) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder.delimiter(delimiter).flexible(true).has_headers(true);
+    builder
+}
+
+fn record_strings(record: &ByteRecord) -> Vec<String> {
+    record
+        .iter()
+        .map(|field| String::from_utf8_lossy(field).to_string())
+        .collect()
+}
+
+/// Pick the delimiter that splits the first lines of `sample` in the same, highest number of fields
+pub fn detect_delimiter(sample: &[u8]) -> u8 {
+    // The last line of the sample may be cut in half
+    let sample = match sample.iter().rposition(|byte| *byte == b'\n') {
+        Some(end) => &sample[..end + 1],
+        None => sample,
+    };
+    let mut best = (b',', 1);
+    for delimiter in DELIMITERS {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .has_headers(false)
+            .from_reader(sample);
+        let mut counts = Vec::new();
+        for record in reader.byte_records().take(20) {
+            match record {
+                Ok(record) => counts.push(record.len()),
+                Err(_) => break,
+            }
+        }
+        let consistent = match counts.first() {
+            Some(first) => counts.iter().all(|count| count == first),
+            None => false,
+        };
+        if consistent && counts[0] > best.1 {
+            best = (delimiter, counts[0]);
+        }
+    }
+    best.0
+}
@@ -0,0 +1,420 @@
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::ListState;
+use std::fmt;
+
+/// Formats the preview can show as a tree, with the file extensions they are detected by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl TreeFormat {
+    pub fn from_extension(extension: &str) -> Option<TreeFormat> {
+        match extension.to_lowercase().as_str() {
+            "json" => Some(TreeFormat::Json),
+            "toml" => Some(TreeFormat::Toml),
+            "yaml" | "yml" => Some(TreeFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Error found while parsing a structured file, lines and columns start at 1
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Null,
+    Bool,
+    Number,
+    String,
+    Date,
+    Array,
+    Object,
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub segment: Option<PathSegment>, // the key or index of the node in its parent, None for the root
+    pub kind: NodeKind,
+    pub value: String, // the text of scalar values
+    pub children: Vec<usize>,
+    pub parent: Option<usize>,
+    pub depth: usize,
+    pub expanded: bool,
+}
+
+/// A parsed structured file shown as a collapsible tree
+#[derive(Debug)]
+pub struct TreeView {
+    pub nodes: Vec<TreeNode>, // every node of the tree, the root is the first one
+    pub visible: Vec<usize>, // the nodes whose ancestors are all expanded, in display order
+    pub list_state: ListState,
+}
+
+/// Depth of the nodes expanded when the tree is opened
+const INITIAL_DEPTH: usize = 1;
+
+impl TreeView {
+    /// Parse `source` as `format` into a tree
+    pub fn parse(source: &str, format: TreeFormat) -> Result<TreeView, ParseError> {
+        let mut tree = TreeView {
+            nodes: Vec::new(),
+            visible: Vec::new(),
+            list_state: ListState::default(),
+        };
+        match format {
+            TreeFormat::Json => match serde_json::from_str::<serde_json::Value>(source) {
+                Ok(value) => tree.add_json(&value, None, None),
+                Err(e) => {
+                    return Err(ParseError {
+                        message: strip_location(&e.to_string()),
+                        line: e.line(),
+                        column: e.column(),
+                    })
+                }
+            },
+            TreeFormat::Toml => match source.parse::<toml::Table>() {
+                Ok(table) => tree.add_toml(&toml::Value::Table(table), None, None),
+                Err(e) => {
+                    let offset = e.span().map(|span| span.start).unwrap_or(0);
+                    let (line, column) = line_column(source, offset);
+                    return Err(ParseError {
+                        message: e.message().trim().replace('\n', ", "),
+                        line,
+                        column,
+                    });
+                }
+            },
+            TreeFormat::Yaml => match serde_yaml::from_str::<serde_yaml::Value>(source) {
+                Ok(value) => tree.add_yaml(&value, None, None),
+                Err(e) => {
+                    let (line, column) = match e.location() {
+                        Some(location) => (location.line(), location.column()),
+                        None => (1, 1),
+                    };
+                    return Err(ParseError {
+                        message: strip_location(&e.to_string()),
+                        line,
+                        column,
+                    });
+                }
+            },
+        }
+        tree.update_visible();
+        tree.list_state.select_first();
+        Ok(tree)
+    }
+
+    fn add_node(&mut self, segment: Option<PathSegment>, parent: Option<usize>, kind: NodeKind, value: String) -> usize {
+        let depth = match parent {
+            Some(parent) => self.nodes[parent].depth + 1,
+            None => 0,
+        };
+        let index = self.nodes.len();
+        self.nodes.push(TreeNode {
+            segment,
+            kind,
+            value,
+            children: Vec::new(),
+            parent,
+            depth,
+            expanded: depth < INITIAL_DEPTH,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        index
+    }
+
+    fn add_json(&mut self, value: &serde_json::Value, segment: Option<PathSegment>, parent: Option<usize>) {
+        match value {
+            serde_json::Value::Null => {
+                self.add_node(segment, parent, NodeKind::Null, "null".to_string());
+            }
+            serde_json::Value::Bool(b) => {
+                self.add_node(segment, parent, NodeKind::Bool, b.to_string());
+            }
+            serde_json::Value::Number(n) => {
+                self.add_node(segment, parent, NodeKind::Number, n.to_string());
+            }
+            serde_json::Value::String(s) => {
+                self.add_node(segment, parent, NodeKind::String, s.clone());
+            }
+            serde_json::Value::Array(items) => {
+                let index = self.add_node(segment, parent, NodeKind::Array, String::new());
+                for (i, item) in items.iter().enumerate() {
+                    self.add_json(item, Some(PathSegment::Index(i)), Some(index));
+                }
+            }
+            serde_json::Value::Object(map) => {
+                let index = self.add_node(segment, parent, NodeKind::Object, String::new());
+                for (key, item) in map.iter() {
+                    self.add_json(item, Some(PathSegment::Key(key.clone())), Some(index));
+                }
+            }
+        }
+    }
+
+    fn add_toml(&mut self, value: &toml::Value, segment: Option<PathSegment>, parent: Option<usize>) {
+        match value {
+            toml::Value::String(s) => {
+                self.add_node(segment, parent, NodeKind::String, s.clone());
+            }
+            toml::Value::Integer(n) => {
+                self.add_node(segment, parent, NodeKind::Number, n.to_string());
+            }
+            toml::Value::Float(n) => {
+                self.add_node(segment, parent, NodeKind::Number, n.to_string());
+            }
+            toml::Value::Boolean(b) => {
+                self.add_node(segment, parent, NodeKind::Bool, b.to_string());
+            }
+            toml::Value::Datetime(date) => {
+                self.add_node(segment, parent, NodeKind::Date, date.to_string());
+            }
+            toml::Value::Array(items) => {
+                let index = self.add_node(segment, parent, NodeKind::Array, String::new());
+                for (i, item) in items.iter().enumerate() {
+                    self.add_toml(item, Some(PathSegment::Index(i)), Some(index));
+                }
+            }
+            toml::Value::Table(table) => {
+                let index = self.add_node(segment, parent, NodeKind::Object, String::new());
+                for (key, item) in table.iter() {
+                    self.add_toml(item, Some(PathSegment::Key(key.clone())), Some(index));
+                }
+            }
+        }
+    }
+
+    fn add_yaml(&mut self, value: &serde_yaml::Value, segment: Option<PathSegment>, parent: Option<usize>) {
+        match value {
+            serde_yaml::Value::Null => {
+                self.add_node(segment, parent, NodeKind::Null, "null".to_string());
+            }
+            serde_yaml::Value::Bool(b) => {
+                self.add_node(segment, parent, NodeKind::Bool, b.to_string());
+            }
+            serde_yaml::Value::Number(n) => {
+                self.add_node(segment, parent, NodeKind::Number, n.to_string());
+            }
+            serde_yaml::Value::String(s) => {
+                self.add_node(segment, parent, NodeKind::String, s.clone());
+            }
+            serde_yaml::Value::Sequence(items) => {
+                let index = self.add_node(segment, parent, NodeKind::Array, String::new());
+                for (i, item) in items.iter().enumerate() {
+                    self.add_yaml(item, Some(PathSegment::Index(i)), Some(index));
+                }
+            }
+            serde_yaml::Value::Mapping(map) => {
+                let index = self.add_node(segment, parent, NodeKind::Object, String::new());
+                for (key, item) in map.iter() {
+                    let key = match key {
+                        serde_yaml::Value::String(s) => s.clone(),
+                        other => serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string(),
+                    };
+                    self.add_yaml(item, Some(PathSegment::Key(key)), Some(index));
+                }
+            }
+            serde_yaml::Value::Tagged(tagged) => {
+                self.add_yaml(&tagged.value, segment, parent);
+            }
+        }
+    }
+
+    /// Rebuild the list of visible nodes after a node was expanded or collapsed
+    fn update_visible(&mut self) {
+        self.visible.clear();
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            if index >= self.nodes.len() {
+                continue;
+            }
+            self.visible.push(index);
+            if self.nodes[index].expanded {
+                stack.extend(self.nodes[index].children.iter().rev());
+            }
+        }
+    }
+
+    /// Index in `nodes` of the node under the cursor
+    pub fn selected_node(&self) -> Option<usize> {
+        match self.list_state.selected() {
+            Some(selected) => self.visible.get(selected.min(self.visible.len().saturating_sub(1))).copied(),
+            None => None,
+        }
+    }
+
+    fn select_node(&mut self, node: usize) {
+        if let Some(position) = self.visible.iter().position(|visible| *visible == node) {
+            self.list_state.select(Some(position));
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        if let Some(node) = self.selected_node() {
+            if !self.nodes[node].children.is_empty() {
+                self.nodes[node].expanded = !self.nodes[node].expanded;
+                self.update_visible();
+                self.select_node(node);
+            }
+        }
+    }
+
+    /// Expand the selected node, or move to its first child when it is already expanded
+    pub fn expand(&mut self) {
+        let node = match self.selected_node() {
+            Some(node) => node,
+            None => return,
+        };
+        if self.nodes[node].children.is_empty() {
+            return;
+        }
+        if self.nodes[node].expanded {
+            let child = self.nodes[node].children[0];
+            self.select_node(child);
+        } else {
+            self.nodes[node].expanded = true;
+            self.update_visible();
+            self.select_node(node);
+        }
+    }
+
+    /// Collapse the selected node, or move to its parent when it is already collapsed
+    pub fn collapse(&mut self) {
+        let node = match self.selected_node() {
+            Some(node) => node,
+            None => return,
+        };
+        if self.nodes[node].expanded && !self.nodes[node].children.is_empty() {
+            self.nodes[node].expanded = false;
+            self.update_visible();
+            self.select_node(node);
+        } else if let Some(parent) = self.nodes[node].parent {
+            self.select_node(parent);
+        }
+    }
+
+    pub fn down(&mut self, count: usize) {
+        let selected = self.list_state.selected().unwrap_or(0);
+        let last = self.visible.len().saturating_sub(1);
+        self.list_state.select(Some(selected.saturating_add(count).min(last)));
+    }
+
+    pub fn up(&mut self, count: usize) {
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state.select(Some(selected.saturating_sub(count)));
+    }
+
+    pub fn first(&mut self) {
+        self.list_state.select_first();
+    }
+
+    pub fn last(&mut self) {
+        self.list_state.select(Some(self.visible.len().saturating_sub(1)));
+    }
+
+    /// Path from the root to the selected node, like `dependencies.ratatui.features[1]`
+    pub fn breadcrumb(&self) -> String {
+        let mut segments = Vec::new();
+        let mut current = self.selected_node();
+        while let Some(node) = current {
+            if let Some(segment) = self.nodes[node].segment.clone() {
+                segments.push(segment);
+            }
+            current = self.nodes[node].parent;
+        }
+        let mut path = String::new();
+        for segment in segments.iter().rev() {
+            match segment {
+                PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+                PathSegment::Key(key) => {
+                    let plain = !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+                    if !plain {
+                        path.push_str(&format!("[{:?}]", key));
+                    } else if path.is_empty() {
+                        path.push_str(key);
+                    } else {
+                        path.push_str(&format!(".{}", key));
+                    }
+                }
+            }
+        }
+        path
+    }
+
+    /// Line drawn for `node`, with its key and its value colored by type
+    pub fn node_line(&self, node: usize) -> Line<'static> {
+        let node = &self.nodes[node];
+        let mut spans = vec![Span::raw("  ".repeat(node.depth))];
+        let marker = match (node.children.is_empty(), node.expanded) {
+            (true, _) => "  ",
+            (false, true) => "▾ ",
+            (false, false) => "▸ ",
+        };
+        spans.push(Span::styled(marker, Style::default().fg(Color::DarkGray)));
+        match &node.segment {
+            Some(PathSegment::Key(key)) => {
+                spans.push(Span::styled(key.clone(), Style::default().fg(Color::Cyan)));
+                spans.push(Span::raw(": "));
+            }
+            Some(PathSegment::Index(index)) => {
+                spans.push(Span::styled(format!("[{}]", index), Style::default().fg(Color::DarkGray)));
+                spans.push(Span::raw(": "));
+            }
+            None => {}
+        }
+        let value = match node.kind {
+            NodeKind::Null => Span::styled(node.value.clone(), Style::default().fg(Color::DarkGray)),
+            NodeKind::Bool => Span::styled(node.value.clone(), Style::default().fg(Color::Magenta)),
+            NodeKind::Number => Span::styled(node.value.clone(), Style::default().fg(Color::Yellow)),
+            NodeKind::String => Span::styled(format!("{:?}", node.value), Style::default().fg(Color::Green)),
+            NodeKind::Date => Span::styled(node.value.clone(), Style::default().fg(Color::Blue)),
+            NodeKind::Array => Span::styled(format!("[{}]", node.children.len()), Style::default().fg(Color::DarkGray)),
+            NodeKind::Object => Span::styled(format!("{{{}}}", node.children.len()), Style::default().fg(Color::DarkGray)),
+        };
+        spans.push(value);
+        Line::from(spans)
+    }
+}
+
+/// Line and column, starting at 1, of the byte `offset` of `source`
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(newline) => before[newline + 1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+    (line, column)
+}
+
+/// Remove the "at line X column Y" suffix of parser errors, the location is shown separately
+fn strip_location(message: &str) -> String {
+    match message.rsplit_once(" at line ") {
+        Some((message, _)) => message.to_string(),
+        None => message.to_string(),
+    }
+}
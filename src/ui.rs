@@ -1,19 +1,42 @@
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style}, text::{Span, Text}, widgets::{
-        Block, Borders, Clear, LineGauge, List, ListDirection, ListItem, Paragraph, Scrollbar, ScrollbarOrientation,Wrap
+    layout::{Constraint, Direction, Layout, Rect}, style::{Color, Modifier, Style}, text::{Line, Span, Text}, widgets::{
+        Block, Borders, Clear, LineGauge, List, ListDirection, ListItem, ListState, Paragraph, Row, Scrollbar, ScrollbarOrientation, Table, TableState, Wrap
     }, Frame
 };
 
+use crate::action::Context;
 use crate::app::{App, CurrentScreen};
+use crate::archive::CREATE_FORMATS;
+use crate::diff::{DiffContent, DiffView, RowKind};
+use crate::encoding::{encoding_name, PICKER_ENCODINGS};
+use crate::files::format_size;
+use crate::keymap;
+use crate::opener::OpenMode;
+use crate::shell::{CommandState, Stream};
+use crate::preview::{char_width, column_range, display_line, wrap_ranges};
+use crate::table::MAX_COLUMN_WIDTH;
 
 pub fn ui(frame: &mut Frame, app: &mut App) {
+    // The palette is drawn over the screen it was opened from
+    if let CurrentScreen::Palette = app.current_screen {
+        if let Some(screen) = app.palette.as_ref().map(|palette| palette.screen.clone()) {
+            app.current_screen = screen;
+            ui(frame, app);
+            app.current_screen = CurrentScreen::Palette;
+        }
+        render_palette(frame, app);
+        return;
+    }
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Percentage(10), Constraint::Percentage(90)])
         .split(frame.area());
+    // The previewer draws for the inside of the borders of the preview, known before the preview is shown
+    let (_, preview_area) = split_body(chunks[1]);
+    app.previewer_size = (preview_area.width.saturating_sub(2), preview_area.height.saturating_sub(2));
 
     match app.current_screen {
-        CurrentScreen::Main | CurrentScreen::Preview => {
+        CurrentScreen::Main | CurrentScreen::Preview | CurrentScreen::GoToLine | CurrentScreen::PreviewSearch | CurrentScreen::EncodingPicker | CurrentScreen::Compress | CurrentScreen::ExtractTarget | CurrentScreen::OpenWith | CurrentScreen::CommandPrompt | CurrentScreen::CommandOutput => {
             let chunk_top = Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
@@ -23,9 +46,12 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
                 .borders(Borders::ALL)
                 .style(Style::default());
             
-            let mut title_str=app.current_dir.to_string_lossy().to_string();
+            let mut title_str=app.location().to_string_lossy().to_string();
             if !app.search_input.is_empty(){
-                title_str=format!("{}\nSearch:{}",app.current_dir.to_string_lossy(),app.search_input);
+                title_str=format!("{}\nSearch:{}",app.location().to_string_lossy(),app.search_input);
+            }
+            if let Some((path, _)) = &app.archive_loader {
+                title_str=format!("{}\nReading {}...",title_str,path.to_string_lossy());
             }
 
             let title = Paragraph::new(Text::styled(
@@ -67,10 +93,37 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
     }
 
     match app.current_screen {
-        CurrentScreen::Main | CurrentScreen::Search=> {
-            let list_block = Block::default()
+        CurrentScreen::Main | CurrentScreen::Search | CurrentScreen::Compress | CurrentScreen::ExtractTarget | CurrentScreen::OpenWith | CurrentScreen::CommandPrompt | CurrentScreen::CommandOutput => {
+            let mut list_area = chunks[1];
+            if app.show_preview_pane {
+                let (list, pane) = split_body(chunks[1]);
+                list_area = list;
+                render_preview_pane(frame, app, pane);
+            }
+            let mut list_block = Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default());
+            let mut jobs: Vec<String> = app
+                .jobs
+                .iter()
+                .map(|job| match job.total {
+                    // Jobs like an opener running in the background do not report how far they got
+                    0 => job.title.clone(),
+                    _ => format!("{} {}%", job.title, job.percent()),
+                })
+                .collect();
+            if let Some(command) = app.shell_command.as_ref().filter(|command| command.is_running()) {
+                jobs.push(format!("!{} running, 'O' shows it", command.line));
+            }
+            if !app.background_errors.is_empty() {
+                jobs.push(format!("{} failed", app.background_errors.len()));
+            }
+            if !jobs.is_empty() {
+                list_block = list_block.title_bottom(Line::styled(
+                    format!(" {} ", jobs.join(" | ")),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
             let mut list_items = Vec::<ListItem>::new();
 
             app.files.iter().for_each(|file| {
@@ -78,8 +131,13 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
                 if file.is_dir {
                     style = Style::default().fg(Color::Cyan);
                 }
+                let mut marker = "";
+                if app.marked.contains(&file.full_path) {
+                    style = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+                    marker = "+";
+                }
                 let widget_item = ListItem::new(Span::styled(
-                    format!("[{}] {}", file.extension.to_uppercase(), file.name,),
+                    format!("{}[{}] {}", marker, file.extension.to_uppercase(), file.name,),
                     style,
                 ));
                 list_items.push(widget_item);
@@ -90,13 +148,10 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
                 .repeat_highlight_symbol(true)
                 .direction(ListDirection::TopToBottom)
                 .block(list_block);
-            frame.render_stateful_widget(list, chunks[1], &mut app.list_state);
+            frame.render_stateful_widget(list, list_area, &mut app.list_state);
         }
-        CurrentScreen::Preview => {
-            let chunk_main = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-                .split(chunks[1]);
+        CurrentScreen::Preview | CurrentScreen::GoToLine | CurrentScreen::PreviewSearch | CurrentScreen::EncodingPicker => {
+            let (list_area, preview_area) = split_body(chunks[1]);
             let list_block = Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default());
@@ -118,26 +173,23 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
                 .repeat_highlight_symbol(true)
                 .direction(ListDirection::TopToBottom)
                 .block(list_block);
-            frame.render_stateful_widget(list, chunk_main[0], &mut app.list_state);
+            frame.render_stateful_widget(list, list_area, &mut app.list_state);
 
-            let preview_block = Block::default()
-                .borders(Borders::ALL)
-                .style(Style::default());
-            let text = Paragraph::new(Text::from(app.preview_string.clone()))
-                .block(preview_block)
-                .scroll((app.vertical_scroll as u16, app.horizontal_scroll as u16));
-
-            frame.render_widget(text, chunk_main[1]);
-            frame.render_stateful_widget(
-                Scrollbar::new(ScrollbarOrientation::VerticalRight),
-                chunk_main[1],
-                &mut app.v_preview_scroll_state,
-            );
-            frame.render_stateful_widget(
-                Scrollbar::new(ScrollbarOrientation::HorizontalBottom),
-                chunk_main[1],
-                &mut app.h_preview_scroll_state,
-            );
+            if app.preview_table.is_some() {
+                render_table(frame, app, preview_area);
+            } else if app.preview_sqlite.is_some() {
+                render_sqlite(frame, app, preview_area);
+            } else if app.tree_active() {
+                render_tree(frame, app, preview_area);
+            } else {
+                render_preview(frame, app, preview_area);
+            }
+        }
+        CurrentScreen::Diff => {
+            render_diff(frame, app, chunks[0], chunks[1]);
+        }
+        CurrentScreen::Edit | CurrentScreen::EditSearch | CurrentScreen::ConfirmEditExit => {
+            render_editor(frame, app, chunks[0], chunks[1]);
         }
         _ => {}
     }
@@ -319,6 +371,240 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
             frame.render_widget(input, chunks_pop_up[1]);
         }
 
+        CurrentScreen::GoToLine => {
+            let area = centered_rect(30, 20, frame.area());
+            frame.render_widget(Clear, area);
+            let input_block = Block::default()
+                .title(format!("Go to line (1-{})", app.preview_line_count()))
+                .borders(Borders::ALL)
+                .style(Style::default());
+            let input = Paragraph::new(Text::styled(app.goto_line_input.clone(), Style::default()))
+                .block(input_block);
+            frame.render_widget(input, area);
+        }
+
+        CurrentScreen::PreviewSearch => {
+            let area = centered_rect(50, 20, frame.area());
+            frame.render_widget(Clear, area);
+            let title = format!(
+                "Search [Tab] ignore case: {} [Ctrl-R] regex: {}",
+                app.preview_search_ignore_case, app.preview_search_regex
+            );
+            let mut text = Text::styled(app.preview_search_input.clone(), Style::default().fg(Color::Yellow));
+            if let Some(error) = app.preview_search_error.clone() {
+                text.extend(Text::styled(error, Style::default().fg(Color::Red)));
+            }
+            let input_block = Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .style(Style::default());
+            let input = Paragraph::new(text)
+                .block(input_block)
+                .wrap(Wrap { trim: false });
+            frame.render_widget(input, area);
+        }
+
+        CurrentScreen::EditSearch => {
+            let area = centered_rect(50, 20, frame.area());
+            frame.render_widget(Clear, area);
+            let input = match &app.editor {
+                Some(editor) => editor.search_input.clone(),
+                None => String::new(),
+            };
+            let input_block = Block::default()
+                .title("Search, ignores case unless it has an uppercase letter")
+                .borders(Borders::ALL)
+                .style(Style::default());
+            let input = Paragraph::new(Text::styled(input, Style::default().fg(Color::Yellow)))
+                .block(input_block)
+                .wrap(Wrap { trim: false });
+            frame.render_widget(input, area);
+        }
+
+        CurrentScreen::ConfirmEditExit => {
+            let area = centered_rect(40, 20, frame.area());
+            frame.render_widget(Clear, area);
+            let name = match &app.editor {
+                Some(editor) => editor.path.to_string_lossy().to_string(),
+                None => String::new(),
+            };
+            let popup_block = Block::default()
+                .title(format!("Unsaved changes in {}", name))
+                .borders(Borders::ALL)
+                .style(Style::default());
+            let text = Text::styled(
+                "Save the changes before leaving? [y/n]\nAny other key goes back to the editor",
+                Style::default(),
+            );
+            let paragraph = Paragraph::new(text).block(popup_block).wrap(Wrap { trim: false });
+            frame.render_widget(paragraph, area);
+        }
+
+        CurrentScreen::EncodingPicker => {
+            let area = centered_rect(40, 50, frame.area());
+            frame.render_widget(Clear, area);
+            let items: Vec<ListItem> = PICKER_ENCODINGS
+                .iter()
+                .map(|encoding| ListItem::new(encoding_name(encoding)))
+                .collect();
+            let list = List::new(items)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+                .highlight_symbol(">>")
+                .block(
+                    Block::default()
+                        .title("Decode preview as")
+                        .borders(Borders::ALL)
+                        .style(Style::default()),
+                );
+            frame.render_stateful_widget(list, area, &mut app.encoding_list_state);
+        }
+
+        CurrentScreen::Compress => {
+            let area = centered_rect(50, 30, frame.area());
+            frame.render_widget(Clear, area);
+            let chunks_pop_up = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Min(0)])
+                .split(area);
+            let input_block = Block::default()
+                .title(format!("Compress {} entries, 'Enter' to create or 'Esc' to cancel", app.compress_sources.len()))
+                .borders(Borders::ALL)
+                .style(Style::default());
+            let input = Paragraph::new(Text::styled(app.compress_name(), Style::default().fg(Color::Yellow)))
+                .block(input_block);
+            frame.render_widget(input, chunks_pop_up[0]);
+            let items: Vec<ListItem> = CREATE_FORMATS
+                .iter()
+                .map(|format| ListItem::new(format.to_string()))
+                .collect();
+            let mut state = ListState::default().with_selected(Some(app.compress_format));
+            let list = List::new(items)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+                .highlight_symbol(">>")
+                .block(
+                    Block::default()
+                        .title("Format, 'Tab' to change")
+                        .borders(Borders::ALL)
+                        .style(Style::default()),
+                );
+            frame.render_stateful_widget(list, chunks_pop_up[1], &mut state);
+        }
+
+        CurrentScreen::ExtractTarget => {
+            let area = centered_rect(50, 20, frame.area());
+            frame.render_widget(Clear, area);
+            let folder = match app.extract_folder() {
+                Some(folder) => folder.to_string_lossy().to_string(),
+                None => String::new(),
+            };
+            let items = vec![
+                ListItem::new(format!("Here, in {}", app.current_dir.to_string_lossy())),
+                ListItem::new(format!("In a new folder {}", folder)),
+            ];
+            let list = List::new(items)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+                .highlight_symbol(">>")
+                .block(
+                    Block::default()
+                        .title("Extract archive")
+                        .borders(Borders::ALL)
+                        .style(Style::default()),
+                );
+            frame.render_stateful_widget(list, area, &mut app.extract_list_state);
+        }
+
+        CurrentScreen::OpenWith => {
+            let area = centered_rect(50, 30, frame.area());
+            frame.render_widget(Clear, area);
+            let items: Vec<ListItem> = app
+                .open_with
+                .iter()
+                .map(|entry| match entry {
+                    Some(index) => {
+                        let opener = &app.openers[*index];
+                        let mode = match opener.mode {
+                            OpenMode::Foreground => "",
+                            OpenMode::Background => " (background)",
+                            OpenMode::Detach => " (detached)",
+                        };
+                        ListItem::new(Line::from(vec![
+                            Span::raw(opener.name.clone()),
+                            Span::styled(mode, Style::default().fg(Color::DarkGray)),
+                        ]))
+                    }
+                    None => ListItem::new(Span::styled("Preview in stfm", Style::default().fg(Color::DarkGray))),
+                })
+                .collect();
+            let name = match &app.selected_file {
+                Some(file) => file.name.clone(),
+                None => String::new(),
+            };
+            let list = List::new(items)
+                .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+                .highlight_symbol(">>")
+                .block(
+                    Block::default()
+                        .title(format!("Open {} with", name))
+                        .borders(Borders::ALL)
+                        .style(Style::default()),
+                );
+            frame.render_stateful_widget(list, area, &mut app.open_with_state);
+        }
+
+        CurrentScreen::CommandPrompt => {
+            let area = centered_rect(60, 20, frame.area());
+            frame.render_widget(Clear, area);
+            let input_block = Block::default()
+                .title("Run a command, %f is the file, %d the directory and %s the selection")
+                .borders(Borders::ALL)
+                .style(Style::default());
+            let input = Paragraph::new(Text::styled(format!("!{}", app.command_input), Style::default().fg(Color::Yellow)))
+                .block(input_block)
+                .wrap(Wrap { trim: false });
+            frame.render_widget(input, area);
+        }
+
+        CurrentScreen::CommandOutput => {
+            let command = match app.shell_command.as_mut() {
+                Some(command) => command,
+                None => return,
+            };
+            let area = centered_rect(80, 80, frame.area());
+            frame.render_widget(Clear, area);
+            command.height = area.height.saturating_sub(2) as usize;
+            let (status, style) = match &command.state {
+                CommandState::Running => (
+                    "running, 'Ctrl-C' to cancel, 'q' to hide".to_string(),
+                    Style::default().fg(Color::Yellow),
+                ),
+                CommandState::Exited(status) if status.success() => {
+                    (format!("{}, 'q' to close", status), Style::default().fg(Color::Green))
+                }
+                CommandState::Exited(status) => (format!("{}, 'q' to close", status), Style::default().fg(Color::Red)),
+                CommandState::Cancelled => ("cancelled, 'q' to close".to_string(), Style::default().fg(Color::Red)),
+                CommandState::Failed(e) => (format!("{}, 'q' to close", e), Style::default().fg(Color::Red)),
+            };
+            let mut lines: Vec<Line> = command
+                .output
+                .iter()
+                .skip(command.scroll)
+                .take(command.height)
+                .map(|(stream, line)| match stream {
+                    Stream::Stdout => Line::raw(line.clone()),
+                    Stream::Stderr => Line::styled(line.clone(), Style::default().fg(Color::Red)),
+                })
+                .collect();
+            if command.truncated && command.scroll + command.height >= command.output.len() {
+                lines.push(Line::styled("(the rest of the output was dropped)", Style::default().fg(Color::DarkGray)));
+            }
+            let block = Block::default()
+                .title(format!(" !{} ", command.line))
+                .title_bottom(Line::styled(format!(" {} ", status), style))
+                .borders(Borders::ALL)
+                .style(Style::default());
+            frame.render_widget(Paragraph::new(lines).block(block), area);
+        }
+
         CurrentScreen::ErrorPopUp => {
             let msg = app.error_message.clone().unwrap_or(String::new());
             frame.render_widget(Clear, frame.area());
@@ -339,16 +625,17 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
         CurrentScreen::Help => {
             frame.render_widget(Clear, frame.area());
             let area = centered_rect(60, 60, frame.area());
-            let title_pop_up = format!("Help");
-            let text = format!(" Welcome and thank you for using STFM! :3\n This is a simple file manager that allows you to navigate through your files and directories\n You can navigate through the files using the arrow keys\n You can open a file or directory by pressing 'Enter' or 'Right Arrow'\n You can go back to the previous directory by pressing 'Backspace' or 'Left' key\n You can see a preview of the file by selecting it\n With preview open you can scroll down by pressing 'Down' and scroll up by pressing 'Up'\n With preview open you can scroll right by pressing 'Right' and scroll left by pressing 'Left'\n With preview open you can go back to the main screen by pressing 'q' or 'Esc'\n You can search for a file or directory by pressing '/' and typing the name of the file\n With search open you can go back to the main screen by pressing 'Esc'\n With search on press enter to show filtered files\n You can create a new file/dir by pressing 'n'\n You can delete a file/dir by pressing 'd'\n You can rename a file/dir by pressing 'r'\n You can toggle hidden files by pressing '.'\n You can scroll down by pressing 'PageDown'\n You can scroll up by pressing 'PageUp'\n You can copy a file by pressing 'c'\n You can paste a file by pressing 'p'\n You can exit the application by pressing 'q' or 'Esc'\n");
+            let lines = help_lines(app);
+            // Keep the last line at the bottom of the popup when scrolling down
+            let max_scroll = lines.len().saturating_sub(area.height.saturating_sub(2) as usize) as u16;
+            app.help_scroll = app.help_scroll.min(max_scroll);
             let popup_block = Block::default()
-                .title(title_pop_up)
+                .title("Help, 'Up'/'Down' to scroll, any other key to close")
                 .borders(Borders::ALL)
                 .style(Style::default());
-            let desc_text = Text::styled(text, Style::default());
-            let desc_paragraph = Paragraph::new(desc_text)
+            let desc_paragraph = Paragraph::new(Text::from(lines))
                 .block(popup_block)
-                .wrap(Wrap { trim: false });
+                .scroll((app.help_scroll, 0));
             frame.render_widget(desc_paragraph, area);
         }
 
@@ -357,6 +644,715 @@ pub fn ui(frame: &mut Frame, app: &mut App) {
 
 }
 
+/// The screens listed in the help, in order, with their heading
+const HELP_CONTEXTS: [(Context, &str); 7] = [
+    (Context::Main, "Files"),
+    (Context::Preview, "Preview"),
+    (Context::Table, "CSV/TSV file or database table"),
+    (Context::Database, "Tables of a SQLite database"),
+    (Context::Tree, "Tree of a JSON, TOML or YAML file"),
+    (Context::Diff, "Diff"),
+    (Context::Edit, "Editor"),
+];
+
+/// The help, built from the keymap: the keys and the description of every action of each screen
+fn help_lines(app: &App) -> Vec<Line<'static>> {
+    let heading_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let key_style = Style::default().fg(Color::Cyan);
+    let mut lines = vec![Line::from(" Welcome and thank you for using STFM! :3")];
+    for (context, heading) in HELP_CONTEXTS {
+        let mut entries: Vec<(String, String)> = keymap::bindings(context)
+            .iter()
+            .map(|(action, keys)| {
+                let keys = keys.iter().map(|key| key.to_string()).collect::<Vec<String>>().join(" ");
+                (keys, action.description().to_string())
+            })
+            .collect();
+        // Custom commands run in the main screen
+        if context == Context::Main {
+            for command in &app.custom_commands {
+                let keys = command.key.map(|key| key.to_string()).unwrap_or_default();
+                entries.push((keys, command.name.clone()));
+            }
+        }
+        let width = entries.iter().map(|(keys, _)| keys.chars().count()).max().unwrap_or(0);
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(format!(" {}", heading), heading_style)));
+        for (keys, description) in entries {
+            lines.push(Line::from(vec![
+                Span::styled(format!("   {:<width$}", keys, width = width), key_style),
+                Span::raw(format!("  {}", description)),
+            ]));
+        }
+    }
+    lines
+}
+
+/// Render the command palette: the input on top, the matching actions with their keys below
+fn render_palette(frame: &mut Frame, app: &mut App) {
+    let palette = match app.palette.as_mut() {
+        Some(palette) => palette,
+        None => return,
+    };
+    let area = centered_rect(60, 60, frame.area());
+    frame.render_widget(Clear, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)])
+        .split(area);
+    let input = Paragraph::new(Text::styled(palette.input.clone(), Style::default().fg(Color::Yellow))).block(
+        Block::default()
+            .title("Command palette, 'Enter' to run or 'Esc' to cancel")
+            .borders(Borders::ALL)
+            .style(Style::default()),
+    );
+    frame.render_widget(input, chunks[0]);
+    frame.set_cursor_position((chunks[0].x + 1 + palette.input.chars().count() as u16, chunks[0].y + 1));
+
+    // The width inside the borders, without the highlight symbol
+    let width = chunks[1].width.saturating_sub(4) as usize;
+    let items: Vec<ListItem> = palette
+        .matches
+        .iter()
+        .map(|(index, positions)| {
+            let entry = &palette.entries[*index];
+            let mut spans: Vec<Span> = entry
+                .title
+                .chars()
+                .enumerate()
+                .map(|(position, c)| match positions.contains(&position) {
+                    true => Span::styled(c.to_string(), Style::default().fg(Color::Yellow)),
+                    false => Span::raw(c.to_string()),
+                })
+                .collect();
+            let used = entry.title.chars().count() + entry.keys.chars().count();
+            spans.push(Span::raw(" ".repeat(width.saturating_sub(used).max(1))));
+            spans.push(Span::styled(entry.keys.clone(), Style::default().fg(Color::DarkGray)));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let title = format!("{} of {} actions", palette.matches.len(), palette.entries.len());
+    let list = List::new(items)
+        .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+        .highlight_symbol(">>")
+        .block(Block::default().title(title).borders(Borders::ALL).style(Style::default()));
+    frame.render_stateful_widget(list, chunks[1], &mut palette.state);
+}
+
+/// The listing and the preview next to it, the preview pane and the preview screen both take the right half
+fn split_body(area: Rect) -> (Rect, Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    (chunks[0], chunks[1])
+}
+
+/// Render the visible lines of the preview with the line number gutter, soft wrap and search matches applied
+fn render_preview(frame: &mut Frame, app: &mut App, area: Rect) {
+    let mut area = area;
+    if let Some(error) = app.preview_tree_error.clone() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(1)])
+            .split(area);
+        let message = Paragraph::new(Text::styled(
+            format!("Parse error at {}", error),
+            Style::default().fg(Color::Red),
+        ));
+        frame.render_widget(message, chunks[0]);
+        area = chunks[1];
+    }
+    let inner_height = area.height.saturating_sub(2) as usize;
+    app.preview_height = inner_height;
+    app.preview_area_width = area.width.saturating_sub(2) as usize;
+
+    let total_lines = app.preview_line_count();
+    let text_width = app.preview_width();
+    let gutter_width = match app.show_line_numbers {
+        true => total_lines.max(1).to_string().len() + 1,
+        false => 0,
+    };
+    let gutter_style = Style::default().fg(Color::DarkGray);
+
+    // Only the matches of the visible lines are needed to highlight them
+    let first_match = app
+        .preview_matches
+        .partition_point(|found| found.line < app.vertical_scroll);
+
+    let mut lines = Vec::<Line>::new();
+    let mut index = app.vertical_scroll;
+    while lines.len() < inner_height && index < total_lines {
+        let chars = app.preview_styled_line(index);
+        let widths: Vec<usize> = chars.iter().map(|(c, _)| char_width(*c)).collect();
+        // Lines are cut by columns, so wide characters never overflow the block
+        let mut padding = 0;
+        let chunks: Vec<(usize, usize)> = if app.soft_wrap {
+            wrap_ranges(&widths, text_width)
+        } else {
+            let (start, end) = column_range(&widths, app.horizontal_scroll, text_width);
+            // A wide character cut by the left edge leaves its column blank
+            padding = widths[..start].iter().sum::<usize>().saturating_sub(app.horizontal_scroll);
+            vec![(start, end)]
+        };
+        let line_matches: Vec<(usize, usize, bool)> = app.preview_matches[first_match..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, found)| found.line <= index)
+            .filter(|(_, found)| found.line == index)
+            .map(|(i, found)| (found.start, found.end, app.preview_match_index == Some(first_match + i)))
+            .collect();
+        for (i, (start, end)) in chunks.into_iter().enumerate() {
+            if lines.len() >= inner_height {
+                break;
+            }
+            let mut spans = Vec::new();
+            if app.show_line_numbers {
+                let number = match i {
+                    0 => (index + 1).to_string(),
+                    _ => String::new(),
+                };
+                spans.push(Span::styled(
+                    format!("{:>width$} ", number, width = gutter_width - 1),
+                    gutter_style,
+                ));
+            }
+            if padding > 0 {
+                spans.push(Span::raw(" ".repeat(padding)));
+            }
+            spans.extend(highlight_matches(&chars, start, end, &line_matches));
+            lines.push(Line::from(spans));
+        }
+        index += 1;
+    }
+
+    let name = match app.selected_file.clone() {
+        Some(file) => file.name,
+        None => String::new(),
+    };
+    let mut title = format!(" {} ", name);
+    if let Some(encoding) = app.preview_encoding {
+        title.push_str(&format!("| {} {} ", encoding_name(encoding), app.preview_line_ending));
+    }
+    title.push_str(&format!("| Ln {}/{} ", (app.vertical_scroll + 1).min(total_lines), total_lines));
+    if app.soft_wrap {
+        title.push_str("| wrap ");
+    }
+    if app.follow.is_some() {
+        title.push_str("| follow ");
+    }
+    if let Some(label) = &app.preview_label {
+        title.push_str(&format!("| {} ", label));
+    }
+    if app.preview_loader.is_some() {
+        title.push_str("| loading ");
+    }
+    if app.preview_text.is_some() && app.preview_label.is_none() {
+        match app.preview_show_raw {
+            true => title.push_str("| raw "),
+            false => title.push_str("| rendered "),
+        }
+    }
+    if !app.preview_search_input.is_empty() {
+        let current = match app.preview_match_index {
+            Some(index) => index + 1,
+            None => 0,
+        };
+        title.push_str(&format!("| {}/{} ", current, app.preview_matches.len()));
+    }
+    let preview_block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .style(Style::default());
+    frame.render_widget(Paragraph::new(Text::from(lines)).block(preview_block), area);
+    frame.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight),
+        area,
+        &mut app.v_preview_scroll_state,
+    );
+    if !app.soft_wrap {
+        frame.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::HorizontalBottom),
+            area,
+            &mut app.h_preview_scroll_state,
+        );
+    }
+}
+
+/// Render the tree of a structured file with the path of the selected node as title
+fn render_tree(frame: &mut Frame, app: &mut App, area: Rect) {
+    let tree = match app.preview_tree.as_mut() {
+        Some(tree) => tree,
+        None => return,
+    };
+    let items: Vec<ListItem> = tree
+        .visible
+        .iter()
+        .map(|node| ListItem::new(tree.node_line(*node)))
+        .collect();
+    let title = format!(" {} ", tree.breadcrumb());
+    let list = List::new(items)
+        .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .style(Style::default()),
+        );
+    frame.render_stateful_widget(list, area, &mut tree.list_state);
+}
+
+/// Render the tables and views of a SQLite database, with the schema of the selected one below the list
+fn render_sqlite(frame: &mut Frame, app: &mut App, area: Rect) {
+    let name = match app.selected_file.clone() {
+        Some(file) => file.name,
+        None => String::new(),
+    };
+    let browser = match app.preview_sqlite.as_mut() {
+        Some(browser) => browser,
+        None => return,
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let name_width = browser.objects.iter().map(|object| object.name.chars().count()).max().unwrap_or(0);
+    let items: Vec<ListItem> = browser
+        .objects
+        .iter()
+        .map(|object| {
+            let rows = match object.rows {
+                Some(rows) => format!("{} rows", rows),
+                None => "counting...".to_string(),
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<6}", object.kind), Style::default().fg(Color::DarkGray)),
+                Span::styled(format!("{:<width$}  ", object.name, width = name_width), Style::default().fg(Color::Green)),
+                Span::raw(format!("{:>12}  ", rows)),
+                Span::styled(format!("{} columns", object.columns.len()), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let title = format!(
+        " {} | SQLite (read-only) | {} tables | {} views ",
+        name,
+        browser.table_count(),
+        browser.view_count()
+    );
+    let list = List::new(items)
+        .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+        .block(Block::default().title(title).borders(Borders::ALL));
+    frame.render_stateful_widget(list, chunks[0], &mut browser.state);
+
+    let mut lines = Vec::new();
+    let mut schema_title = " Schema ".to_string();
+    match browser.selected() {
+        Some(object) => {
+            schema_title = format!(" Schema of {} ", object.name);
+            for (column, kind) in object.columns.iter() {
+                lines.push(Line::from(vec![
+                    Span::styled(format!("{} ", column), Style::default().fg(Color::Yellow)),
+                    Span::styled(kind.clone(), Style::default().fg(Color::DarkGray)),
+                ]));
+            }
+            lines.push(Line::default());
+            for line in object.sql.lines() {
+                lines.push(Line::styled(line.to_string(), Style::default().fg(Color::Cyan)));
+            }
+        }
+        None => lines.push(Line::styled("No tables or views", Style::default().fg(Color::DarkGray))),
+    }
+    let schema = Paragraph::new(Text::from(lines))
+        .wrap(Wrap { trim: false })
+        .block(Block::default().title(schema_title).borders(Borders::ALL));
+    frame.render_widget(schema, chunks[1]);
+}
+
+/// Render a delimited file as a table with a frozen header, or the summary of its columns
+fn render_table(frame: &mut Frame, app: &mut App, area: Rect) {
+    let name = match app.selected_file.clone() {
+        Some(file) => file.name,
+        None => String::new(),
+    };
+    let table = match app.preview_table.as_mut() {
+        Some(table) => table,
+        None => return,
+    };
+    let rows = match table.total_rows {
+        Some(count) => count.to_string(),
+        None => format!("{}+ (counting)", table.read_rows),
+    };
+    let title = format!(
+        " {} | {} rows | {} columns | {} ",
+        name,
+        rows,
+        table.column_count(),
+        table.description
+    );
+    let mut block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .style(Style::default());
+    if let Some(error) = &table.error {
+        block = block.title_bottom(Line::styled(format!(" {} ", error), Style::default().fg(Color::Red)));
+    } else if table.going_to_last {
+        block = block.title_bottom(Line::styled(
+            " Going to the last row once the rows are counted ",
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    let header_style = Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+
+    if table.show_summary {
+        let sampled = match table.fully_loaded() {
+            true => "all rows".to_string(),
+            false => format!("rows {}-{}", table.first_row + 1, table.first_row + table.rows.len()),
+        };
+        let summary_rows: Vec<Row> = (0..table.column_count())
+            .map(|column| {
+                Row::new(vec![
+                    table.headers.get(column).cloned().unwrap_or_default(),
+                    table.column_type(column).to_string(),
+                    table.column_filled(column).to_string(),
+                ])
+            })
+            .collect();
+        let summary = Table::new(
+            summary_rows,
+            [Constraint::Percentage(50), Constraint::Percentage(25), Constraint::Percentage(25)],
+        )
+        .header(Row::new(vec!["Column".to_string(), "Type".to_string(), format!("Filled ({})", sampled)]).style(header_style))
+        .block(block);
+        frame.render_widget(summary, area);
+        return;
+    }
+
+    // Only the columns from the scroll offset that fit in the block are drawn
+    let mut columns = Vec::new();
+    let mut used = 0;
+    for column in table.column_offset..table.column_count() {
+        let width = table.widths[column].max(1);
+        if used > 0 && used + width + 1 > area.width.saturating_sub(2) as usize {
+            break;
+        }
+        used += width + 1;
+        columns.push(column);
+    }
+    let cells = |values: &Vec<String>| -> Vec<String> {
+        columns
+            .iter()
+            .map(|column| values.get(*column).cloned().unwrap_or_default().chars().take(MAX_COLUMN_WIDTH).collect())
+            .collect()
+    };
+    let header = Row::new(cells(&table.headers)).style(header_style);
+    // Only the rows that fit are built, under the borders and the header
+    let (visible, selected) = table.visible_rows(area.height.saturating_sub(3) as usize);
+    let rows: Vec<Row> = visible.iter().map(|row| Row::new(cells(row))).collect();
+    let widths: Vec<Constraint> = columns
+        .iter()
+        .map(|column| Constraint::Length(table.widths[*column].max(1) as u16))
+        .collect();
+    let widget = Table::new(rows, widths)
+        .header(header)
+        .column_spacing(1)
+        .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+        .block(block);
+    frame.render_stateful_widget(widget, area, &mut TableState::default().with_selected(selected));
+}
+
+/// Render the diff screen: the compared files and the change counts on top, the visible rows of the diff below
+fn render_diff(frame: &mut Frame, app: &mut App, top: Rect, area: Rect) {
+    let diff = match app.diff.as_mut() {
+        Some(diff) => diff,
+        None => return,
+    };
+    let name = |path: &std::path::Path| match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => path.to_string_lossy().to_string(),
+    };
+    let (old_name, new_name) = (name(&diff.old_path), name(&diff.new_path));
+    let layout = match diff.side_by_side {
+        true => "side by side",
+        false => "unified",
+    };
+    let mut summary = vec![
+        Span::raw(" Diff | "),
+        Span::styled(old_name.clone(), Style::default().fg(Color::Red)),
+        Span::raw(" → "),
+        Span::styled(new_name.clone(), Style::default().fg(Color::Green)),
+    ];
+    match &diff.content {
+        Some(Ok(DiffContent::Lines { added, removed, .. })) => {
+            summary.push(Span::styled(format!(" | +{}", added), Style::default().fg(Color::Green)));
+            summary.push(Span::styled(format!(" -{}", removed), Style::default().fg(Color::Red)));
+            summary.push(Span::raw(format!(" | hunk {}/{} | {}", diff.current_hunk(), diff.hunks().len(), layout)));
+        }
+        Some(Ok(DiffContent::Bytes { ranges, truncated, .. })) => {
+            let more = match truncated {
+                true => "+",
+                false => "",
+            };
+            summary.push(Span::raw(format!(" | {}{} differing byte ranges", ranges.len(), more)));
+        }
+        Some(Err(_)) => {}
+        None => summary.push(Span::styled(" | comparing...", Style::default().fg(Color::DarkGray))),
+    }
+    summary.push(Span::raw(" "));
+    frame.render_widget(Block::default().title(Line::from(summary)).borders(Borders::ALL), top);
+
+    diff.height = area.height.saturating_sub(2) as usize;
+    let rows = match &diff.content {
+        None => {
+            let message = Paragraph::new(Text::styled("Comparing...", Style::default().fg(Color::DarkGray)));
+            frame.render_widget(message.block(Block::default().borders(Borders::ALL)), area);
+            return;
+        }
+        Some(Err(e)) => {
+            let message = Paragraph::new(Text::styled(e.clone(), Style::default().fg(Color::Red)));
+            frame.render_widget(message.block(Block::default().borders(Borders::ALL)), area);
+            return;
+        }
+        Some(Ok(DiffContent::Bytes {
+            old_size,
+            new_size,
+            ranges,
+            truncated,
+            reason,
+        })) => {
+            let mut block = Block::default()
+                .title(format!(" {} | {} {} | {} {} ", reason, old_name, format_size(*old_size), new_name, format_size(*new_size)))
+                .borders(Borders::ALL);
+            if *truncated {
+                block = block.title_bottom(format!(" Stopped after {} ranges ", ranges.len()));
+            }
+            let lines: Vec<Line> = match ranges.is_empty() {
+                true => vec![Line::styled("The files are identical", Style::default().fg(Color::DarkGray))],
+                false => ranges
+                    .iter()
+                    .skip(diff.scroll)
+                    .take(diff.height)
+                    .map(|(start, end)| {
+                        Line::from(vec![
+                            Span::styled(format!("{:#010x}..{:#010x}", start, end), Style::default().fg(Color::Yellow)),
+                            Span::raw(match end - start {
+                                1 => "  1 byte".to_string(),
+                                length => format!("  {} bytes", length),
+                            }),
+                        ])
+                    })
+                    .collect(),
+            };
+            frame.render_widget(Paragraph::new(lines).block(block), area);
+            return;
+        }
+        Some(Ok(DiffContent::Lines { .. })) => diff.rows(),
+    };
+    if rows.is_empty() {
+        let message = Paragraph::new(Text::styled("The files are identical", Style::default().fg(Color::DarkGray)));
+        frame.render_widget(message.block(Block::default().borders(Borders::ALL)), area);
+        return;
+    }
+
+    let visible: Vec<_> = rows.iter().skip(diff.scroll).take(diff.height).copied().collect();
+    let last_line = visible.iter().filter_map(|row| row.old.max(row.new)).max().unwrap_or(0);
+    let number_width = (last_line + 1).to_string().len();
+    let gutter_style = Style::default().fg(Color::DarkGray);
+    // Removed and added lines are red and green, the words that changed inside them are reversed
+    let line_style = |kind: RowKind, new: bool| match (kind, new) {
+        (RowKind::Equal, _) => Style::default(),
+        (RowKind::Added, _) | (RowKind::Changed, true) => Style::default().fg(Color::Green),
+        _ => Style::default().fg(Color::Red),
+    };
+    let text_spans = |diff: &DiffView, row, new: bool, style: Style, width: usize| -> Vec<Span<'static>> {
+        let mut chars = Vec::new();
+        for (changed, text) in diff.segments(row, new).unwrap_or_default() {
+            let style = match changed {
+                true => style.add_modifier(Modifier::REVERSED),
+                false => style,
+            };
+            chars.extend(display_line(&text).chars().map(|c| (c, style)));
+        }
+        let start = diff.horizontal_scroll.min(chars.len());
+        highlight_matches(&chars, start, (start + width).min(chars.len()), &[])
+    };
+
+    if diff.side_by_side {
+        let halves = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(area);
+        for (half, new, title) in [(halves[0], false, &old_name), (halves[1], true, &new_name)] {
+            let width = (half.width.saturating_sub(2) as usize).saturating_sub(number_width + 1);
+            let lines: Vec<Line> = visible
+                .iter()
+                .map(|row| {
+                    let line = match new {
+                        true => row.new,
+                        false => row.old,
+                    };
+                    let number = match line {
+                        Some(index) => (index + 1).to_string(),
+                        None => String::new(),
+                    };
+                    let mut spans = vec![Span::styled(format!("{:>width$} ", number, width = number_width), gutter_style)];
+                    if line.is_some() {
+                        spans.extend(text_spans(diff, row, new, line_style(row.kind, new), width));
+                    }
+                    Line::from(spans)
+                })
+                .collect();
+            let block = Block::default().title(format!(" {} ", title)).borders(Borders::ALL);
+            frame.render_widget(Paragraph::new(lines).block(block), half);
+        }
+    } else {
+        let width = (area.width.saturating_sub(2) as usize).saturating_sub(2 * number_width + 4);
+        let lines: Vec<Line> = visible
+            .iter()
+            .map(|row| {
+                let (old, new, sign) = match row.kind {
+                    RowKind::Removed => (row.old, None, "-"),
+                    RowKind::Added => (None, row.new, "+"),
+                    _ => (row.old, row.new, " "),
+                };
+                let number = |line: Option<usize>| match line {
+                    Some(index) => (index + 1).to_string(),
+                    None => String::new(),
+                };
+                let style = line_style(row.kind, row.kind == RowKind::Added);
+                let mut spans = vec![
+                    Span::styled(
+                        format!("{:>width$} {:>width$} ", number(old), number(new), width = number_width),
+                        gutter_style,
+                    ),
+                    Span::styled(format!("{} ", sign), style),
+                ];
+                spans.extend(text_spans(diff, row, row.kind == RowKind::Added, style, width));
+                Line::from(spans)
+            })
+            .collect();
+        let block = Block::default().title(format!(" {} → {} ", old_name, new_name)).borders(Borders::ALL);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// Render the editor: the file and the cursor position on top, the visible lines with the cursor below
+fn render_editor(frame: &mut Frame, app: &mut App, top: Rect, area: Rect) {
+    let editor = match app.editor.as_mut() {
+        Some(editor) => editor,
+        None => return,
+    };
+    let mut title = vec![
+        Span::raw(" Edit | "),
+        Span::raw(editor.path.to_string_lossy().to_string()),
+    ];
+    if editor.is_dirty() {
+        title.push(Span::styled(" [modified]", Style::default().fg(Color::Yellow)));
+    }
+    title.push(Span::raw(format!(
+        " | Ln {}/{}, Col {} | {} ",
+        editor.row + 1,
+        editor.lines.len(),
+        editor.col + 1,
+        editor.line_ending
+    )));
+    frame.render_widget(Block::default().title(Line::from(title)).borders(Borders::ALL), top);
+
+    editor.height = area.height.saturating_sub(2) as usize;
+    let number_width = editor.lines.len().to_string().len();
+    let text_width = (area.width.saturating_sub(2) as usize).saturating_sub(number_width + 1);
+    editor.scroll_to_cursor(text_width);
+    let gutter_style = Style::default().fg(Color::DarkGray);
+    let lines: Vec<Line> = editor
+        .lines
+        .iter()
+        .enumerate()
+        .skip(editor.scroll_row)
+        .take(editor.height)
+        .map(|(index, line)| {
+            let chars: Vec<char> = display_line(line).chars().collect();
+            let widths: Vec<usize> = chars.iter().map(|c| char_width(*c)).collect();
+            let (start, end) = column_range(&widths, editor.scroll_col, text_width);
+            // A wide character cut by the left edge leaves its column blank
+            let padding = widths[..start].iter().sum::<usize>().saturating_sub(editor.scroll_col);
+            let text = format!("{}{}", " ".repeat(padding), chars[start..end].iter().collect::<String>());
+            Line::from(vec![
+                Span::styled(format!("{:>width$} ", index + 1, width = number_width), gutter_style),
+                Span::raw(text),
+            ])
+        })
+        .collect();
+    let status = match &editor.message {
+        Some(message) => Line::styled(format!(" {} ", message), Style::default().fg(Color::Yellow)),
+        None => Line::styled(
+            " Ctrl-S save | Ctrl-Z undo | Ctrl-F search | Ctrl-N next match | Esc quit ",
+            gutter_style,
+        ),
+    };
+    let block = Block::default().title_bottom(status).borders(Borders::ALL);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+
+    if let CurrentScreen::Edit = app.current_screen {
+        let x = area.x + 1 + (number_width + 1 + editor.cursor_column() - editor.scroll_col) as u16;
+        let y = area.y + 1 + (editor.row - editor.scroll_row) as u16;
+        frame.set_cursor_position((x, y));
+    }
+}
+
+/// Render the preview pane that follows the selected entry in the main screen
+fn render_preview_pane(frame: &mut Frame, app: &App, area: Rect) {
+    let file = match app.selected_file.clone() {
+        Some(file) => file,
+        None => {
+            frame.render_widget(Block::default().borders(Borders::ALL), area);
+            return;
+        }
+    };
+    let mut title = format!(" {} ", file.name);
+    let mut content = Text::default();
+    if let Some(summary) = &app.pane_directory {
+        if summary.path == file.full_path {
+            content = summary.text();
+        }
+    }
+    match &app.pane_content {
+        Some((path, text)) if *path == file.full_path => {
+            content.extend(text.lines.clone());
+        }
+        _ => {
+            title.push_str("| loading ");
+        }
+    }
+    let pane_block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .style(Style::default());
+    frame.render_widget(Paragraph::new(content).block(pane_block), area);
+}
+
+/// Split the columns `start..end` of a line into spans, highlighting the parts covered by `matches`
+fn highlight_matches(chars: &[(char, Style)], start: usize, end: usize, matches: &[(usize, usize, bool)]) -> Vec<Span<'static>> {
+    let match_style = Style::default().bg(Color::Yellow).fg(Color::Black);
+    let current_style = Style::default().bg(Color::LightRed).fg(Color::Black);
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    for (column, (c, style)) in chars.iter().enumerate().take(end).skip(start) {
+        let mut style = *style;
+        for (match_start, match_end, current) in matches.iter() {
+            if (*match_start..*match_end).contains(&column) {
+                style = match current {
+                    true => style.patch(current_style),
+                    false => style.patch(match_style),
+                };
+            }
+        }
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.content.to_mut().push(*c),
+            _ => spans.push(Span::styled(c.to_string(), style)),
+        }
+    }
+    spans
+}
+
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     // Cut the given rectangle into three vertical pieces